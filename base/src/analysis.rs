@@ -0,0 +1,369 @@
+//! Dimensional analysis: finding the products of quantities which have no unit, as in the
+//! Buckingham π theorem, or which have a chosen unit class.
+
+use crate::{
+    data::{Data, MetaData, ValueData},
+    expression::Expression,
+    prelude::*,
+};
+use num_integer::Integer;
+use num_traits::{Signed, Zero};
+use std::collections::BTreeSet;
+
+/// The names of some quantities, each with the power it is raised to.
+pub type Group = Vec<(String, Exponent)>;
+
+/// The most combinations solve_for_units returns.
+const MAX_COMBINATIONS: usize = 5;
+/// How many times each independent dimensionless group is tried in both directions when
+/// searching for simpler combinations.
+const GROUP_SEARCH_RANGE: i64 = 2;
+/// The most combinations solve_for_units compares before giving up. Each independent group
+/// multiplies the number of combinations to compare, so many interchangeable quantities would
+/// otherwise take more memory than there is.
+const MAX_CANDIDATES: usize = 100_000;
+
+/// Reduces the first `columns` columns of the matrix to reduced row echelon form. Returns the
+/// column each nonzero row has its leading 1 in.
+fn row_reduce(matrix: &mut [Vec<Exponent>], columns: usize) -> Vec<usize> {
+    let mut pivots = Vec::new();
+    let mut row = 0;
+    for column in 0..columns {
+        let pivot = match (row..matrix.len()).find(|&r| !matrix[r][column].is_zero()) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        matrix.swap(row, pivot);
+        let scale = matrix[row][column];
+        for value in matrix[row].iter_mut() {
+            *value /= scale;
+        }
+        let pivot_row = matrix[row].clone();
+        for (other, values) in matrix.iter_mut().enumerate() {
+            let factor = values[column];
+            if other != row && !factor.is_zero() {
+                for (value, pivot_value) in values.iter_mut().zip(&pivot_row) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+        pivots.push(column);
+        row += 1;
+    }
+    pivots
+}
+
+/// Returns a basis of the vectors `x` for which `matrix * x = 0`. Every vector has a 1 in the
+/// position of a different free column.
+fn nullspace(mut matrix: Vec<Vec<Exponent>>, columns: usize) -> Vec<Vec<Exponent>> {
+    let pivots = row_reduce(&mut matrix, columns);
+    (0..columns)
+        .filter(|column| !pivots.contains(column))
+        .map(|free| {
+            let mut vector = vec![Exponent::zero(); columns];
+            vector[free] = Exponent::from_integer(1);
+            for (row, &pivot) in pivots.iter().enumerate() {
+                vector[pivot] = -matrix[row][free];
+            }
+            vector
+        })
+        .collect()
+}
+
+/// Scales a vector so that it only has integers with no common factor, starting with a positive
+/// one.
+fn simplest_multiple(vector: Vec<Exponent>) -> Vec<Exponent> {
+    let denominators = vector.iter().fold(1, |lcm, value| lcm.lcm(value.denom()));
+    let integers: Vec<_> = vector
+        .iter()
+        .map(|value| (value * denominators).to_integer())
+        .collect();
+    let divisor = integers.iter().fold(0, |gcd, value| gcd.gcd(value)).max(1);
+    let first = integers.iter().find(|value| !value.is_zero());
+    let sign = if first.is_some_and(|first| first.is_negative()) {
+        -1
+    } else {
+        1
+    };
+    integers
+        .into_iter()
+        .map(|value| Exponent::from_integer(value / divisor * sign))
+        .collect()
+}
+
+/// Writes a group like `rho * v * L / mu`.
+pub fn describe_group(group: &[(String, Exponent)]) -> String {
+    let factor = |(name, power): &(String, Exponent)| {
+        if power.is_integer() && power.abs() == Exponent::from_integer(1) {
+            name.clone()
+        } else if power.abs().is_integer() {
+            format!("{}^{}", name, power.abs())
+        } else {
+            format!("{}^({})", name, power.abs())
+        }
+    };
+    let numerator: Vec<_> = group
+        .iter()
+        .filter(|(_, power)| power.is_positive())
+        .map(factor)
+        .collect();
+    let denominator: Vec<_> = group
+        .iter()
+        .filter(|(_, power)| power.is_negative())
+        .map(factor)
+        .collect();
+    let mut description = if numerator.is_empty() {
+        "1".to_owned()
+    } else {
+        numerator.join(" * ")
+    };
+    match denominator.len() {
+        0 => (),
+        1 => description.push_str(&format!(" / {}", denominator[0])),
+        _ => description.push_str(&format!(" / ({})", denominator.join(" * "))),
+    }
+    description
+}
+
+/// Returns an `x` for which `matrix * x = target`, if there is one.
+fn particular_solution(
+    matrix: &[Vec<Exponent>],
+    target: &[Exponent],
+    columns: usize,
+) -> Option<Vec<Exponent>> {
+    let mut augmented: Vec<_> = matrix
+        .iter()
+        .zip(target)
+        .map(|(row, target)| row.iter().chain(std::iter::once(target)).cloned().collect())
+        .collect();
+    let pivots = row_reduce(&mut augmented, columns);
+    // A row with no pivot left over must have nothing left to reach either.
+    if augmented[pivots.len()..]
+        .iter()
+        .any(|row| !row[columns].is_zero())
+    {
+        return None;
+    }
+    let mut solution = vec![Exponent::zero(); columns];
+    for (row, &pivot) in pivots.iter().enumerate() {
+        solution[pivot] = augmented[row][columns];
+    }
+    Some(solution)
+}
+
+/// Simpler combinations use fewer quantities, then smaller powers.
+fn complexity(powers: &[Exponent]) -> (usize, i64) {
+    let used = powers.iter().filter(|power| !power.is_zero()).count();
+    let size = powers
+        .iter()
+        .map(|power| power.numer().abs() + power.denom() - 1)
+        .sum();
+    (used, size)
+}
+
+impl Instance {
+    /// The unit class of a quantity. Names can refer to unit classes, units, or scalars.
+    fn quantity_class(&self, name: &str) -> Result<CompositeUnitClass, ()> {
+        let lookup = Expression::LookupName(name.to_owned());
+        match self.resolve_expression(&lookup, Default::default())? {
+            Data::Meta(MetaData::UnitClass(class)) => Ok(class),
+            Data::Meta(MetaData::Unit(unit)) => unit.unit_class(self),
+            Data::Value(ValueData::Scalar(scalar)) => Ok(scalar.unit().clone()),
+            _ => Err(()),
+        }
+    }
+
+    /// Makes a matrix with a row for each dimension the classes are made of and a column for
+    /// each class, holding the power of that dimension.
+    fn exponent_matrix(classes: &[&CompositeUnitClass]) -> Vec<Vec<Exponent>> {
+        let dimensions: BTreeSet<_> = classes
+            .iter()
+            .flat_map(|class| class.powers().map(|(id, _)| id))
+            .collect();
+        dimensions
+            .iter()
+            .map(|&dimension| {
+                classes
+                    .iter()
+                    .map(|class| {
+                        class
+                            .powers()
+                            .find(|(id, _)| *id == dimension)
+                            .map_or(Exponent::zero(), |(_, power)| power)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Finds a largest set of independent products of the named quantities which have no unit.
+    /// Every other dimensionless product of them is a product of powers of these groups.
+    pub fn dimensionless_groups(&self, names: &[String]) -> Result<Vec<Group>, ()> {
+        let classes = names
+            .iter()
+            .map(|name| self.quantity_class(name))
+            .collect::<Result<Vec<_>, ()>>()?;
+        let matrix = Self::exponent_matrix(&classes.iter().collect::<Vec<_>>());
+        Ok(nullspace(matrix, names.len())
+            .into_iter()
+            .map(|vector| {
+                let powers = simplest_multiple(vector);
+                names
+                    .iter()
+                    .cloned()
+                    .zip(powers)
+                    .filter(|(_, power)| !power.is_zero())
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Searches for products of powers of the named quantities which have the same unit class
+    /// as the target, simplest first. Any two answers differ by a dimensionless group, so the
+    /// search tries adding a few of each group to one answer. Returns Err(()) if there are too
+    /// many groups to search through.
+    pub fn solve_for_units(&self, target: &str, names: &[String]) -> Result<Vec<Group>, ()> {
+        let target = self.quantity_class(target)?;
+        let classes = names
+            .iter()
+            .map(|name| self.quantity_class(name))
+            .collect::<Result<Vec<_>, ()>>()?;
+        let mut all_classes: Vec<_> = classes.iter().collect();
+        all_classes.push(&target);
+        let mut matrix = Self::exponent_matrix(&all_classes);
+        let target: Vec<_> = matrix.iter_mut().map(|row| row.pop().unwrap()).collect();
+        let columns = names.len();
+        let start = match particular_solution(&matrix, &target, columns) {
+            Some(start) => start,
+            None => return Ok(Vec::new()),
+        };
+        let groups: Vec<_> = nullspace(matrix, columns)
+            .into_iter()
+            .map(simplest_multiple)
+            .collect();
+        let per_group = (2 * GROUP_SEARCH_RANGE + 1) as usize;
+        let mut searched = 1usize;
+        for _ in &groups {
+            searched = searched
+                .checked_mul(per_group)
+                .filter(|&searched| searched <= MAX_CANDIDATES)
+                .ok_or(())?;
+        }
+        let mut candidates = vec![start];
+        for group in &groups {
+            let mut extended = Vec::new();
+            for candidate in &candidates {
+                for times in -GROUP_SEARCH_RANGE..=GROUP_SEARCH_RANGE {
+                    let times = Exponent::from_integer(times);
+                    let moved = candidate
+                        .iter()
+                        .zip(group)
+                        .map(|(power, step)| power + step * times)
+                        .collect();
+                    extended.push(moved);
+                }
+            }
+            candidates = extended;
+        }
+        candidates.sort_by_key(|candidate| (complexity(candidate), candidate.clone()));
+        candidates.dedup();
+        Ok(candidates
+            .into_iter()
+            .take(MAX_COMBINATIONS)
+            .map(|powers| {
+                names
+                    .iter()
+                    .cloned()
+                    .zip(powers)
+                    .filter(|(_, power)| !power.is_zero())
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::instance_with;
+
+    const DEFINITIONS: &str = r#"
+        make unit_class called Mass
+        make base_unit called Gram, Grams { class: Mass, symbol: "g", metric, }
+        make label called density for Mass / Length^3
+        make label called speed for Length / Time
+        make label called viscosity for Mass / Length / Time
+        make label called g for 9.8 * Meters / Second^2
+        make label called Energy for Mass * Length^2 / Time^2
+    "#;
+
+    fn groups(instance: &Instance, names: &[&str]) -> Vec<String> {
+        let names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+        let groups = instance.dimensionless_groups(&names).unwrap();
+        groups.iter().map(|group| describe_group(group)).collect()
+    }
+
+    #[test]
+    fn buckingham_pi() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            groups(&instance, &["density", "speed", "Meter", "viscosity"]),
+            vec!["density * speed * Meter / viscosity"]
+        );
+        // The mass of a pendulum can't be part of any group, since nothing else cancels it.
+        assert_eq!(
+            groups(&instance, &["Second", "Meter", "g", "Gram"]),
+            vec!["Second^2 * g / Meter"]
+        );
+        assert_eq!(
+            groups(&instance, &["Meter", "Meters", "Second"]),
+            vec!["Meter / Meters"]
+        );
+        assert!(groups(&instance, &["Meter", "Second"]).is_empty());
+        assert!(instance
+            .dimensionless_groups(&["nonsense".to_owned()])
+            .is_err());
+    }
+
+    #[test]
+    fn combinations_for_a_target() {
+        let instance = instance_with(DEFINITIONS);
+        let solve = |target: &str, names: &[&str]| {
+            let names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+            let combinations = instance.solve_for_units(target, &names).unwrap();
+            combinations
+                .iter()
+                .map(|group| describe_group(group))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            solve("Energy", &["Gram", "speed", "Meter"]),
+            vec!["Gram * speed^2"]
+        );
+        let lengths = solve("Length", &["speed", "Second", "Meter"]);
+        assert_eq!(lengths[..2], ["Meter", "speed * Second"]);
+        assert_eq!(lengths.len(), 5);
+        assert_eq!(solve("Length", &["Gram", "Second"]), Vec::<String>::new());
+        assert_eq!(solve("Length", &["density"]), Vec::<String>::new());
+        assert_eq!(solve("Meter", &["Meter", "Meter"]).len(), 5);
+        assert_eq!(
+            solve("speed", &["g", "Meter"]),
+            vec!["g^(1/2) * Meter^(1/2)"]
+        );
+        let many_lengths: Vec<_> = (0..12).map(|_| "Meter".to_owned()).collect();
+        assert!(instance.solve_for_units("Length", &many_lengths).is_err());
+    }
+
+    #[test]
+    fn fractional_powers_are_cleared() {
+        let vector = vec![
+            Exponent::new(-1, 2),
+            Exponent::new(1, 3),
+            Exponent::from_integer(0),
+        ];
+        assert_eq!(
+            simplest_multiple(vector),
+            vec![3.into(), Exponent::from_integer(-2), 0.into()]
+        );
+    }
+}
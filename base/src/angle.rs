@@ -0,0 +1,176 @@
+//! Angles as a pseudo-dimension, declared with `make angle_class called Angle`. Units of an angle
+//! class convert through their base ratio like any other unit, so the base unit of an angle class
+//! should be the radian. Unlike other unit classes, angles are dropped from a quantity whenever it
+//! has to match a unit class without them: when it is added to such a quantity, shown in such a
+//! unit, checked against such a class or passed to a trig function.
+
+use crate::prelude::*;
+
+impl CompositeUnitClass {
+    pub fn has_angles(&self, instance: &Instance) -> bool {
+        self.powers().any(|(class, _)| instance[class].angle)
+    }
+
+    /// This unit class with every angle class taken out of it.
+    pub fn without_angles(&self, instance: &Instance) -> Self {
+        self.filter(|class| !instance[class].angle)
+    }
+
+    /// Whether the two unit classes are the same once their angles are taken out.
+    pub fn matches_without_angles(&self, other: &Self, instance: &Instance) -> bool {
+        self.without_angles(instance) == other.without_angles(instance)
+    }
+}
+
+impl CompositeUnit {
+    /// This unit with the angles taken out of it. Units of nothing but angles are dropped and other
+    /// units with angles in their class, like revolutions per minute, are replaced by the base
+    /// units of the rest of their class. Fails if one of those has no base unit.
+    pub fn without_angles(&self, instance: &Instance) -> Result<Self, ()> {
+        let mut result = Self::identity();
+        for (unit, power) in self.powers() {
+            let class = &instance[unit].class;
+            let mut factor = if class.has_angles(instance) {
+                let mut replacement = Self::identity();
+                for (class, power) in class.without_angles(instance).powers() {
+                    let mut base = Self::from(instance.base_unit_of(class).ok_or(())?);
+                    base.pow(power)?;
+                    replacement = (replacement * base)?;
+                }
+                replacement
+            } else {
+                Self::from(unit)
+            };
+            factor.pow(power)?;
+            result = (result * factor)?;
+        }
+        Ok(result)
+    }
+}
+
+impl Instance {
+    /// The unprefixed unit of the given class which values of that class are stored in.
+    pub fn base_unit_of(&self, class: UnitClassId) -> Option<UnitId> {
+        let class = CompositeUnitClass::from(class);
+        self.units()
+            .find(|(_, unit)| {
+                unit.class == class
+                    && unit.prefix_origin.is_none()
+                    && unit.base_ratio == Number::from_integer(1)
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// The unit angles are described in when they weren't given in one: the base unit of the
+    /// first angle class, if there is one.
+    pub(crate) fn default_angle_unit(&self) -> Option<CompositeUnit> {
+        self.unit_classes()
+            .filter(|(_, class)| class.angle)
+            .find_map(|(id, _)| self.base_unit_of(id))
+            .map(CompositeUnit::from)
+    }
+
+    /// Trig functions take plain numbers or a single power of an angle, which they drop.
+    pub fn angle_argument(&self, x: &Scalar) -> Result<Scalar, ()> {
+        if x.unit().is_identity() {
+            Ok(x.clone())
+        } else if x.unit().as_single().is_some_and(|class| self[class].angle) {
+            x.without_angles(self)
+        } else {
+            Err(())
+        }
+    }
+
+    /// Drops the angles from whichever of the two scalars has them if that makes their unit
+    /// classes match, so that they can be added.
+    pub(crate) fn match_angles(&self, lhs: Scalar, rhs: Scalar) -> Result<(Scalar, Scalar), ()> {
+        if lhs.unit() == rhs.unit() || !lhs.unit().matches_without_angles(rhs.unit(), self) {
+            return Ok((lhs, rhs));
+        }
+        Ok((lhs.without_angles(self)?, rhs.without_angles(self)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{evaluate, instance_with};
+
+    const DEFINITIONS: &str = r#"
+        make angle_class called Angle
+        make base_unit called Radian, Radians { class: Angle, symbol: "rad", metric, }
+        make derived_unit called Degree, Degrees {
+            symbol: "deg", value: 3.141592653589793 / 180 * Radians,
+        }
+        make derived_unit called Turn, Turns { symbol: "turn", value: 360 * Degrees, }
+        make derived_unit called Steradian, Steradians { symbol: "sr", value: 1 * Radians^2, }
+        make label called Velocity for Length / Time
+        make label called omega for 2 * Radians / Second
+        make label called r for 3 * Meters
+    "#;
+
+    #[test]
+    fn conversion() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            evaluate(&instance, "1 * Turns in Degrees").unwrap(),
+            "360deg"
+        );
+        assert_eq!(evaluate(&instance, "omega").unwrap(), "2rad/s");
+        assert_eq!(
+            evaluate(&instance, "90 * Degrees is Angle").unwrap(),
+            "true"
+        );
+    }
+
+    #[test]
+    fn trig() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(evaluate(&instance, "sin(90 * Degrees)").unwrap(), "1");
+        assert_eq!(evaluate(&instance, "cos(0 * Radians)").unwrap(), "1");
+        assert!(evaluate(&instance, "sin(1 * Steradians)").is_err());
+        assert!(evaluate(&instance, "sin(1 * Meters)").is_err());
+    }
+
+    #[test]
+    fn polar() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            evaluate(&instance, "re(2 ∠ (180 * Degrees))").unwrap(),
+            "-2"
+        );
+        assert_eq!(
+            evaluate(&instance, "2 ∠ (90 * Degrees)").unwrap(),
+            "2 ∠ 90deg"
+        );
+        assert_eq!(
+            evaluate(&instance, "2 ∠ (0.25 * Turns)").unwrap(),
+            "2 ∠ 0.25turn"
+        );
+        assert_eq!(
+            evaluate(&instance, "polar(1i)").unwrap(),
+            "1 ∠ 1.5707963267948966rad"
+        );
+        assert!(evaluate(&instance, "2 ∠ (1 * Meters)").is_err());
+        assert!(evaluate(&instance, "2 ∠ (1 * Steradians)").is_err());
+    }
+
+    #[test]
+    fn dropped_to_match() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(evaluate(&instance, "omega * r").unwrap(), "6mrad/s");
+        assert_eq!(
+            evaluate(&instance, "omega * r in Meters / Second").unwrap(),
+            "6m/s"
+        );
+        assert_eq!(
+            evaluate(&instance, "omega * r + 1 * Meters / Second").unwrap(),
+            "7m/s"
+        );
+        assert_eq!(
+            evaluate(&instance, "omega * r is Velocity").unwrap(),
+            "true"
+        );
+        assert!(evaluate(&instance, "{ speed: Velocity = omega * r }").is_ok());
+        assert!(evaluate(&instance, "omega * r + 1 * Meters").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! `derivative(f, x)` and `integrate(f, x, a, b)`. Derivatives are worked out symbolically and then
+//! evaluated, integrals are estimated numerically in base units. Either way the unit class of the
+//! result comes from the unit classes of `f` and `x`.
+
+use crate::{data::Data, expression::Expression, matrix::count, prelude::*, symbolic};
+
+/// How many times an interval can be halved while integrating.
+const MAX_DEPTH: u32 = 16;
+/// Integrals are estimated to this fraction of the size of the integrand times the interval.
+const TOLERANCE: f64 = 1e-10;
+
+/// Simpson's rule over `a..b` given the value of `f` at both ends and the middle.
+fn simpson(a: f64, b: f64, fa: f64, fm: f64, fb: f64) -> f64 {
+    (b - a) / 6.0 * (fa + 4.0 * fm + fb)
+}
+
+/// Adaptive Simpson's rule. Returns the integral and an estimate of its error.
+fn adaptive_simpson(
+    f: &impl Fn(f64) -> Result<f64, ()>,
+    (a, b): (f64, f64),
+    (fa, fm, fb): (f64, f64, f64),
+    whole: f64,
+    tolerance: f64,
+    depth: u32,
+) -> Result<(f64, f64), ()> {
+    let m = (a + b) / 2.0;
+    let (flm, frm) = (f((a + m) / 2.0)?, f((m + b) / 2.0)?);
+    let left = simpson(a, m, fa, flm, fm);
+    let right = simpson(m, b, fm, frm, fb);
+    let delta = left + right - whole;
+    if depth == 0 || delta.abs() <= 15.0 * tolerance {
+        // Richardson extrapolation of the two estimates.
+        return Ok((left + right + delta / 15.0, delta.abs() / 15.0));
+    }
+    let tolerance = tolerance / 2.0;
+    let (left, left_error) =
+        adaptive_simpson(f, (a, m), (fa, flm, fm), left, tolerance, depth - 1)?;
+    let (right, right_error) =
+        adaptive_simpson(f, (m, b), (fm, frm, fb), right, tolerance, depth - 1)?;
+    Ok((left + right, left_error + right_error))
+}
+
+impl Instance {
+    /// Resolves `derivative(f, x)`, `derivative(f, x, at)` and `integrate(f, x, from, to)`.
+    pub(crate) fn resolve_calculus(
+        &self,
+        name: &str,
+        arguments: &[Expression],
+        bindings: &Bindings,
+    ) -> Result<Data, ()> {
+        use Expression::LookupName;
+        let result = match (name, arguments) {
+            ("derivative", [f, LookupName(x)]) => self.derivative(f, x, None, bindings)?,
+            ("derivative", [f, LookupName(x), at]) => self.derivative(f, x, Some(at), bindings)?,
+            ("integrate", [f, LookupName(x), from, to]) => {
+                self.integrate(f, x, from, to, bindings)?
+            }
+            _ => return Err(()),
+        };
+        Ok(result.into())
+    }
+
+    /// The derivative of `f` with respect to `variable`, at the value of `variable` given by `at`
+    /// or by the surrounding bindings.
+    pub fn derivative(
+        &self,
+        f: &Expression,
+        variable: &str,
+        at: Option<&Expression>,
+        bindings: &Bindings,
+    ) -> Result<Scalar, ()> {
+        let mut bindings = bindings.clone();
+        if let Some(at) = at {
+            let at = self.resolve_real(at, &bindings)?;
+            bindings.insert(variable.to_owned(), at.into());
+        }
+        let point = self.resolve_real(&Expression::LookupName(variable.to_owned()), &bindings)?;
+        let derivative = symbolic::differentiate(f, variable)?;
+        let value = self.resolve_real(&derivative, &bindings)?;
+        let expected = (self.resolve_real(f, &bindings)? / point)?;
+        if value.unit() == expected.unit() {
+            Ok(value)
+        } else if value.raw_value().is_zero() {
+            // The derivative of a constant is written as a plain zero, which has lost its unit.
+            expected * count(0)
+        } else {
+            Err(())
+        }
+    }
+
+    /// The integral of `f` as `variable` goes from `from` to `to`. The precision of the result
+    /// is estimated from how much the estimate changed when the intervals were halved.
+    pub fn integrate(
+        &self,
+        f: &Expression,
+        variable: &str,
+        from: &Expression,
+        to: &Expression,
+        bindings: &Bindings,
+    ) -> Result<Scalar, ()> {
+        let from = self.resolve_real(from, bindings)?;
+        let to = self.resolve_real(to, bindings)?;
+        if from.unit() != to.unit() {
+            return Err(());
+        }
+        let value_at = |x: f64| {
+            let x = Scalar::new(
+                x.into(),
+                Precision::Exact,
+                from.unit().clone(),
+                from.display_unit().clone(),
+            );
+            let mut bindings = bindings.clone();
+            bindings.insert(variable.to_owned(), x.into());
+            self.resolve_real(f, &bindings)
+        };
+        let (a, b) = (from.raw_value().to_f64(), to.raw_value().to_f64());
+        let first = value_at(a)?;
+        let f = |x: f64| {
+            let value = value_at(x)?;
+            let same_unit = value.unit() == first.unit();
+            let value = value.raw_value().to_f64();
+            if value.is_finite() && same_unit {
+                Ok(value)
+            } else {
+                Err(())
+            }
+        };
+        let (fa, fm, fb) = (f(a)?, f((a + b) / 2.0)?, f(b)?);
+        let whole = simpson(a, b, fa, fm, fb);
+        let scale = (b - a).abs() * fa.abs().max(fm.abs()).max(fb.abs());
+        let (integral, error) = adaptive_simpson(
+            &f,
+            (a, b),
+            (fa, fm, fb),
+            whole,
+            TOLERANCE * scale,
+            MAX_DEPTH,
+        )?;
+        let precision = if error > 0.0 && integral != 0.0 {
+            Precision::PercentError(error / integral.abs())
+        } else {
+            Precision::Exact
+        };
+        Ok(Scalar::new(
+            integral.into(),
+            precision,
+            (first.unit().clone() * from.unit().clone())?,
+            (first.display_unit().clone() * from.display_unit().clone())?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expression::parse_expression,
+        test_util::{evaluate, instance_with},
+    };
+
+    const DEFINITIONS: &str = r#"
+        make label called acceleration for 5 * Meters / Seconds^2
+    "#;
+
+    #[test]
+    fn derivatives() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            evaluate(
+                &instance,
+                "derivative(acceleration * t^2 / 2, t, 3 * Seconds)"
+            )
+            .unwrap(),
+            "15m/s"
+        );
+        assert_eq!(
+            evaluate(&instance, "derivative(5 * Meters, t, 3 * Seconds)").unwrap(),
+            "0m/s"
+        );
+        assert_eq!(
+            evaluate(&instance, "derivative(sin(x), x, 0)").unwrap(),
+            "1"
+        );
+        assert!(evaluate(&instance, "derivative(t^2, t)").is_err());
+        assert!(evaluate(&instance, "derivative(abs(t), t, 1)").is_err());
+    }
+
+    #[test]
+    fn integrals() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            evaluate(
+                &instance,
+                "integrate(acceleration * t, t, 0 * Seconds, 2 * Seconds)"
+            )
+            .unwrap(),
+            "10m"
+        );
+        let (_, expression) =
+            parse_expression("integrate(sin(x), x, 0, 3.141592653589793)").unwrap();
+        let area = instance
+            .resolve_expression(&expression, Default::default())
+            .unwrap();
+        match area {
+            Data::Value(crate::data::ValueData::Scalar(area)) => {
+                assert!((area.raw_value().to_f64() - 2.0).abs() < 1e-9)
+            }
+            _ => panic!("not a scalar"),
+        }
+        assert!(evaluate(&instance, "integrate(t, t, 0 * Seconds, 1 * Meters)").is_err());
+        assert!(evaluate(&instance, "integrate(1 / t, t, 0, 1)").is_err());
+    }
+}
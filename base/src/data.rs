@@ -1,5 +1,5 @@
-use std::fmt::{Debug, Formatter, Write};
 use crate::{entity::Entity, prelude::*};
+use std::fmt::{Debug, Formatter, Write};
 
 pub trait Describe {
     fn describe(&self, into: &mut String, instance: &Instance);
@@ -8,6 +8,7 @@ pub trait Describe {
 macro_rules! make_enum {
     ($EnumName:ident { $($VariantName:ident($Contents:ident),)* }) => {
         #[derive(Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum $EnumName {
             $($VariantName($Contents),)*
         }
@@ -50,6 +51,7 @@ make_enum! {
 make_enum! {
     ValueData {
         Scalar(Scalar),
+        Matrix(Matrix),
         Entity(Entity),
         String(String),
         Bool(bool),
@@ -84,6 +86,7 @@ from_into!(UnitId MetaData Unit);
 from_into!(EntityClassId MetaData EntityClass);
 
 from_into!(Scalar ValueData Scalar);
+from_into!(Matrix ValueData Matrix);
 from_into!(Entity ValueData Entity);
 from_into!(String ValueData String);
 from_into!(bool ValueData Bool);
@@ -1,13 +1,53 @@
 use crate::{
-    data::{Data, Describe},
+    data::{Data, Describe, MetaData},
+    expression::Expression,
     prelude::{EntityClassId, Instance},
 };
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EntityClass {
     pub names: Vec<String>,
+    /// Entities of this class are also of these classes and have their properties.
+    pub superclasses: Vec<EntityClassId>,
+    /// The properties this class declares, not including inherited ones.
+    pub properties: Vec<PropertyDeclaration>,
+}
+
+impl EntityClass {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            superclasses: Vec::new(),
+            properties: Vec::new(),
+        }
+    }
+}
+
+/// A property of every entity of a class, written `Radius: Length` if entities must give it,
+/// `Sides: 0` to give it a default or `Sides: Count = 0` for both.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PropertyDeclaration {
+    pub name: String,
+    pub annotation: Option<Annotation>,
+    pub default: Option<Data>,
+}
+
+/// The unit class or entity class named by an annotation like `Radius: Length`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Annotation {
+    /// The annotation as it was written, for error messages.
+    pub text: String,
+    /// Whether the annotation is the name of a label, which error messages explain.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub names_label: bool,
+    pub expected: Data,
+    /// Set if the annotation names a kind of quantity rather than just a unit class.
+    pub kind: Option<String>,
 }
 
 impl Describe for EntityClass {
@@ -17,8 +57,17 @@ impl Describe for EntityClass {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entity {
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::snapshot::serialize_sorted_map")
+    )]
     pub properties: HashMap<String, Data>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::snapshot::serialize_sorted_set")
+    )]
     pub classes: HashSet<EntityClassId>,
 }
 
@@ -32,7 +81,7 @@ impl Describe for Entity {
                 write!(into, ", ").unwrap();
                 class.describe(into, instance);
             }
-            if self.properties.len() > 0 {
+            if !self.properties.is_empty() {
                 write!(into, ", ").unwrap();
             }
         }
@@ -49,3 +98,199 @@ impl Describe for Entity {
         write!(into, " }}").unwrap();
     }
 }
+
+impl Instance {
+    /// Makes an entity class from a definition like `{ Shape, Radius: Length, Sides: 1 }`. Names
+    /// on their own are superclasses. A property whose value is a unit class or entity class must
+    /// be given by every entity, any other value is a default.
+    pub fn define_entity_class(
+        &mut self,
+        names: Vec<String>,
+        definition: &Expression,
+    ) -> Result<EntityClassId, ()> {
+        let (properties, class_names) = match definition {
+            Expression::BuildEntity {
+                properties,
+                class_names,
+            } => (properties, class_names),
+            _ => return Err(()),
+        };
+        let mut class = EntityClass::new(names);
+        for name in class_names {
+            match self.lookup_item(name).as_meta {
+                Some(MetaData::EntityClass(id)) => class.superclasses.push(*id),
+                _ => return Err(()),
+            }
+        }
+        for (name, value) in properties {
+            let no_bindings = Default::default();
+            let declaration = match value {
+                Expression::Annotated { value, annotation } => {
+                    let annotation = self
+                        .resolve_annotation(annotation, &no_bindings)
+                        .map_err(|message| self.fail(message))?;
+                    let default = self.resolve_expression(value, Default::default())?;
+                    let default = self
+                        .check_type(default, &annotation)
+                        .map_err(|message| self.fail(message))?;
+                    PropertyDeclaration {
+                        name: name.clone(),
+                        annotation: Some(annotation),
+                        default: Some(default),
+                    }
+                }
+                _ => match self.resolve_expression(value, Default::default())? {
+                    Data::Meta(MetaData::UnitClass(..)) | Data::Meta(MetaData::EntityClass(..)) => {
+                        PropertyDeclaration {
+                            name: name.clone(),
+                            annotation: self.resolve_annotation(value, &no_bindings).ok(),
+                            default: None,
+                        }
+                    }
+                    default => PropertyDeclaration {
+                        name: name.clone(),
+                        annotation: None,
+                        default: Some(default),
+                    },
+                },
+            };
+            class.properties.push(declaration);
+        }
+        self.add_entity_class(class)
+    }
+
+    /// The class followed by all of the classes it inherits from, nearest first.
+    pub fn entity_class_ancestry(&self, class: EntityClassId) -> Vec<EntityClassId> {
+        let mut ancestry = vec![class];
+        let mut next = 0;
+        while next < ancestry.len() {
+            for &superclass in &self[ancestry[next]].superclasses {
+                if !ancestry.contains(&superclass) {
+                    ancestry.push(superclass);
+                }
+            }
+            next += 1;
+        }
+        ancestry
+    }
+
+    /// Whether the entity is of the class, either directly or through a superclass.
+    pub fn entity_is_a(&self, entity: &Entity, class: EntityClassId) -> bool {
+        entity
+            .classes
+            .iter()
+            .any(|&own| self.entity_class_ancestry(own).contains(&class))
+    }
+
+    /// Fills in the defaults of the entity's classes and checks the properties they declare. Where
+    /// classes declare the same property, the nearest one is used.
+    pub fn complete_entity(&self, mut entity: Entity) -> Result<Entity, String> {
+        let mut own_classes: Vec<_> = entity.classes.iter().copied().collect();
+        own_classes.sort();
+        let mut declared = HashSet::new();
+        for own in own_classes {
+            for class in self.entity_class_ancestry(own) {
+                for declaration in &self[class].properties {
+                    if !declared.insert(declaration.name.clone()) {
+                        continue;
+                    }
+                    let value = match entity.properties.remove(&declaration.name) {
+                        Some(value) => value,
+                        None => match &declaration.default {
+                            Some(default) => default.clone(),
+                            None => {
+                                return Err(format!(
+                                    "{} needs a {} property",
+                                    self[class].names[0], declaration.name
+                                ))
+                            }
+                        },
+                    };
+                    let value = match &declaration.annotation {
+                        Some(annotation) => {
+                            self.check_type(value, annotation).map_err(|message| {
+                                format!(
+                                    "{}.{}: {}",
+                                    self[class].names[0], declaration.name, message
+                                )
+                            })?
+                        }
+                        None => value,
+                    };
+                    entity.properties.insert(declaration.name.clone(), value);
+                }
+            }
+        }
+        Ok(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::ValueData, expression::parse_expression, test_util::instance_with};
+
+    const DEFINITIONS: &str = r#"
+        make entity_class called Shape { Sides: 0, Name: "shape" }
+        make entity_class called Circle { Shape, Radius: Length, Sides: 1 }
+        make entity_class called Wheel { Circle, Spokes: 12 }
+    "#;
+
+    fn build(instance: &Instance, source: &str) -> Result<Entity, ()> {
+        let (rest, expression) = parse_expression(source).unwrap();
+        assert_eq!(rest, "");
+        match instance.resolve_expression(&expression, Default::default())? {
+            Data::Value(ValueData::Entity(entity)) => Ok(entity),
+            _ => Err(()),
+        }
+    }
+
+    fn describe(instance: &Instance, data: &Data) -> String {
+        let mut description = String::new();
+        data.describe(&mut description, instance);
+        description
+    }
+
+    #[test]
+    fn defaults_are_inherited() {
+        let instance = instance_with(DEFINITIONS);
+        let wheel = build(&instance, "{ Wheel, Radius: 2 * Meters }").unwrap();
+        assert_eq!(describe(&instance, &wheel.properties["Sides"]), "1");
+        assert_eq!(describe(&instance, &wheel.properties["Name"]), "shape");
+        assert_eq!(describe(&instance, &wheel.properties["Spokes"]), "12");
+        let wheel = build(&instance, "{ Wheel, Radius: 2 * Meters, Spokes: 32 }").unwrap();
+        assert_eq!(describe(&instance, &wheel.properties["Spokes"]), "32");
+    }
+
+    #[test]
+    fn required_properties() {
+        let instance = instance_with(DEFINITIONS);
+        assert!(build(&instance, "{ Circle }").is_err());
+        assert_eq!(
+            instance.take_error().unwrap(),
+            "Circle needs a Radius property"
+        );
+        assert!(build(&instance, "{ Circle, Radius: 2 }").is_err());
+        assert!(instance
+            .take_error()
+            .unwrap()
+            .starts_with("Circle.Radius: "));
+        assert!(build(&instance, "{ Shape }").is_ok());
+    }
+
+    #[test]
+    fn superclass_chain() {
+        let instance = instance_with(DEFINITIONS);
+        let (_, expression) = parse_expression("{ Wheel, Radius: 1 * Meters } is Shape").unwrap();
+        let result = instance
+            .resolve_expression(&expression, Default::default())
+            .unwrap();
+        assert_eq!(describe(&instance, &result), "true");
+        let shape = build(&instance, "{ Shape }").unwrap();
+        let circle = instance.lookup_item(&"Circle".to_owned()).as_meta.cloned();
+        match circle {
+            Some(MetaData::EntityClass(circle)) => assert!(!instance.entity_is_a(&shape, circle)),
+            _ => panic!("Circle is not an entity class"),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Dumps the units, unit classes and labels an instance knows about so that other tools can use
+//! the same conversion factors.
+
+use crate::{
+    data::{Data, Describe, MetaData, ValueData},
+    prelude::*,
+};
+use std::{fmt::Write, path::Path};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A single file containing every table.
+    Json,
+    /// A directory containing `unit_classes.csv`, `units.csv` and `labels.csv`.
+    Csv,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnitClassRow {
+    pub id: usize,
+    pub names: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnitRow {
+    pub id: usize,
+    pub names: Vec<String>,
+    pub symbol: String,
+    /// The first name of each unit class this unit is made of, with the power it is raised to.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "json::dimensions"))]
+    pub dimensions: Vec<(String, Exponent)>,
+    /// Multiply a value in this unit by this number to get the value in base units.
+    pub base_ratio: f64,
+    /// The name of the unit and the prefix this unit was generated from, if any.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "json::prefix_origin"))]
+    pub prefix_origin: Option<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LabelRow {
+    pub names: Vec<String>,
+    /// Present if the label refers to a unit class, a unit, or a scalar.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "json::optional_dimensions"))]
+    pub dimensions: Option<Vec<(String, Exponent)>>,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnitTable {
+    pub unit_classes: Vec<UnitClassRow>,
+    pub units: Vec<UnitRow>,
+    pub labels: Vec<LabelRow>,
+}
+
+fn dimensions(class: &CompositeUnitClass, instance: &Instance) -> Vec<(String, Exponent)> {
+    class
+        .powers()
+        .map(|(id, power)| (instance[id].names[0].clone(), power))
+        .collect()
+}
+
+impl Instance {
+    pub fn unit_table(&self) -> UnitTable {
+        let unit_classes = self
+            .unit_classes()
+            .map(|(id, class)| UnitClassRow {
+                id: id.index(),
+                names: class.names.clone(),
+            })
+            .collect();
+        let units = self
+            .units()
+            .map(|(id, unit)| UnitRow {
+                id: id.index(),
+                names: unit.names.clone(),
+                symbol: unit.symbol.clone(),
+                dimensions: dimensions(&unit.class, self),
+                base_ratio: unit.base_ratio.to_f64(),
+                prefix_origin: unit.prefix_origin.map(|origin| {
+                    let unit_name = self[origin.unit].names[0].clone();
+                    let prefix_name = METRIC_PREFIXES[origin.prefix].0.to_owned();
+                    (unit_name, prefix_name)
+                }),
+            })
+            .collect();
+        let labels = self
+            .labels()
+            .into_iter()
+            .map(|(names, data)| {
+                let class = match data {
+                    Data::Meta(MetaData::UnitClass(class)) => Some(class.clone()),
+                    Data::Meta(MetaData::Unit(unit)) => unit.unit_class(self).ok(),
+                    Data::Value(ValueData::Scalar(scalar)) => Some(scalar.unit().clone()),
+                    _ => None,
+                };
+                let mut value = String::new();
+                data.describe(&mut value, self);
+                LabelRow {
+                    names: names.into_iter().cloned().collect(),
+                    dimensions: class.map(|class| dimensions(&class, self)),
+                    value,
+                }
+            })
+            .collect();
+        UnitTable {
+            unit_classes,
+            units,
+            labels,
+        }
+    }
+}
+
+/// How the export tables are laid out in JSON where that differs from how they are stored.
+#[cfg(feature = "serde")]
+mod json {
+    use crate::prelude::*;
+    use serde::Serializer;
+
+    /// Powers are written as exact fractions in strings like "1/3", the same way as in CSV files.
+    pub fn dimensions<S: Serializer>(
+        dimensions: &[(String, Exponent)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            dimensions
+                .iter()
+                .map(|(name, power)| (name, power.to_string())),
+        )
+    }
+
+    pub fn optional_dimensions<S: Serializer>(
+        dimensions: &Option<Vec<(String, Exponent)>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match dimensions {
+            Some(dimensions) => self::dimensions(dimensions, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn prefix_origin<S: Serializer>(
+        prefix_origin: &Option<(String, String)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match prefix_origin {
+            Some((unit, prefix)) => {
+                serializer.collect_map(vec![("unit", unit), ("prefix", prefix)])
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Quotes the field if it contains anything that would otherwise break up the row.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Lists are written into a single cell separated by semicolons.
+fn csv_names(names: &[String]) -> String {
+    csv_field(&names.join(";"))
+}
+
+fn csv_dimensions(dimensions: &[(String, Exponent)]) -> String {
+    let parts: Vec<_> = dimensions
+        .iter()
+        .map(|(name, power)| format!("{}^{}", name, power))
+        .collect();
+    csv_field(&parts.join(";"))
+}
+
+impl UnitTable {
+    /// Infinite or NaN base ratios are written as null, since JSON has no way to write them.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, ()> {
+        serde_json::to_string(self).map_err(|_| ())
+    }
+
+    pub fn unit_classes_csv(&self) -> String {
+        let mut csv = String::from("id,names\n");
+        for row in &self.unit_classes {
+            writeln!(csv, "{},{}", row.id, csv_names(&row.names)).unwrap();
+        }
+        csv
+    }
+
+    pub fn units_csv(&self) -> String {
+        let mut csv = String::from("id,names,symbol,dimensions,base_ratio,prefix_of,prefix\n");
+        for row in &self.units {
+            let (prefix_of, prefix) = row
+                .prefix_origin
+                .clone()
+                .unwrap_or((String::new(), String::new()));
+            writeln!(
+                csv,
+                "{},{},{},{},{:?},{},{}",
+                row.id,
+                csv_names(&row.names),
+                csv_field(&row.symbol),
+                csv_dimensions(&row.dimensions),
+                row.base_ratio,
+                csv_field(&prefix_of),
+                csv_field(&prefix),
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    pub fn labels_csv(&self) -> String {
+        let mut csv = String::from("names,dimensions,value\n");
+        for row in &self.labels {
+            let dimensions = row
+                .dimensions
+                .as_ref()
+                .map(|dimensions| csv_dimensions(dimensions))
+                .unwrap_or_default();
+            writeln!(
+                csv,
+                "{},{},{}",
+                csv_names(&row.names),
+                dimensions,
+                csv_field(&row.value)
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// Writes the table to a file (for JSON) or a directory (for CSV), see ExportFormat. JSON is
+    /// only available with the serde feature.
+    pub fn write(&self, format: ExportFormat, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        match format {
+            #[cfg(feature = "serde")]
+            ExportFormat::Json => std::fs::write(path, serde_json::to_string(self)?),
+            #[cfg(not(feature = "serde"))]
+            ExportFormat::Json => Err(std::io::Error::other(
+                "exporting JSON needs the serde feature",
+            )),
+            ExportFormat::Csv => {
+                std::fs::create_dir_all(path)?;
+                std::fs::write(path.join("unit_classes.csv"), self.unit_classes_csv())?;
+                std::fs::write(path.join("units.csv"), self.units_csv())?;
+                std::fs::write(path.join("labels.csv"), self.labels_csv())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statement::parse_statements;
+
+    fn make_table() -> UnitTable {
+        let mut instance = Instance::new();
+        let (_, statements) = parse_statements(
+            r#"
+            make entity_class called metric
+            make entity_class called partial_metric
+            make unit_class called Length
+            make base_unit called Meter, Meters { class: Length, symbol: "m", metric, }
+            make unit_class called Time
+            make base_unit called Second, Seconds { class: Time, symbol: "s", }
+            make label called Velocity for Length / Time
+            make label called VolumeSide for Length^(1/3)
+            "#,
+        )
+        .unwrap();
+        instance.execute_statements(statements).unwrap();
+        instance.unit_table()
+    }
+
+    #[test]
+    fn units_have_dimensions_and_prefixes() {
+        let table = make_table();
+        let kilometer = table
+            .units
+            .iter()
+            .find(|row| row.names[0] == "Kilometer")
+            .unwrap();
+        assert_eq!(kilometer.symbol, "km");
+        assert_eq!(kilometer.dimensions, vec![("Length".to_owned(), 1.into())]);
+        assert_eq!(kilometer.base_ratio, 1000.0);
+        assert_eq!(
+            kilometer.prefix_origin,
+            Some(("Meter".to_owned(), "Kilo".to_owned()))
+        );
+        let velocity = &table.labels[0];
+        assert_eq!(
+            velocity.dimensions,
+            Some(vec![
+                ("Length".to_owned(), 1.into()),
+                ("Time".to_owned(), (-1).into())
+            ])
+        );
+    }
+
+    #[test]
+    fn csv_and_json_output() {
+        let table = make_table();
+        let units = table.units_csv();
+        let mut lines = units.lines();
+        assert_eq!(
+            lines.next(),
+            Some("id,names,symbol,dimensions,base_ratio,prefix_of,prefix")
+        );
+        assert_eq!(lines.next(), Some("0,Meter;Meters,m,Length^1,1.0,,"));
+        assert!(units.contains("Kilometer;Kilometers,km,Length^1,1000.0,Meter,Kilo\n"));
+        assert!(table
+            .labels_csv()
+            .contains("Velocity,Length^1;Time^-1,Length/Time\n"));
+        #[cfg(feature = "serde")]
+        assert!(table.to_json().unwrap().contains(
+            "{\"id\":0,\"names\":[\"Meter\",\"Meters\"],\"symbol\":\"m\",\
+            \"dimensions\":{\"Length\":\"1\"},\"base_ratio\":1.0,\"prefix_origin\":null}"
+        ));
+        #[cfg(feature = "serde")]
+        assert!(table
+            .to_json()
+            .unwrap()
+            .contains("{\"names\":[\"VolumeSide\"],\"dimensions\":{\"Length\":\"1/3\"},"));
+        #[cfg(feature = "serde")]
+        assert!(table
+            .to_json()
+            .unwrap()
+            .contains("\"prefix_origin\":{\"unit\":\"Meter\",\"prefix\":\"Kilo\"}"));
+    }
+}
@@ -1,4 +1,9 @@
+use crate::number::Number;
 use nom::IResult;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
@@ -12,14 +17,22 @@ pub enum BinaryOp {
     Mul,
     Div,
     Pow,
+    /// Builds a complex number from a magnitude and an angle, `5 ∠ 53.13deg`.
+    Polar,
+    /// Picks a component of a vector or a row of a matrix, `v[0]`.
+    Index,
     InUnits,
     IsClass,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
-    NumericLiteral(f64),
+    NumericLiteral(Number),
+    /// A literal like `4i`.
+    ImaginaryLiteral(Number),
     StringLiteral(String),
+    /// `[a, b, c]`, which builds a vector, or a matrix if the items are themselves vectors.
+    ListLiteral(Vec<Expression>),
     LookupName(String),
     UnaryExpr(UnaryOp, Box<Expression>),
     BinaryExpr(Box<Expression>, BinaryOp, Box<Expression>),
@@ -31,11 +44,206 @@ pub enum Expression {
         properties: Vec<(String, Expression)>,
         class_names: Vec<String>,
     },
+    /// `start..end step step`, which builds a list of evenly spaced scalars.
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+        step: Option<Box<Expression>>,
+    },
+    /// A value which must have the unit class or entity class given by the annotation, written
+    /// `name: Annotation = value` in an entity builder.
+    Annotated {
+        value: Box<Expression>,
+        annotation: Box<Expression>,
+    },
 }
 
-mod parsing {
-    use std::f64::NAN;
+impl Expression {
+    /// Returns every name this expression looks up, including the names of entity classes used
+    /// when building entities.
+    pub fn referenced_names(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.collect_referenced_names(&mut names);
+        names
+    }
+
+    fn collect_referenced_names(&self, into: &mut BTreeSet<String>) {
+        match self {
+            Self::NumericLiteral(..) | Self::ImaginaryLiteral(..) | Self::StringLiteral(..) => (),
+            Self::LookupName(name) => {
+                into.insert(name.clone());
+            }
+            Self::ListLiteral(items) => {
+                for item in items {
+                    item.collect_referenced_names(into);
+                }
+            }
+            Self::UnaryExpr(_, rhs) => rhs.collect_referenced_names(into),
+            Self::BinaryExpr(lhs, _, rhs) => {
+                lhs.collect_referenced_names(into);
+                rhs.collect_referenced_names(into);
+            }
+            Self::ApplyFunction {
+                function,
+                arguments,
+            } => {
+                function.collect_referenced_names(into);
+                for argument in arguments {
+                    argument.collect_referenced_names(into);
+                }
+            }
+            Self::BuildEntity {
+                properties,
+                class_names,
+            } => {
+                for (_, value) in properties {
+                    value.collect_referenced_names(into);
+                }
+                into.extend(class_names.iter().cloned());
+            }
+            Self::Range { start, end, step } => {
+                start.collect_referenced_names(into);
+                end.collect_referenced_names(into);
+                if let Some(step) = step {
+                    step.collect_referenced_names(into);
+                }
+            }
+            Self::Annotated { value, annotation } => {
+                value.collect_referenced_names(into);
+                annotation.collect_referenced_names(into);
+            }
+        }
+    }
+}
+
+impl BinaryOp {
+    /// Operators with higher precedence are applied first, matching the parser.
+    fn precedence(self) -> u8 {
+        match self {
+            Self::InUnits | Self::IsClass => 5,
+            Self::Add | Self::Sub => 10,
+            Self::Polar => 15,
+            Self::Mul | Self::Div => 20,
+            Self::Pow => 30,
+            Self::Index => 40,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => " + ",
+            Self::Sub => " - ",
+            Self::Mul => " * ",
+            Self::Div => " / ",
+            Self::Pow => "^",
+            Self::Polar => " ∠ ",
+            Self::InUnits => " in ",
+            Self::IsClass => " is ",
+            Self::Index => unreachable!(),
+        }
+    }
+}
 
+impl Expression {
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::BinaryExpr(_, op, _) => op.precedence(),
+            Self::Range { .. } => 7,
+            // Only written as the value of a property, where it needs no brackets.
+            Self::Annotated { .. } => 50,
+            // Written as (0 - x) since there is no syntax for negation.
+            Self::UnaryExpr(..) => 50,
+            _ => 50,
+        }
+    }
+}
+
+/// Writes the expression as text which parses back to the same expression, adding only the
+/// brackets which are needed.
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let child = |f: &mut Formatter<'_>, child: &Expression, min_precedence: u8| {
+            if child.precedence() < min_precedence {
+                write!(f, "({})", child)
+            } else {
+                write!(f, "{}", child)
+            }
+        };
+        match self {
+            Self::NumericLiteral(value) => write!(f, "{}", value),
+            Self::ImaginaryLiteral(value) => write!(f, "{}i", value),
+            Self::StringLiteral(value) => write!(f, "\"{}\"", value),
+            Self::ListLiteral(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Self::LookupName(name) => write!(f, "{}", name),
+            Self::UnaryExpr(UnaryOp::Negate, rhs) => write!(f, "(0 - {})", rhs),
+            Self::BinaryExpr(lhs, BinaryOp::Index, rhs) => {
+                child(f, lhs, 40)?;
+                write!(f, "[{}]", rhs)
+            }
+            Self::BinaryExpr(lhs, op, rhs) => {
+                let precedence = op.precedence();
+                // Powers group to the right, everything else groups to the left.
+                let (lhs_min, rhs_min) = if *op == BinaryOp::Pow {
+                    (precedence + 1, precedence)
+                } else {
+                    (precedence, precedence + 1)
+                };
+                child(f, lhs, lhs_min)?;
+                write!(f, "{}", op.symbol())?;
+                child(f, rhs, rhs_min)
+            }
+            Self::ApplyFunction {
+                function,
+                arguments,
+            } => {
+                child(f, function, 50)?;
+                write!(f, "(")?;
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Self::BuildEntity {
+                properties,
+                class_names,
+            } => {
+                write!(f, "{{")?;
+                for (name, value) in properties {
+                    write!(f, " {}: {},", name, value)?;
+                }
+                for name in class_names {
+                    write!(f, " {},", name)?;
+                }
+                write!(f, " }}")
+            }
+            Self::Range { start, end, step } => {
+                child(f, start, 10)?;
+                write!(f, "..")?;
+                child(f, end, 10)?;
+                if let Some(step) = step {
+                    write!(f, " step ")?;
+                    child(f, step, 10)?;
+                }
+                Ok(())
+            }
+            Self::Annotated { value, annotation } => write!(f, "{} = {}", annotation, value),
+        }
+    }
+}
+
+mod parsing {
     use super::*;
     use nom::{
         branch::alt,
@@ -44,7 +252,7 @@ mod parsing {
         combinator::{not, opt},
         error::make_error,
         multi::{fold_many0, many0, many1, separated_list0},
-        sequence::{delimited, pair, tuple},
+        sequence::{delimited, pair, preceded, terminated, tuple},
     };
 
     fn whitespace(input: &str) -> IResult<&str, ()> {
@@ -87,35 +295,56 @@ mod parsing {
             }
         });
 
-        let mut result = 0f64;
-        if let Some(part) = integer_part {
-            result += part
-                .parse::<f64>()
-                .map_err(|_| problem = true)
-                .unwrap_or_default();
+        let mut text = String::new();
+        if negative {
+            text.push('-');
         }
+        text.push_str(integer_part.as_deref().unwrap_or("0"));
         if let Some((_dot, part)) = fractional_part {
-            result += format!(".{}", part)
-                .parse::<f64>()
-                .map_err(|_| problem = true)
-                .unwrap_or_default();
+            text.push('.');
+            text.push_str(&part);
         }
         if let Some(power) = exponent {
-            result *= 10f64.powi(power);
+            text.push_str(&format!("e{}", power));
         }
-        if negative {
-            result = -result;
+        let value = match Number::parse_decimal(&text) {
+            Some(value) if !problem => value,
+            _ => f64::NAN.into(),
+        };
+        literal_suffix(input, value)
+    }
+
+    fn identifier_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    /// Handles `4i` for imaginary numbers and `30deg` for angles in degrees.
+    fn literal_suffix(input: &str, value: Number) -> IResult<&str, Expression> {
+        let suffix = |name| {
+            pair::<_, _, _, nom::error::Error<&str>, _, _>(
+                tag(name),
+                not(take_while1(identifier_char)),
+            )
+        };
+        if let Ok((input, _)) = suffix("i")(input) {
+            return Ok((input, Expression::ImaginaryLiteral(value)));
         }
-        if problem {
-            Ok((input, Expression::NumericLiteral(NAN)))
-        } else {
-            Ok((input, Expression::NumericLiteral(result)))
+        let literal = Expression::NumericLiteral(value);
+        if let Ok((input, _)) = suffix("deg")(input) {
+            let radians_per_degree = (std::f64::consts::PI / 180.0).into();
+            let expr = Expression::BinaryExpr(
+                Box::new(literal),
+                BinaryOp::Mul,
+                Box::new(Expression::NumericLiteral(radians_per_degree)),
+            );
+            return Ok((input, expr));
         }
+        Ok((input, literal))
     }
 
     fn identifier(input: &str) -> IResult<&str, String> {
         let (input, _) = not(collect_digits)(input)?;
-        let (input, value) = take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)?;
+        let (input, value) = take_while1(identifier_char)(input)?;
         Ok((input, value.to_owned()))
     }
 
@@ -129,8 +358,21 @@ mod parsing {
         let (input, name) = identifier(input)?;
         let (input, value) =
             opt(tuple((whitespace, char(':'), whitespace, parse_expression)))(input)?;
+        let value = value.map(|v| v.3);
+        // `name: Annotation = value`
+        let (input, annotated) = match value {
+            Some(..) => opt(preceded(char('='), parse_expression))(input)?,
+            None => (input, None),
+        };
+        let value = match (value, annotated) {
+            (Some(annotation), Some(value)) => Some(Expression::Annotated {
+                value: Box::new(value),
+                annotation: Box::new(annotation),
+            }),
+            (value, _) => value,
+        };
         let (input, _) = whitespace(input)?;
-        Ok((input, (name, value.map(|v| v.3))))
+        Ok((input, (name, value)))
     }
 
     fn entity_builder(input: &str) -> IResult<&str, Expression> {
@@ -164,6 +406,14 @@ mod parsing {
         Ok((input, expr))
     }
 
+    fn list_items(input: &str) -> IResult<&str, Expression> {
+        let (input, items) = separated_list0(char(','), parse_expression)(input)?;
+        // Trailing comma.
+        let (input, _) = opt(char(','))(input)?;
+        let (input, _) = whitespace(input)?;
+        Ok((input, Expression::ListLiteral(items)))
+    }
+
     /// This should always be called with delimited(whitespace, this, whitespace) because it is a
     /// consistent and efficent position to handle that.
     fn expr_priority50(input: &str) -> IResult<&str, Expression> {
@@ -173,6 +423,7 @@ mod parsing {
             delimited(char('"'), string_content, char('"')),
             delimited(char('('), parse_expression, char(')')),
             delimited(char('{'), entity_builder, char('}')),
+            delimited(pair(char('['), whitespace), list_items, char(']')),
         ))(input)
     }
 
@@ -197,17 +448,25 @@ mod parsing {
 
     fn expr_priority40(input: &str) -> IResult<&str, Expression> {
         let (input, term) = delimited(whitespace, expr_priority50, whitespace)(input)?;
-        if let Ok((input, arguments)) = delimited(char('('), fn_args, char(')'))(input) {
-            Ok((
-                input,
-                Expression::ApplyFunction {
+        let (input, term) =
+            if let Ok((input, arguments)) = delimited(char('('), fn_args, char(')'))(input) {
+                let (input, _) = whitespace(input)?;
+                let call = Expression::ApplyFunction {
                     function: Box::new(term),
                     arguments,
-                },
-            ))
-        } else {
-            Ok((input, term))
-        }
+                };
+                (input, call)
+            } else {
+                (input, term)
+            };
+        fold_many0(
+            terminated(
+                delimited(char('['), parse_expression, char(']')),
+                whitespace,
+            ),
+            term,
+            |lhs, index| Expression::BinaryExpr(Box::new(lhs), BinaryOp::Index, Box::new(index)),
+        )(input)
     }
 
     fn expr_priority30(input: &str) -> IResult<&str, Expression> {
@@ -245,10 +504,22 @@ mod parsing {
         )(input)
     }
 
-    fn expr_priority10(input: &str) -> IResult<&str, Expression> {
+    /// "∠" operator.
+    fn expr_priority15(input: &str) -> IResult<&str, Expression> {
         let (input, first_term) = expr_priority20(input)?;
         fold_many0(
-            pair(one_of("+-"), expr_priority20),
+            pair(char('∠'), expr_priority20),
+            first_term,
+            |lhs, (_, rhs): (char, Expression)| {
+                Expression::BinaryExpr(Box::new(lhs), BinaryOp::Polar, Box::new(rhs))
+            },
+        )(input)
+    }
+
+    fn expr_priority10(input: &str) -> IResult<&str, Expression> {
+        let (input, first_term) = expr_priority15(input)?;
+        fold_many0(
+            pair(one_of("+-"), expr_priority15),
             first_term,
             |lhs, (op, rhs): (char, Expression)| {
                 let op = match op {
@@ -261,11 +532,31 @@ mod parsing {
         )(input)
     }
 
+    /// ".." operator with an optional step.
+    fn expr_priority7(input: &str) -> IResult<&str, Expression> {
+        let (input, start) = expr_priority10(input)?;
+        let (input, rest) = opt(pair(
+            preceded(tag(".."), expr_priority10),
+            opt(preceded(tag("step"), expr_priority10)),
+        ))(input)?;
+        Ok(match rest {
+            Some((end, step)) => (
+                input,
+                Expression::Range {
+                    start: Box::new(start),
+                    end: Box::new(end),
+                    step: step.map(Box::new),
+                },
+            ),
+            None => (input, start),
+        })
+    }
+
     /// "is" and "in" operator.
     fn expr_priority5(input: &str) -> IResult<&str, Expression> {
-        let (input, first_term) = expr_priority10(input)?;
+        let (input, first_term) = expr_priority7(input)?;
         fold_many0(
-            pair(alt((tag("is"), tag("in"))), expr_priority10),
+            pair(alt((tag("is"), tag("in"))), expr_priority7),
             first_term,
             |lhs, (op, rhs): (_, Expression)| {
                 let op = match op {
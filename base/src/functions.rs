@@ -0,0 +1,289 @@
+//! Functions which are always available in expressions, like `sqrt(2)`.
+
+use crate::{
+    data::{Data, ValueData},
+    matrix::count,
+    prelude::*,
+};
+
+impl Instance {
+    pub fn apply_function(&self, name: &str, arguments: Vec<Data>) -> Result<Data, ()> {
+        if let Some(result) = apply_matrix_function(name, &arguments) {
+            return result;
+        }
+        // Functions of one scalar are applied to each component of a list or matrix.
+        if let [Data::Value(ValueData::Matrix(matrix))] = &arguments[..] {
+            let result =
+                matrix.map(
+                    |x| match self.apply_function(name, vec![x.clone().into()])? {
+                        Data::Value(ValueData::Scalar(scalar)) => Ok(scalar),
+                        _ => Err(()),
+                    },
+                )?;
+            return Ok(result.into());
+        }
+        let scalars = arguments
+            .into_iter()
+            .map(|argument| match argument {
+                Data::Value(ValueData::Scalar(scalar)) => Ok(scalar),
+                _ => Err(()),
+            })
+            .collect::<Result<Vec<_>, ()>>()?;
+        let result = match (name, &scalars[..]) {
+            ("sqrt", [x]) => x.pow_exponent(Exponent::new(1, 2))?,
+            ("abs", [x]) => x.abs(),
+            ("exp", [x]) => x.apply(f64::exp, |bounds| Ok(bounds.exp()))?,
+            ("ln", [x]) => x.apply(f64::ln, Interval::ln)?,
+            ("sin", [x]) => self
+                .angle_argument(x)?
+                .apply(f64::sin, |bounds| Ok(bounds.sin()))?,
+            ("cos", [x]) => self
+                .angle_argument(x)?
+                .apply(f64::cos, |bounds| Ok(bounds.cos()))?,
+            ("tan", [x]) => self
+                .angle_argument(x)?
+                .apply(f64::tan, |bounds| Ok(bounds.tan()))?,
+            ("interval", [lower, upper]) => Scalar::interval(lower, upper)?,
+            ("re", [z]) => z.real_part(),
+            ("im", [z]) => z.imaginary_as_real(),
+            ("arg", [z]) => z.argument(),
+            ("conj", [z]) => z.conjugate(),
+            ("polar", [z]) | ("rectangular", [z]) => {
+                let mut z = z.clone();
+                z.set_polar(name == "polar");
+                z
+            }
+            ("drop_kind", [x]) => {
+                let mut x = x.clone();
+                x.set_kind(None);
+                x
+            }
+            _ => return Err(()),
+        };
+        Ok(result.into())
+    }
+}
+
+fn apply_matrix_function(name: &str, arguments: &[Data]) -> Option<Result<Data, ()>> {
+    let matrices = arguments
+        .iter()
+        .map(|argument| match argument {
+            Data::Value(ValueData::Matrix(matrix)) => Some(matrix),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(match (name, &matrices[..]) {
+        ("dot", [a, b]) => a.dot(b).map(Into::into),
+        ("cross", [a, b]) => a.cross(b).map(Into::into),
+        ("norm", [v]) => v.norm().map(Into::into),
+        ("transpose", [m]) => Ok(m.transpose().into()),
+        ("sum", [list]) => list.sum().map(Into::into),
+        ("mean", [list]) => list.mean().map(Into::into),
+        ("stddev", [list]) => list.stddev().map(Into::into),
+        ("min", [list]) => list.min().map(Into::into),
+        ("max", [list]) => list.max().map(Into::into),
+        ("len", [list]) => Ok(count(list.len()).into()),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::{Data, ValueData},
+        expression::parse_expression,
+        test_util::{evaluate, make_instance},
+    };
+
+    #[test]
+    fn builtin_functions() {
+        let instance = make_instance();
+        assert_eq!(evaluate(&instance, "sqrt(16 * Meters^2)").unwrap(), "4m");
+        assert_eq!(evaluate(&instance, "abs(-3 * Meters)").unwrap(), "3m");
+        assert_eq!(evaluate(&instance, "exp(0)").unwrap(), "1");
+        assert!(evaluate(&instance, "exp(1 * Meter)").is_err());
+        assert!(evaluate(&instance, "nonsense(1)").is_err());
+    }
+
+    #[test]
+    fn intervals() {
+        let instance = make_instance();
+        assert_eq!(
+            evaluate(
+                &instance,
+                "interval(1, 2) * Meters + interval(1, 3) * Meters"
+            )
+            .unwrap(),
+            "[2, 5] m"
+        );
+        assert_eq!(
+            evaluate(&instance, "(interval(-1, 2) * Meters)^2").unwrap(),
+            "[0, 4] m^2"
+        );
+        assert_eq!(
+            evaluate(&instance, "sqrt(interval(4, 9) * Meters^2) in Millimeters").unwrap(),
+            // Millimeters are an inexact number of meters, so the bounds are widened.
+            "[1999.9999999999998, 3000.0000000000005] mm"
+        );
+        assert_eq!(
+            evaluate(&instance, "(0 - interval(1, 2)) / interval(2, 4)").unwrap(),
+            "[-1, -0.25]"
+        );
+        // Neither 0.1 nor 0.3 is a float, so the bounds have to be on either side of them.
+        let bounds = |expression: &str| {
+            let (_, expression) = parse_expression(expression).unwrap();
+            match instance.resolve_expression(&expression, Default::default()) {
+                Ok(Data::Value(ValueData::Scalar(scalar))) => scalar.bounds(),
+                _ => panic!("not a scalar"),
+            }
+        };
+        let tenth = bounds("interval(0.1, 0.1)");
+        assert!(tenth.lower < 0.1 && 0.1 <= tenth.upper);
+        let sum = bounds("interval(0.1, 0.1) + 0.2");
+        assert!(sum.lower < 0.3 && 0.3 < sum.upper);
+        assert!(sum.upper - sum.lower < 1e-15);
+        assert!(evaluate(&instance, "interval(2, 1)").is_err());
+        assert!(evaluate(&instance, "interval(1 * Meter, 2)").is_err());
+    }
+
+    #[test]
+    fn vectors_and_matrices() {
+        let instance = make_instance();
+        assert_eq!(
+            evaluate(&instance, "[3, 4, 0] * Meters").unwrap(),
+            "[3m, 4m, 0m]"
+        );
+        assert_eq!(
+            evaluate(&instance, "([3, 4, 0] * Meters)[1]").unwrap(),
+            "4m"
+        );
+        assert_eq!(
+            evaluate(&instance, "norm([3, 4, 0] * Meters)").unwrap(),
+            "5m"
+        );
+        assert_eq!(
+            evaluate(&instance, "dot([1, 2] * Meters, [3, 4])").unwrap(),
+            "11m"
+        );
+        assert_eq!(
+            evaluate(&instance, "cross([1, 0, 0] * Meters, [0, 1, 0] * Meters)").unwrap(),
+            "[0m^2, 0m^2, 1m^2]"
+        );
+        assert!(evaluate(&instance, "[[1, 2], [3, 4]] * [1, 1 * Meters]").is_err());
+        assert_eq!(
+            evaluate(&instance, "[[2 * Meters, 0 * Meters], [0, 1]] * [1, 1]").unwrap(),
+            "[2m, 1]"
+        );
+        assert_eq!(
+            evaluate(&instance, "transpose([[1, 2], [3, 4]])[0]").unwrap(),
+            "[1, 3]"
+        );
+        assert!(evaluate(&instance, "[1, 2] * Meters - [1, 1 * Meters]").is_err());
+        assert!(evaluate(&instance, "[1, 2][2]").is_err());
+        assert!(evaluate(&instance, "[[1, 2], [3]]").is_err());
+    }
+
+    #[test]
+    fn lists() {
+        let instance = make_instance();
+        let list = "[1 * Meters, 20 * Centimeters, 3 * Meters]";
+        assert_eq!(evaluate(&instance, list).unwrap(), "[1m, 20cm, 3m]");
+        assert_eq!(evaluate(&instance, &format!("len({})", list)).unwrap(), "3");
+        assert_eq!(
+            evaluate(&instance, &format!("sum({})", list)).unwrap(),
+            "4.2m"
+        );
+        assert_eq!(
+            evaluate(&instance, "mean([1, 2, 3, 4] * Meters)").unwrap(),
+            "2.5m"
+        );
+        assert_eq!(
+            evaluate(&instance, &format!("min({})", list)).unwrap(),
+            "20cm"
+        );
+        assert_eq!(
+            evaluate(&instance, &format!("max({})", list)).unwrap(),
+            "3m"
+        );
+        assert_eq!(
+            evaluate(&instance, "stddev([2, 4, 4, 4, 5, 5, 7, 9] * Meters)").unwrap(),
+            "2.138089935299395m"
+        );
+        assert_eq!(
+            evaluate(&instance, "sum([interval(1, 2), interval(3, 4)])").unwrap(),
+            "[4, 6]"
+        );
+        assert!(evaluate(&instance, "sum([1 * Meters, 1])").is_err());
+        assert!(evaluate(&instance, "max([1 * Meters, 1])").is_err());
+        assert!(evaluate(&instance, "mean([])").is_err());
+    }
+
+    #[test]
+    fn broadcasting() {
+        let instance = make_instance();
+        assert_eq!(
+            evaluate(&instance, "[1, 2, 3] * Meters + 1 * Meters").unwrap(),
+            "[2m, 3m, 4m]"
+        );
+        assert_eq!(evaluate(&instance, "1 - [1, 2]").unwrap(), "[0, -1]");
+        assert_eq!(evaluate(&instance, "[1, 2] * [3, 4]").unwrap(), "[3, 8]");
+        assert_eq!(evaluate(&instance, "[1, 2]^2").unwrap(), "[1, 4]");
+        assert_eq!(evaluate(&instance, "sqrt([4, 9])").unwrap(), "[2, 3]");
+        assert!(evaluate(&instance, "[1, 2] + [1, 2, 3]").is_err());
+    }
+
+    #[test]
+    fn complex_numbers() {
+        let instance = make_instance();
+        assert_eq!(evaluate(&instance, "3 + 4i").unwrap(), "3 + 4i");
+        assert_eq!(
+            evaluate(&instance, "(3 + 4i) * (1 - 2i)").unwrap(),
+            "11 - 2i"
+        );
+        assert_eq!(evaluate(&instance, "(3 + 4i) * (3 - 4i)").unwrap(), "25");
+        assert_eq!(evaluate(&instance, "(1 + 1i)^2").unwrap(), "2i");
+        assert_eq!(
+            evaluate(&instance, "(10 + 5i) / (1 + 2i)").unwrap(),
+            "4 - 3i"
+        );
+        assert_eq!(
+            evaluate(&instance, "(3 + 4i) * Meters + 1 * Meters").unwrap(),
+            "(4 + 4i)m"
+        );
+        assert_eq!(evaluate(&instance, "abs((3 + 4i) * Meters)").unwrap(), "5m");
+        assert_eq!(
+            evaluate(&instance, "re(3 - 4i) + im(3 - 4i)").unwrap(),
+            "-1"
+        );
+        assert_eq!(evaluate(&instance, "conj(3 + 4i)").unwrap(), "3 - 4i");
+        assert_eq!(
+            evaluate(&instance, "arg(0 - 2)").unwrap(),
+            evaluate(&instance, "arg(1i) * 2").unwrap()
+        );
+        assert_eq!(
+            evaluate(&instance, "polar(3 + 4i)").unwrap(),
+            "5 ∠ 53.13010235415598deg"
+        );
+        assert_eq!(
+            evaluate(&instance, "(2 * Meters ∠ 90deg) / 2 in Millimeters").unwrap(),
+            "1000mm ∠ 90deg"
+        );
+        assert!(evaluate(&instance, "1 * Meters ∠ 1 * Meters").is_err());
+        assert!(evaluate(&instance, "2^(1i)").is_err());
+    }
+
+    #[test]
+    fn roots_of_negative_numbers() {
+        let instance = make_instance();
+        assert_eq!(evaluate(&instance, "sqrt(0 - 4)").unwrap(), "2i");
+        assert_eq!(evaluate(&instance, "(0 - 4)^0.5").unwrap(), "2i");
+        assert_eq!(evaluate(&instance, "(0 - 4)^1.5").unwrap(), "-8i");
+        assert_eq!(evaluate(&instance, "(0 - 4)^2").unwrap(), "16");
+        assert_eq!(
+            evaluate(&instance, "sqrt((0 - 9) * Meters^2)").unwrap(),
+            "(3i)m"
+        );
+        assert_eq!(evaluate(&instance, "abs((0 - 8)^(1/3))").unwrap(), "2");
+        assert_eq!(evaluate(&instance, "abs((0 - 1)^3.14159)").unwrap(), "1");
+    }
+}
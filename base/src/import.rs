@@ -0,0 +1,1319 @@
+//! Reads units from standard unit databases: UCUM's `ucum-essence.xml` and QUDT's Turtle unit
+//! files. Units that can't be represented as a plain multiple of base units (like degrees
+//! Celsius or decibels) are reported instead of being silently dropped.
+
+use crate::{data::MetaData, prelude::*};
+use num_traits::{One, Zero};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    Ucum,
+    Qudt,
+}
+
+/// Says which unit class one of the base dimensions of a unit database corresponds to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BaseDimension {
+    /// How the database refers to the dimension, e.g. "L" for length.
+    pub symbol: String,
+    /// The unit class to use for the dimension. It is created if it does not exist yet.
+    pub unit_class: String,
+    /// Multiply a value in the database's base unit for this dimension by this number to get the
+    /// value in the instance's base unit.
+    pub scale: f64,
+    /// Whether the unit class is created as an angle class, see UnitClass::angle.
+    pub angle: bool,
+}
+
+impl BaseDimension {
+    pub fn new(symbol: &str, unit_class: &str, scale: f64) -> Self {
+        Self {
+            symbol: symbol.to_owned(),
+            unit_class: unit_class.to_owned(),
+            scale,
+            angle: false,
+        }
+    }
+
+    /// Marks the dimension as plane angle.
+    pub fn as_angle(mut self) -> Self {
+        self.angle = true;
+        self
+    }
+
+    /// UCUM's base units are the meter, second, gram, radian, kelvin, coulomb and candela.
+    pub fn ucum_defaults() -> Vec<Self> {
+        vec![
+            Self::new("L", "Length", 1.0),
+            Self::new("T", "Time", 1.0),
+            Self::new("M", "Mass", 1.0),
+            Self::new("A", "Angle", 1.0).as_angle(),
+            Self::new("C", "Temperature", 1.0),
+            Self::new("Q", "Charge", 1.0),
+            Self::new("F", "LuminousIntensity", 1.0),
+        ]
+    }
+
+    /// QUDT's conversion multipliers are relative to the SI base units. Mass is scaled because
+    /// the gram rather than the kilogram is used as the base unit of mass.
+    pub fn qudt_defaults() -> Vec<Self> {
+        vec![
+            Self::new("A", "AmountOfSubstance", 1.0),
+            Self::new("E", "ElectricCurrent", 1.0),
+            Self::new("L", "Length", 1.0),
+            Self::new("I", "LuminousIntensity", 1.0),
+            Self::new("M", "Mass", 1000.0),
+            Self::new("H", "Temperature", 1.0),
+            Self::new("T", "Time", 1.0),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SkipReason {
+    /// The unit needs an offset as well as a ratio, like degrees Celsius.
+    Offset,
+    /// The unit is on a logarithmic scale, like decibels or pH.
+    Logarithmic,
+    /// Any other unit that is not a plain multiple of base units, such as UCUM's arbitrary units.
+    NotRatio,
+    /// The unit uses a base dimension that was not given a unit class.
+    UnknownDimension(String),
+    /// The definition of the unit could not be understood.
+    InvalidDefinition,
+    /// Every name the unit could be given is already in use.
+    NameTaken,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offset => write!(f, "conversion requires an offset"),
+            Self::Logarithmic => write!(f, "unit is logarithmic"),
+            Self::NotRatio => write!(f, "unit is not a multiple of base units"),
+            Self::UnknownDimension(symbol) => write!(f, "no unit class for dimension {}", symbol),
+            Self::InvalidDefinition => write!(f, "definition could not be understood"),
+            Self::NameTaken => write!(f, "name is already in use"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// The name each imported unit was declared with.
+    pub imported: Vec<String>,
+    /// The name and reason for each unit that was not imported.
+    pub skipped: Vec<(String, SkipReason)>,
+}
+
+/// A unit read from a database, before it is added to an instance.
+#[derive(Clone, Debug)]
+struct Candidate {
+    /// Names to try declaring the unit with, in order of preference.
+    names: Vec<String>,
+    symbol: String,
+    definition: Result<Definition, SkipReason>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Definition {
+    /// The value of the unit in the database's base units.
+    factor: f64,
+    /// The power of each of the database's base dimensions.
+    dimensions: BTreeMap<String, Exponent>,
+    /// Whether metric prefixes should be generated for the unit.
+    metric: bool,
+}
+
+impl Definition {
+    fn dimensionless(factor: f64) -> Self {
+        Self {
+            factor,
+            dimensions: BTreeMap::new(),
+            metric: false,
+        }
+    }
+
+    fn mul(mut self, other: &Self) -> Self {
+        self.factor *= other.factor;
+        for (symbol, power) in &other.dimensions {
+            *self
+                .dimensions
+                .entry(symbol.clone())
+                .or_insert_with(Zero::zero) += power;
+        }
+        self.dimensions.retain(|_, power| !power.is_zero());
+        self
+    }
+
+    fn pow(mut self, exp: Exponent) -> Self {
+        self.factor = pow_f64(self.factor, exp);
+        for power in self.dimensions.values_mut() {
+            *power *= exp;
+        }
+        self.dimensions.retain(|_, power| !power.is_zero());
+        self
+    }
+}
+
+/// Turns a description like "degree Celsius" into an identifier like "DegreeCelsius". Returns
+/// None if nothing usable is left.
+fn pascal_case(text: &str) -> Option<String> {
+    let mut result = String::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            result.extend(first.to_uppercase());
+            result.extend(chars);
+        }
+    }
+    match result.chars().next() {
+        Some(first) if !first.is_numeric() => Some(result),
+        _ => None,
+    }
+}
+
+/// The names to try for a unit: its description, then its description followed by its code in
+/// the database in case several units share a description (like the many kinds of inch.)
+fn candidate_names(description: &str, code: &str) -> Vec<String> {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    match (pascal_case(description), pascal_case(&code)) {
+        (Some(name), _) if code.is_empty() => vec![name],
+        (Some(name), _) => vec![name.clone(), format!("{}_{}", name, code)],
+        (None, Some(code)) => vec![code],
+        (None, None) => Vec::new(),
+    }
+}
+
+impl Instance {
+    /// Imports every unit from the contents of a UCUM `ucum-essence.xml` file. Returns Err(()) if
+    /// the file could not be read at all, in which case nothing is changed.
+    pub fn import_ucum(
+        &mut self,
+        xml: &str,
+        dimensions: &[BaseDimension],
+    ) -> Result<ImportReport, ()> {
+        let candidates = ucum::read(xml)?;
+        self.add_candidates(candidates, dimensions)
+    }
+
+    /// Imports every unit from the contents of a QUDT Turtle file, such as `VOCAB_QUDT-UNITS-ALL`.
+    /// Returns Err(()) if the file could not be read at all, in which case nothing is changed.
+    pub fn import_qudt(
+        &mut self,
+        turtle: &str,
+        dimensions: &[BaseDimension],
+    ) -> Result<ImportReport, ()> {
+        let candidates = qudt::read(turtle)?;
+        self.add_candidates(candidates, dimensions)
+    }
+
+    /// Finds or creates the unit class for each base dimension.
+    fn dimension_classes(
+        &mut self,
+        dimensions: &[BaseDimension],
+    ) -> Result<HashMap<String, (UnitClassId, f64)>, ()> {
+        let mut classes = HashMap::new();
+        for dimension in dimensions {
+            let id = match self.lookup_item(&dimension.unit_class).as_meta {
+                Some(MetaData::UnitClass(class)) => class.as_single().ok_or(())?,
+                Some(..) => return Err(()),
+                None => self.add_unit_class(UnitClass {
+                    names: vec![dimension.unit_class.clone()],
+                    angle: dimension.angle,
+                })?,
+            };
+            classes.insert(dimension.symbol.clone(), (id, dimension.scale));
+        }
+        Ok(classes)
+    }
+
+    fn add_candidates(
+        &mut self,
+        candidates: Vec<Candidate>,
+        dimensions: &[BaseDimension],
+    ) -> Result<ImportReport, ()> {
+        let classes = self.transaction(|instance| instance.dimension_classes(dimensions))?;
+        let mut report = ImportReport::default();
+        'candidates: for candidate in candidates {
+            let display_name = candidate
+                .names
+                .first()
+                .cloned()
+                .unwrap_or_else(|| candidate.symbol.clone());
+            let definition = match candidate.definition {
+                Ok(definition) => definition,
+                Err(reason) => {
+                    report.skipped.push((display_name, reason));
+                    continue;
+                }
+            };
+            let mut class = CompositeUnitClass::identity();
+            let mut base_ratio = definition.factor;
+            for (symbol, power) in &definition.dimensions {
+                if let Some((id, scale)) = classes.get(symbol) {
+                    let mut factor = CompositeUnitClass::from(*id);
+                    let product = factor.pow(*power).and_then(|_| class.clone() * factor);
+                    if let Ok(product) = product {
+                        class = product;
+                    } else {
+                        report
+                            .skipped
+                            .push((display_name, SkipReason::InvalidDefinition));
+                        continue 'candidates;
+                    }
+                    base_ratio *= pow_f64(*scale, *power);
+                } else {
+                    let reason = SkipReason::UnknownDimension(symbol.clone());
+                    report.skipped.push((display_name, reason));
+                    continue 'candidates;
+                }
+            }
+            let prefix_type = if definition.metric {
+                UnitPrefixType::Metric
+            } else {
+                UnitPrefixType::None
+            };
+            for name in candidate.names {
+                let unit = Unit {
+                    names: vec![name.clone()],
+                    class: class.clone(),
+                    symbol: candidate.symbol.clone(),
+                    base_ratio: base_ratio.into(),
+                    prefix_origin: None,
+                    kind: None,
+                };
+                if self.add_unit(unit, prefix_type).is_ok() {
+                    report.imported.push(name);
+                    continue 'candidates;
+                }
+            }
+            report.skipped.push((display_name, SkipReason::NameTaken));
+        }
+        Ok(report)
+    }
+}
+
+mod ucum {
+    use super::*;
+
+    struct Atom {
+        names: Vec<String>,
+        symbol: String,
+        metric: bool,
+        kind: AtomKind,
+    }
+
+    enum AtomKind {
+        Base { dimension: String },
+        Derived { value: f64, unit: String },
+        Unsupported(SkipReason),
+    }
+
+    /// Maps the functions UCUM uses to define special units onto the reason they can't be
+    /// imported.
+    fn special_reason(function: &str) -> SkipReason {
+        match function {
+            "Cel" | "degF" | "degRe" => SkipReason::Offset,
+            "ln" | "lg" | "ld" | "lgTimes2" | "pH" => SkipReason::Logarithmic,
+            _ => SkipReason::NotRatio,
+        }
+    }
+
+    fn text_of(node: roxmltree::Node) -> String {
+        node.descendants()
+            .filter(|child| child.is_text())
+            .filter_map(|child| child.text())
+            .collect::<String>()
+            .trim()
+            .to_owned()
+    }
+
+    fn child<'a, 'input>(
+        node: roxmltree::Node<'a, 'input>,
+        name: &str,
+    ) -> Option<roxmltree::Node<'a, 'input>> {
+        node.children()
+            .find(|child| child.tag_name().name() == name)
+    }
+
+    fn read_atom(node: roxmltree::Node) -> Option<(String, Atom)> {
+        let code = node.attribute("Code")?.to_owned();
+        let names = node
+            .children()
+            .filter(|child| child.tag_name().name() == "name")
+            .map(text_of)
+            .collect();
+        let symbol = child(node, "printSymbol")
+            .map(text_of)
+            .filter(|symbol| !symbol.is_empty())
+            .unwrap_or_else(|| code.clone());
+        // Base units have no isMetric attribute but can all be prefixed.
+        let metric =
+            node.attribute("isMetric") == Some("yes") || node.tag_name().name() == "base-unit";
+        let value = child(node, "value");
+        let kind = if let Some(dimension) = node.attribute("dim") {
+            AtomKind::Base {
+                dimension: dimension.to_owned(),
+            }
+        } else if node.attribute("isArbitrary") == Some("yes") {
+            AtomKind::Unsupported(SkipReason::NotRatio)
+        } else if node.attribute("isSpecial") == Some("yes") {
+            let function = value
+                .and_then(|value| child(value, "function"))
+                .and_then(|function| function.attribute("name"))
+                .unwrap_or_default();
+            AtomKind::Unsupported(special_reason(function))
+        } else {
+            let value_node = value?;
+            let number = value_node
+                .attribute("value")
+                .and_then(|value| value.trim().parse().ok());
+            match (number, value_node.attribute("Unit")) {
+                (Some(value), Some(unit)) => AtomKind::Derived {
+                    value,
+                    unit: unit.to_owned(),
+                },
+                _ => AtomKind::Unsupported(SkipReason::InvalidDefinition),
+            }
+        };
+        Some((
+            code,
+            Atom {
+                names,
+                symbol,
+                metric,
+                kind,
+            },
+        ))
+    }
+
+    struct Database {
+        /// Sorted longest first so that "da" is tried before "d".
+        prefixes: Vec<(String, f64)>,
+        atoms: HashMap<String, Atom>,
+        /// Atoms in the order they appear in the file.
+        order: Vec<String>,
+        resolved: HashMap<String, Result<Definition, SkipReason>>,
+    }
+
+    impl Database {
+        fn resolve_atom(&mut self, code: &str, depth: usize) -> Result<Definition, SkipReason> {
+            if let Some(result) = self.resolved.get(code) {
+                return result.clone();
+            }
+            // Definitions in UCUM are never this deeply nested, so this must be a cycle.
+            if depth > 64 {
+                return Err(SkipReason::InvalidDefinition);
+            }
+            let atom = self.atoms.get(code).ok_or(SkipReason::InvalidDefinition)?;
+            let metric = atom.metric;
+            let result = match &atom.kind {
+                AtomKind::Base { dimension } => {
+                    let mut definition = Definition::dimensionless(1.0);
+                    definition
+                        .dimensions
+                        .insert(dimension.clone(), Exponent::one());
+                    Ok(definition)
+                }
+                AtomKind::Derived { value, unit } => {
+                    let (value, unit) = (*value, unit.clone());
+                    self.evaluate(&unit, depth + 1)
+                        .map(|definition| definition.mul(&Definition::dimensionless(value)))
+                }
+                AtomKind::Unsupported(reason) => Err(reason.clone()),
+            };
+            let result = result.map(|definition| Definition {
+                metric,
+                ..definition
+            });
+            self.resolved.insert(code.to_owned(), result.clone());
+            result
+        }
+
+        /// Resolves something like "km" which may be an atom or a prefixed atom.
+        fn resolve_simple_unit(
+            &mut self,
+            text: &str,
+            depth: usize,
+        ) -> Result<Definition, SkipReason> {
+            if self.atoms.contains_key(text) {
+                return self.resolve_atom(text, depth);
+            }
+            for index in 0..self.prefixes.len() {
+                let (prefix, factor) = self.prefixes[index].clone();
+                if let Some(rest) = text.strip_prefix(&prefix[..]) {
+                    if self.atoms.get(rest).map(|atom| atom.metric) == Some(true) {
+                        let definition = self.resolve_atom(rest, depth)?;
+                        return Ok(definition.mul(&Definition::dimensionless(factor)));
+                    }
+                }
+            }
+            Err(SkipReason::InvalidDefinition)
+        }
+
+        /// Evaluates a whole unit term like "kg.m/s2".
+        fn evaluate(&mut self, text: &str, depth: usize) -> Result<Definition, SkipReason> {
+            let (rest, definition) = self.term(text, depth)?;
+            if rest.is_empty() {
+                Ok(definition)
+            } else {
+                Err(SkipReason::InvalidDefinition)
+            }
+        }
+
+        fn term<'t>(
+            &mut self,
+            mut text: &'t str,
+            depth: usize,
+        ) -> Result<(&'t str, Definition), SkipReason> {
+            let mut definition = if let Some(rest) = text.strip_prefix('/') {
+                let (rest, divisor) = self.component(rest, depth)?;
+                text = rest;
+                divisor.pow(-Exponent::one())
+            } else {
+                let (rest, first) = self.component(text, depth)?;
+                text = rest;
+                first
+            };
+            loop {
+                if let Some(rest) = text.strip_prefix('.') {
+                    let (rest, factor) = self.component(rest, depth)?;
+                    definition = definition.mul(&factor);
+                    text = rest;
+                } else if let Some(rest) = text.strip_prefix('/') {
+                    let (rest, divisor) = self.component(rest, depth)?;
+                    definition = definition.mul(&divisor.pow(-Exponent::one()));
+                    text = rest;
+                } else {
+                    return Ok((text, definition));
+                }
+            }
+        }
+
+        fn component<'t>(
+            &mut self,
+            text: &'t str,
+            depth: usize,
+        ) -> Result<(&'t str, Definition), SkipReason> {
+            if let Some(rest) = text.strip_prefix('(') {
+                let (rest, definition) = self.term(rest, depth)?;
+                let rest = rest
+                    .strip_prefix(')')
+                    .ok_or(SkipReason::InvalidDefinition)?;
+                return Ok((rest, definition));
+            }
+            if text.starts_with('{') {
+                // A lone annotation like {cells} counts as the number one.
+                return Ok((skip_annotation(text)?, Definition::dimensionless(1.0)));
+            }
+            let (token, rest) = split_simple_unit(text);
+            if token.is_empty() {
+                return Err(SkipReason::InvalidDefinition);
+            }
+            let rest = if rest.starts_with('{') {
+                skip_annotation(rest)?
+            } else {
+                rest
+            };
+            if token.chars().all(|c| c.is_ascii_digit()) {
+                let factor = token.parse().map_err(|_| SkipReason::InvalidDefinition)?;
+                return Ok((rest, Definition::dimensionless(factor)));
+            }
+            let (unit, exponent) = split_exponent(token);
+            let definition = self.resolve_simple_unit(unit, depth)?;
+            Ok((rest, definition.pow(exponent)))
+        }
+    }
+
+    fn skip_annotation(text: &str) -> Result<&str, SkipReason> {
+        let end = text.find('}').ok_or(SkipReason::InvalidDefinition)?;
+        Ok(&text[end + 1..])
+    }
+
+    /// Splits off a unit symbol with its exponent from the start of the text. Anything inside
+    /// square brackets is part of the symbol.
+    fn split_simple_unit(text: &str) -> (&str, &str) {
+        let mut in_brackets = false;
+        for (index, c) in text.char_indices() {
+            match c {
+                '[' => in_brackets = true,
+                ']' => in_brackets = false,
+                '.' | '/' | '(' | ')' | '{' if !in_brackets => return text.split_at(index),
+                _ => (),
+            }
+        }
+        (text, "")
+    }
+
+    /// Splits "m2" into "m" and 2, or "10*-3" into "10*" and -3.
+    fn split_exponent(token: &str) -> (&str, Exponent) {
+        let digits_start = token
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_ascii_digit())
+            .last()
+            .map(|(index, _)| index);
+        let digits_start = match digits_start {
+            Some(index) if index > 0 => index,
+            _ => return (token, Exponent::one()),
+        };
+        // The sign only counts if there is a symbol before it.
+        let sign_start = match token[..digits_start].strip_suffix(&['+', '-'][..]) {
+            Some(symbol) if !symbol.is_empty() => symbol.len(),
+            _ => digits_start,
+        };
+        match token[sign_start..].parse::<i64>() {
+            Ok(exponent) => (&token[..sign_start], exponent.into()),
+            Err(_) => (token, Exponent::one()),
+        }
+    }
+
+    pub(super) fn read(xml: &str) -> Result<Vec<Candidate>, ()> {
+        let document = roxmltree::Document::parse(xml).map_err(|_| ())?;
+        let mut database = Database {
+            prefixes: Vec::new(),
+            atoms: HashMap::new(),
+            order: Vec::new(),
+            resolved: HashMap::new(),
+        };
+        for node in document.root_element().children() {
+            match node.tag_name().name() {
+                "prefix" => {
+                    let code = node.attribute("Code");
+                    let value = child(node, "value")
+                        .and_then(|value| value.attribute("value"))
+                        .and_then(|value| value.trim().parse().ok());
+                    if let (Some(code), Some(value)) = (code, value) {
+                        database.prefixes.push((code.to_owned(), value));
+                    }
+                }
+                "base-unit" | "unit" => {
+                    if let Some((code, atom)) = read_atom(node) {
+                        database.order.push(code.clone());
+                        database.atoms.insert(code, atom);
+                    }
+                }
+                _ => (),
+            }
+        }
+        database
+            .prefixes
+            .sort_by_key(|(code, _)| std::cmp::Reverse(code.len()));
+        let order = std::mem::take(&mut database.order);
+        let mut candidates = Vec::new();
+        for code in order {
+            let definition = database.resolve_atom(&code, 0);
+            let atom = &database.atoms[&code];
+            let description = atom.names.first().cloned().unwrap_or_default();
+            candidates.push(Candidate {
+                names: candidate_names(&description, &code),
+                symbol: atom.symbol.clone(),
+                definition,
+            });
+        }
+        Ok(candidates)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn exponents() {
+            assert_eq!(split_exponent("m2"), ("m", Exponent::from(2)));
+            assert_eq!(split_exponent("s-1"), ("s", Exponent::from(-1)));
+            assert_eq!(split_exponent("10*-3"), ("10*", Exponent::from(-3)));
+            assert_eq!(split_exponent("10"), ("10", Exponent::from(1)));
+            assert_eq!(split_exponent("°2"), ("°", Exponent::from(2)));
+            assert_eq!(split_exponent("µ-1"), ("µ", Exponent::from(-1)));
+        }
+    }
+}
+
+mod qudt {
+    use super::*;
+    use turtle::Term;
+
+    /// The part of an IRI after its namespace, e.g. "conversionMultiplier" for
+    /// "qudt:conversionMultiplier" or "<http://qudt.org/schema/qudt/conversionMultiplier>".
+    fn local_name(iri: &str) -> &str {
+        iri.rsplit(['/', '#', ':']).next().unwrap_or(iri)
+    }
+
+    /// Parses a dimension vector like "A0E0L1I0M0H0T-2D0". QUDT writes fractional powers like
+    /// "L0dot5".
+    fn parse_dimension_vector(text: &str) -> Option<BTreeMap<String, Exponent>> {
+        let mut dimensions = BTreeMap::new();
+        let mut rest = text;
+        while let Some(symbol) = rest.chars().next() {
+            if !symbol.is_ascii_alphabetic() {
+                return None;
+            }
+            rest = &rest[1..];
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+                .unwrap_or(rest.len());
+            let mut number = rest[..end].to_owned();
+            rest = &rest[end..];
+            if let Some(fraction) = rest.strip_prefix("dot") {
+                let end = fraction
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(fraction.len());
+                number = format!("{}.{}", number, &fraction[..end]);
+                rest = &fraction[end..];
+            }
+            let power = exponent_from_f64(number.parse().ok()?).ok()?;
+            // D only marks whether the quantity is a ratio and is not a real dimension.
+            if !power.is_zero() && symbol != 'D' {
+                dimensions.insert(symbol.to_string(), power);
+            }
+        }
+        Some(dimensions)
+    }
+
+    fn literal(term: &Term) -> Option<&str> {
+        match term {
+            Term::Literal { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    fn number(term: &Term) -> Option<f64> {
+        literal(term).and_then(|value| value.trim().parse().ok())
+    }
+
+    /// Prefers an English label, then one without a language.
+    fn english_label(properties: &[(String, Term)]) -> Option<&str> {
+        let mut fallback = None;
+        for (predicate, term) in properties {
+            if local_name(predicate) != "label" {
+                continue;
+            }
+            if let Term::Literal { value, language } = term {
+                match language.as_deref() {
+                    Some("en") => return Some(value),
+                    Some(lang) if lang.starts_with("en-") => fallback = fallback.or(Some(value)),
+                    None => fallback = fallback.or(Some(value)),
+                    _ => (),
+                }
+            }
+        }
+        fallback.map(|value| &value[..])
+    }
+
+    fn read_unit(subject: &str, properties: &[(String, Term)]) -> Candidate {
+        let find = |name: &'static str| {
+            properties
+                .iter()
+                .filter(move |(predicate, _)| local_name(predicate) == name)
+                .map(|(_, term)| term)
+        };
+        let code = local_name(subject);
+        let names = candidate_names(english_label(properties).unwrap_or(code), code);
+        let symbol = find("symbol").find_map(literal).unwrap_or(code).to_owned();
+        let is_logarithmic = find("type").any(|term| match term {
+            Term::Iri(iri) => local_name(iri) == "LogarithmicUnit",
+            _ => false,
+        });
+        let offset = find("conversionOffset").find_map(number).unwrap_or(0.0);
+        let multiplier = find("conversionMultiplier").find_map(number);
+        let dimensions = find("hasDimensionVector").find_map(|term| match term {
+            Term::Iri(iri) => parse_dimension_vector(local_name(iri)),
+            _ => None,
+        });
+        let definition = if is_logarithmic {
+            Err(SkipReason::Logarithmic)
+        } else if offset != 0.0 {
+            Err(SkipReason::Offset)
+        } else if let (Some(factor), Some(dimensions)) = (multiplier, dimensions) {
+            Ok(Definition {
+                factor,
+                dimensions,
+                metric: false,
+            })
+        } else {
+            Err(SkipReason::InvalidDefinition)
+        };
+        Candidate {
+            names,
+            symbol,
+            definition,
+        }
+    }
+
+    pub(super) fn read(turtle: &str) -> Result<Vec<Candidate>, ()> {
+        let subjects = turtle::parse_document(turtle)?;
+        let is_unit = |properties: &[(String, Term)]| {
+            properties.iter().any(|(predicate, term)| match term {
+                Term::Iri(iri) => local_name(predicate) == "type" && local_name(iri) == "Unit",
+                _ => false,
+            })
+        };
+        Ok(subjects
+            .iter()
+            .filter(|(_, properties)| is_unit(properties))
+            .map(|(subject, properties)| read_unit(subject, properties))
+            .collect())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn dimension_vectors() {
+            let dimensions = parse_dimension_vector("A0E0L1I0M0H0T-2D0").unwrap();
+            assert_eq!(dimensions.get("L"), Some(&Exponent::from(1)));
+            assert_eq!(dimensions.get("T"), Some(&Exponent::from(-2)));
+            assert_eq!(dimensions.len(), 2);
+            let dimensions = parse_dimension_vector("A0E0L0dot5I0M0H0T0D0").unwrap();
+            assert_eq!(dimensions.get("L"), Some(&Exponent::new(1, 2)));
+        }
+    }
+}
+
+/// Just enough of a Turtle parser to read QUDT's vocabulary files. Prefixed names are kept as
+/// written rather than expanded since only the local part of each name is ever looked at.
+mod turtle {
+    use nom::{
+        branch::alt,
+        bytes::complete::{tag, tag_no_case, take_while, take_while1},
+        character::complete::{char, one_of},
+        combinator::{map, opt, recognize},
+        error::{make_error, ErrorKind},
+        multi::{many0, separated_list1},
+        sequence::{delimited, pair, preceded, tuple},
+        IResult,
+    };
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Term {
+        Iri(String),
+        Literal {
+            value: String,
+            language: Option<String>,
+        },
+        Blank(Vec<(String, Term)>),
+        Collection(Vec<Term>),
+    }
+
+    fn fail<T>(input: &str) -> IResult<&str, T> {
+        Err(nom::Err::Error(make_error(input, ErrorKind::Alt)))
+    }
+
+    fn whitespace(mut input: &str) -> IResult<&str, ()> {
+        loop {
+            let trimmed = input.trim_start();
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                input = comment.find('\n').map_or("", |end| &comment[end..]);
+            } else {
+                return Ok((trimmed, ()));
+            }
+        }
+    }
+
+    fn iri_ref(input: &str) -> IResult<&str, String> {
+        let (input, iri) = delimited(char('<'), take_while(|c| c != '>'), char('>'))(input)?;
+        Ok((input, iri.to_owned()))
+    }
+
+    fn is_name_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '%' || c == '\\'
+    }
+
+    /// Something like `qudt:Unit` or `:thing`. Names may not end with a period, so that the
+    /// period ending a statement is not mistaken for part of the name.
+    fn prefixed_name(input: &str) -> IResult<&str, String> {
+        let (_, name) = recognize(tuple((
+            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '.'),
+            char(':'),
+            take_while(|c: char| is_name_char(c) || c == ':'),
+        )))(input)?;
+        let trimmed = name.trim_end_matches('.');
+        Ok((&input[trimmed.len()..], trimmed.replace('\\', "")))
+    }
+
+    fn iri(input: &str) -> IResult<&str, String> {
+        alt((iri_ref, prefixed_name))(input)
+    }
+
+    fn blank_node_label(input: &str) -> IResult<&str, String> {
+        let (_, label) = recognize(pair(tag("_:"), take_while1(is_name_char)))(input)?;
+        let trimmed = label.trim_end_matches('.');
+        Ok((&input[trimmed.len()..], trimmed.to_owned()))
+    }
+
+    /// Reads the contents of a string up to the closing quote, handling escapes.
+    fn string_body<'i>(input: &'i str, quote: &str) -> IResult<&'i str, String> {
+        let mut value = String::new();
+        let mut chars = input.char_indices();
+        while let Some((index, c)) = chars.next() {
+            if input[index..].starts_with(quote) {
+                return Ok((&input[index + quote.len()..], value));
+            }
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            let escaped = match chars.next() {
+                Some((_, escaped)) => escaped,
+                None => break,
+            };
+            match escaped {
+                't' => value.push('\t'),
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'u' | 'U' => {
+                    let length = if escaped == 'u' { 4 } else { 8 };
+                    let start = index + 2;
+                    let code = input
+                        .get(start..start + length)
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .and_then(std::char::from_u32);
+                    match code {
+                        Some(code) => value.push(code),
+                        None => return fail(&input[index..]),
+                    }
+                    for _ in 0..length {
+                        chars.next();
+                    }
+                }
+                other => value.push(other),
+            }
+        }
+        fail(input)
+    }
+
+    fn string(input: &str) -> IResult<&str, String> {
+        for quote in &["\"\"\"", "'''", "\"", "'"] {
+            if let Some(rest) = input.strip_prefix(quote) {
+                return string_body(rest, quote);
+            }
+        }
+        fail(input)
+    }
+
+    fn number(input: &str) -> IResult<&str, String> {
+        let digits = || take_while1(|c: char| c.is_ascii_digit());
+        let (_, text) = recognize(tuple((
+            opt(one_of("+-")),
+            alt((
+                recognize(pair(digits(), opt(pair(char('.'), opt(digits()))))),
+                recognize(pair(char('.'), digits())),
+            )),
+            opt(tuple((one_of("eE"), opt(one_of("+-")), digits()))),
+        )))(input)?;
+        // A trailing period ends the statement rather than being part of the number.
+        let trimmed = text.trim_end_matches('.');
+        Ok((&input[trimmed.len()..], trimmed.to_owned()))
+    }
+
+    fn literal(input: &str) -> IResult<&str, Term> {
+        if let Ok((input, value)) = string(input) {
+            let (input, language) = opt(preceded(
+                char('@'),
+                take_while1(|c: char| c.is_alphanumeric() || c == '-'),
+            ))(input)?;
+            // The datatype is not needed, numbers are parsed from the text either way.
+            let (input, _) = opt(preceded(tag("^^"), iri))(input)?;
+            let language = language.map(|language: &str| language.to_lowercase());
+            return Ok((input, Term::Literal { value, language }));
+        }
+        let (input, value) =
+            alt((number, map(alt((tag("true"), tag("false"))), str::to_owned)))(input)?;
+        Ok((
+            input,
+            Term::Literal {
+                value,
+                language: None,
+            },
+        ))
+    }
+
+    fn blank_node_properties(input: &str) -> IResult<&str, Vec<(String, Term)>> {
+        let (input, _) = char('[')(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, properties) = opt(predicate_object_list)(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, _) = char(']')(input)?;
+        Ok((input, properties.unwrap_or_default()))
+    }
+
+    fn collection(input: &str) -> IResult<&str, Vec<Term>> {
+        let (input, _) = char('(')(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, items) = many0(delimited(whitespace, object, whitespace))(input)?;
+        let (input, _) = char(')')(input)?;
+        Ok((input, items))
+    }
+
+    fn object(input: &str) -> IResult<&str, Term> {
+        alt((
+            map(blank_node_label, Term::Iri),
+            map(iri, Term::Iri),
+            map(blank_node_properties, Term::Blank),
+            map(collection, Term::Collection),
+            literal,
+        ))(input)
+    }
+
+    fn verb(input: &str) -> IResult<&str, String> {
+        if let Some(rest) = input.strip_prefix('a') {
+            if rest.starts_with(char::is_whitespace) {
+                return Ok((rest, "rdf:type".to_owned()));
+            }
+        }
+        iri(input)
+    }
+
+    fn object_list(input: &str) -> IResult<&str, Vec<Term>> {
+        separated_list1(delimited(whitespace, char(','), whitespace), object)(input)
+    }
+
+    fn predicate_object_list(input: &str) -> IResult<&str, Vec<(String, Term)>> {
+        let separator = pair(
+            many0(delimited(whitespace, char(';'), whitespace)),
+            whitespace,
+        );
+        let (input, first) = pair(verb, preceded(whitespace, object_list))(input)?;
+        let (input, others) = many0(preceded(
+            separator,
+            pair(verb, preceded(whitespace, object_list)),
+        ))(input)?;
+        // Trailing semicolons are allowed.
+        let (input, _) = many0(preceded(whitespace, char(';')))(input)?;
+        let mut properties = Vec::new();
+        for (predicate, objects) in std::iter::once(first).chain(others) {
+            for object in objects {
+                properties.push((predicate.clone(), object));
+            }
+        }
+        Ok((input, properties))
+    }
+
+    fn directive(input: &str) -> IResult<&str, ()> {
+        let (input, sparql_style) = alt((
+            map(alt((tag("@prefix"), tag("@base"))), |_| false),
+            map(alt((tag_no_case("PREFIX"), tag_no_case("BASE"))), |_| true),
+        ))(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, _) = opt(pair(
+            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '.'),
+            char(':'),
+        ))(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, _) = iri_ref(input)?;
+        if sparql_style {
+            Ok((input, ()))
+        } else {
+            let (input, _) = preceded(whitespace, char('.'))(input)?;
+            Ok((input, ()))
+        }
+    }
+
+    type Subject = (String, Vec<(String, Term)>);
+
+    fn triples(input: &str) -> IResult<&str, Subject> {
+        if let Ok((input, mut properties)) = blank_node_properties(input) {
+            let (input, _) = whitespace(input)?;
+            let (input, more) = opt(predicate_object_list)(input)?;
+            properties.extend(more.unwrap_or_default());
+            let (input, _) = preceded(whitespace, char('.'))(input)?;
+            return Ok((input, ("[]".to_owned(), properties)));
+        }
+        let (input, subject) = alt((blank_node_label, iri))(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, properties) = predicate_object_list(input)?;
+        let (input, _) = preceded(whitespace, char('.'))(input)?;
+        Ok((input, (subject, properties)))
+    }
+
+    /// Returns each subject in the document along with its predicates and objects.
+    pub fn parse_document(mut input: &str) -> Result<Vec<Subject>, ()> {
+        let mut subjects = Vec::new();
+        loop {
+            input = whitespace(input).map_err(|_| ())?.0;
+            if input.is_empty() {
+                return Ok(subjects);
+            }
+            if let Ok((rest, _)) = directive(input) {
+                input = rest;
+            } else {
+                let (rest, subject) = triples(input).map_err(|_| ())?;
+                subjects.push(subject);
+                input = rest;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn statements() {
+            let document = r#"
+                @prefix qudt: <http://qudt.org/schema/qudt/> .
+                PREFIX unit: <http://qudt.org/vocab/unit/>
+                # A comment.
+                unit:FT
+                  a qudt:Unit ;
+                  qudt:conversionMultiplier 0.3048 ;
+                  qudt:conversionMultiplierSN 3.048E-1 ;
+                  rdfs:label "Foot"@en, "pied"@fr ;
+                  dcterms:description """Multi
+                    line "text"."""^^rdf:HTML ;
+                  qudt:exactMatch [ a qudt:Thing ; ] ;
+                .
+            "#;
+            let subjects = parse_document(document).unwrap();
+            assert_eq!(subjects.len(), 1);
+            let (subject, properties) = &subjects[0];
+            assert_eq!(subject, "unit:FT");
+            assert_eq!(properties.len(), 7);
+            assert_eq!(
+                properties[1],
+                (
+                    "qudt:conversionMultiplier".to_owned(),
+                    Term::Literal {
+                        value: "0.3048".to_owned(),
+                        language: None
+                    }
+                )
+            );
+            assert_eq!(
+                properties[4],
+                (
+                    "rdfs:label".to_owned(),
+                    Term::Literal {
+                        value: "pied".to_owned(),
+                        language: Some("fr".to_owned())
+                    }
+                )
+            );
+        }
+
+        #[test]
+        fn malformed_input() {
+            for document in &[
+                "unit:FT a qudt:Unit",
+                "unit:FT a qudt:Unit ;",
+                "unit:FT a",
+                "unit:FT",
+                "<http://qudt.org/vocab/unit/FT",
+                "@prefix qudt: <http://qudt.org/schema/qudt/>",
+                "@prefix qudt: <http://qudt.org/schema/qudt/",
+                "unit:FT rdfs:label \"Foot .",
+                "unit:FT rdfs:label \"\"\"Foot\" .",
+                "unit:FT rdfs:label \"Foot\\",
+                "unit:FT rdfs:label \"\\u00\" .",
+                "unit:FT rdfs:label \"\\u00é9\" .",
+                "unit:FT rdfs:label \"Pied\"@ .",
+                "unit:FT qudt:exactMatch [ a qudt:Thing .",
+                "unit:FT qudt:exactMatch ( unit:M .",
+                "[ a qudt:Unit",
+                "unit:FT a qudt:Unit . }",
+                "é",
+                ".",
+            ] {
+                assert!(parse_document(document).is_err(), "{}", document);
+            }
+        }
+
+        #[test]
+        fn truncated_input() {
+            let directive = "@prefix unit: <http://qudt.org/vocab/unit/> .";
+            let document = format!(
+                "{}\nunit:DEG a qudt:Unit ; rdfs:label \"Degree °\"@en, '''Grad'''@de ;\n\
+                qudt:factor -15e-4 ; qudt:exactMatch [ a qudt:Thing ], ( unit:RAD ) .",
+                directive
+            );
+            assert_eq!(parse_document(&document).unwrap().len(), 1);
+            // Cutting the document short anywhere except between the statements leaves one of
+            // them unfinished.
+            for (end, _) in document.char_indices() {
+                let prefix = &document[..end];
+                let finished = prefix.is_empty() || prefix.trim_end() == directive;
+                assert_eq!(parse_document(prefix).is_ok(), finished, "{}", prefix);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{evaluate, run};
+
+    const UCUM: &str = r#"<?xml version="1.0" encoding="ascii"?>
+        <root xmlns="http://unitsofmeasure.org/ucum-essence" version="2.1">
+          <prefix Code="k" CODE="K"><name>kilo</name><printSymbol>k</printSymbol>
+            <value value="1e3">1 &#215; 10<sup>3</sup></value></prefix>
+          <prefix Code="c" CODE="C"><name>centi</name><printSymbol>c</printSymbol>
+            <value value="1e-2">1 &#215; 10<sup>-2</sup></value></prefix>
+          <base-unit Code="m" CODE="M" dim="L"><name>meter</name><printSymbol>m</printSymbol>
+            <property>length</property></base-unit>
+          <base-unit Code="s" CODE="S" dim="T"><name>second</name><printSymbol>s</printSymbol>
+            <property>time</property></base-unit>
+          <base-unit Code="g" CODE="G" dim="M"><name>gram</name><printSymbol>g</printSymbol>
+            <property>mass</property></base-unit>
+          <base-unit Code="K" CODE="K" dim="C"><name>kelvin</name><printSymbol>K</printSymbol>
+            <property>temperature</property></base-unit>
+          <unit Code="N" CODE="N" isMetric="yes" class="si"><name>newton</name>
+            <printSymbol>N</printSymbol><property>force</property>
+            <value Unit="kg.m/s2" UNIT="KG.M/S2" value="1">1</value></unit>
+          <unit Code="[in_i]" CODE="[IN_I]" isMetric="no" class="intcust"><name>inch</name>
+            <printSymbol>in</printSymbol><property>length</property>
+            <value Unit="cm" UNIT="CM" value="254e-2">2.54</value></unit>
+          <unit Code="[in_us]" CODE="[IN_US]" isMetric="no" class="us-lengths"><name>inch</name>
+            <printSymbol>in</printSymbol><property>length</property>
+            <value Unit="[ft_us]/12" UNIT="[FT_US]/12" value="1">1</value></unit>
+          <unit Code="[ft_us]" CODE="[FT_US]" isMetric="no" class="us-lengths"><name>foot</name>
+            <printSymbol>ft<sub>us</sub></printSymbol><property>length</property>
+            <value Unit="m/3937" UNIT="M/3937" value="1200">1200</value></unit>
+          <unit Code="Cel" CODE="CEL" isMetric="yes" isSpecial="yes" class="si">
+            <name>degree Celsius</name><printSymbol>&#176;C</printSymbol>
+            <property>temperature</property>
+            <value Unit="cel(1 K)" UNIT="CEL(1 K)"><function name="Cel" value="1" Unit="K"/></value>
+          </unit>
+          <unit Code="B" CODE="B" isMetric="yes" isSpecial="yes" class="levels"><name>bel</name>
+            <printSymbol>B</printSymbol><property>level</property>
+            <value Unit="lg(1 1)" UNIT="LG(1 1)"><function name="lg" value="1" Unit="1"/></value>
+          </unit>
+        </root>"#;
+
+    #[test]
+    fn import_ucum() {
+        let mut instance = Instance::new();
+        let report = instance
+            .import_ucum(UCUM, &BaseDimension::ucum_defaults())
+            .unwrap();
+        assert_eq!(
+            report.imported,
+            vec![
+                "Meter",
+                "Second",
+                "Gram",
+                "Kelvin",
+                "Newton",
+                "Inch",
+                "Inch_in_us",
+                "Foot"
+            ]
+        );
+        assert_eq!(
+            report.skipped,
+            vec![
+                ("DegreeCelsius".to_owned(), SkipReason::Offset),
+                ("Bel".to_owned(), SkipReason::Logarithmic),
+            ]
+        );
+        let (_, inch) = instance
+            .units()
+            .find(|(_, unit)| unit.names[0] == "Inch")
+            .unwrap();
+        assert!((inch.base_ratio.to_f64() - 0.0254).abs() < 1e-12);
+        assert_eq!(
+            evaluate(&instance, "(1 * Kilonewton) is (Mass * Length / Time^2)").unwrap(),
+            "true"
+        );
+        assert_eq!(
+            evaluate(&instance, "1 * Kilonewton in Newton").unwrap(),
+            "1000N"
+        );
+        let angle = instance.lookup_item(&"Angle".to_owned()).as_meta.cloned();
+        match angle {
+            Some(MetaData::UnitClass(angle)) => assert!(instance[angle.as_single().unwrap()].angle),
+            _ => panic!("Angle is not a unit class"),
+        }
+    }
+
+    #[test]
+    fn import_statement() {
+        let path = std::env::temp_dir().join(format!("ackulator-{}.ucum.xml", std::process::id()));
+        std::fs::write(&path, UCUM).unwrap();
+        let mut instance = Instance::new();
+        let result = run(
+            &mut instance,
+            &format!("import ucum from \"{}\"", path.display()),
+        );
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        let reports = instance.take_import_reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].imported.len(), 8);
+        assert_eq!(reports[0].skipped.len(), 2);
+        assert!(instance.take_import_reports().is_empty());
+    }
+
+    #[test]
+    fn import_qudt() {
+        let turtle = r#"
+            @prefix qudt: <http://qudt.org/schema/qudt/> .
+            @prefix qkdv: <http://qudt.org/vocab/dimensionvector/> .
+            @prefix unit: <http://qudt.org/vocab/unit/> .
+            unit:KiloGM a qudt:Unit ;
+              qudt:conversionMultiplier 1.0 ;
+              qudt:hasDimensionVector qkdv:A0E0L0I0M1H0T0D0 ;
+              qudt:symbol "kg" ;
+              rdfs:label "Kilogram"@en .
+            unit:FT a qudt:Unit ;
+              qudt:conversionMultiplier 0.3048 ;
+              qudt:hasDimensionVector qkdv:A0E0L1I0M0H0T0D0 ;
+              qudt:symbol "ft" ;
+              rdfs:label "Foot"@en .
+            unit:DEG_C a qudt:Unit ;
+              qudt:conversionMultiplier 1.0 ;
+              qudt:conversionOffset 273.15 ;
+              qudt:hasDimensionVector qkdv:A0E0L0I0M0H1T0D0 ;
+              rdfs:label "degree Celsius"@en .
+            unit:DeciB a qudt:LogarithmicUnit, qudt:Unit ;
+              qudt:hasDimensionVector qkdv:A0E0L0I0M0H0T0D1 ;
+              rdfs:label "Decibel"@en .
+            qkdv:A0E0L1I0M0H0T0D0 a qudt:DimensionVector .
+        "#;
+        let mut instance = Instance::new();
+        let report = instance
+            .import_qudt(turtle, &BaseDimension::qudt_defaults())
+            .unwrap();
+        assert_eq!(report.imported, vec!["Kilogram", "Foot"]);
+        assert_eq!(
+            report.skipped,
+            vec![
+                ("DegreeCelsius".to_owned(), SkipReason::Offset),
+                ("Decibel".to_owned(), SkipReason::Logarithmic),
+            ]
+        );
+        // Mass is scaled so that the base unit is the gram.
+        assert_eq!(
+            evaluate(&instance, "2 * Kilogram in Kilogram").unwrap(),
+            "2kg"
+        );
+        let (_, kilogram) = instance
+            .units()
+            .find(|(_, unit)| unit.names[0] == "Kilogram")
+            .unwrap();
+        assert_eq!(kilogram.base_ratio.to_f64(), 1000.0);
+    }
+
+    #[test]
+    fn unknown_dimensions_are_reported() {
+        let mut instance = Instance::new();
+        let dimensions = vec![BaseDimension::new("L", "Length", 1.0)];
+        let report = instance.import_ucum(UCUM, &dimensions).unwrap();
+        assert!(report.skipped.contains(&(
+            "Second".to_owned(),
+            SkipReason::UnknownDimension("T".to_owned())
+        )));
+    }
+}
@@ -1,15 +1,17 @@
 use crate::{
     data::{AmbiguousItem, Data, Describe, MetaData, ValueData},
-    entity::{Entity, EntityClass},
+    entity::{Annotation, Entity, EntityClass},
     expression::{BinaryOp, Expression, UnaryOp},
+    import::{BaseDimension, ImportFormat, ImportReport},
     prelude::*,
     statement::Statement,
     storage::{StorageId, StoragePool},
 };
 use paste::paste;
 use std::{
-    collections::{HashMap, HashSet},
-    fmt::Debug,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
+    fmt::{Debug, Display, Formatter},
     hash::Hash,
     ops::Index,
 };
@@ -18,9 +20,43 @@ pub type UnitClassId = StorageId<UnitClass>;
 pub type UnitId = StorageId<Unit>;
 pub type EntityClassId = StorageId<EntityClass>;
 
+/// The kinds of item that can be redefined or forgotten.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ItemKind {
+    Unit,
+    Label,
+    Value,
+}
+
+impl Display for ItemKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unit => write!(f, "unit"),
+            Self::Label => write!(f, "label"),
+            Self::Value => write!(f, "value"),
+        }
+    }
+}
+
+/// Refers to an item by its kind and the first name it was declared with.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemRef {
+    pub kind: ItemKind,
+    pub name: String,
+}
+
+impl Display for ItemRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.kind, self.name)
+    }
+}
+
+/// Removed items leave a hole in `items` so that the indexes of the other items stay the same.
 #[derive(Clone, Debug)]
 struct ManyToOneMap<K: Hash + Eq, V> {
-    items: Vec<V>,
+    items: Vec<Option<V>>,
     keys: HashMap<K, usize>,
 }
 
@@ -41,24 +77,113 @@ impl<K: Hash + Eq, V> ManyToOneMap<K, V> {
 
     pub fn insert(&mut self, keys: impl IntoIterator<Item = K>, item: V) {
         let index = self.items.len();
-        self.items.push(item);
+        self.items.push(Some(item));
         for key in keys {
             self.keys.insert(key, index);
         }
     }
 
     pub fn get(&self, key: &K) -> Option<&V> {
-        self.keys.get(key).map(|&idx| &self.items[idx])
+        self.keys.get(key).and_then(|&idx| self.items[idx].as_ref())
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = *self.keys.get(key)?;
+        self.items[idx].as_mut()
+    }
+
+    /// Returns every key which refers to the same item as the given key.
+    pub fn keys_of(&self, key: &K) -> Vec<&K> {
+        match self.keys.get(key) {
+            Some(&index) => self
+                .keys
+                .iter()
+                .filter(|(_, &idx)| idx == index)
+                .map(|(key, _)| key)
+                .collect(),
+            None => Vec::new(),
+        }
     }
 
     pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
-        self.keys
-            .get_key_value(key)
-            .map(|(key, &idx)| (key, &self.items[idx]))
+        let (key, &idx) = self.keys.get_key_value(key)?;
+        self.items[idx].as_ref().map(|item| (key, item))
+    }
+
+    /// Removes the item the key refers to, along with every other key that refers to the same
+    /// item. Returns the removed keys and item.
+    pub fn remove(&mut self, key: &K) -> Option<(Vec<K>, V)> {
+        let index = *self.keys.get(key)?;
+        let (removed, kept): (HashMap<K, usize>, _) = std::mem::take(&mut self.keys)
+            .into_iter()
+            .partition(|(_, idx)| *idx == index);
+        self.keys = kept;
+        let removed_keys = removed.into_keys().collect();
+        self.items[index].take().map(|item| (removed_keys, item))
+    }
+
+    /// Returns every item along with all the keys that refer to it, in the order the items were
+    /// inserted. Keys are sorted so that the output does not depend on hashing order.
+    pub fn entries(&self) -> Vec<(Vec<&K>, &V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self.items.iter().map(|item| (Vec::new(), item)).collect();
+        for (key, &idx) in &self.keys {
+            entries[idx].0.push(key);
+        }
+        entries
+            .into_iter()
+            .filter_map(|(mut keys, item)| {
+                keys.sort();
+                item.as_ref().map(|item| (keys, item))
+            })
+            .collect()
+    }
+}
+
+/// ManyToOneMaps are stored as a list of `{ names, item }` entries so that every item is written
+/// exactly once no matter how many names refer to it.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ManyToOneEntry<K, V> {
+    names: Vec<K>,
+    item: V,
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> serde::Serialize for ManyToOneMap<K, V>
+where
+    K: Hash + Eq + Ord + serde::Serialize,
+    V: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.entries()
+                .into_iter()
+                .map(|(names, item)| ManyToOneEntry { names, item }),
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> serde::Deserialize<'de> for ManyToOneMap<K, V>
+where
+    K: Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<ManyToOneEntry<K, V>>::deserialize(deserializer)?;
+        let mut map = Self::new();
+        for entry in entries {
+            map.insert(entry.names, entry.item);
+        }
+        Ok(map)
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instance {
     unit_classes: StoragePool<UnitClass>,
     units: StoragePool<Unit>,
@@ -67,6 +192,35 @@ pub struct Instance {
     meta_items: ManyToOneMap<String, MetaData>,
     values: ManyToOneMap<String, Entity>,
     labels: ManyToOneMap<String, Data>,
+    /// Maps every name of a label declared as a kind of quantity to the kind's first name, which
+    /// is what scalars of that kind are tagged with.
+    kinds: ManyToOneMap<String, String>,
+
+    /// The names each item's definition referred to when it was declared by a statement.
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::map_as_pairs"))]
+    dependencies: BTreeMap<ItemRef, BTreeSet<String>>,
+    /// The statements that declared and then changed each item, which are run again to recompute
+    /// it. These are not kept in snapshots, so restored items keep their values.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    definitions: BTreeMap<ItemRef, Vec<Statement>>,
+    /// The names looked up while running the current statement.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    used_names: RefCell<Option<BTreeSet<String>>>,
+    /// Explains why the last expression or statement failed, when there is more to say than
+    /// Err(()). Kept until taken by take_error, so that whoever ran the statement can report it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: RefCell<Option<String>>,
+    /// Things worth telling the user about which didn't stop a statement from running, kept until
+    /// they are taken by take_warnings.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    warnings: Vec<String>,
+    /// What each import statement imported and skipped, kept until taken by take_import_reports.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    import_reports: Vec<ImportReport>,
+    /// Set while dependants are being recomputed, so that replaying their definitions doesn't
+    /// start recomputing again.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replaying: bool,
 }
 
 macro_rules! index_storage {
@@ -86,6 +240,12 @@ index_storage!(unit_classes UnitClass);
 index_storage!(units Unit);
 index_storage!(entity_classes EntityClass);
 
+impl Default for Instance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Instance {
     pub fn new() -> Self {
         Self {
@@ -96,9 +256,31 @@ impl Instance {
             meta_items: ManyToOneMap::new(),
             values: ManyToOneMap::new(),
             labels: ManyToOneMap::new(),
+            kinds: ManyToOneMap::new(),
+
+            dependencies: BTreeMap::new(),
+            definitions: BTreeMap::new(),
+            used_names: RefCell::new(None),
+            error: RefCell::new(None),
+            warnings: Vec::new(),
+            import_reports: Vec::new(),
+            replaying: false,
         }
     }
 
+    /// Whether every id read along with this instance from a snapshot refers to one of its items.
+    #[cfg(feature = "serde")]
+    pub(crate) fn holds_loaded_ids(&self) -> bool {
+        let prefixes_exist = self.units.iter().all(|(_, unit)| {
+            unit.prefix_origin
+                .is_none_or(|origin| origin.prefix < METRIC_PREFIXES.len())
+        });
+        prefixes_exist
+            && self.unit_classes.holds_loaded_ids()
+            && self.units.holds_loaded_ids()
+            && self.entity_classes.holds_loaded_ids()
+    }
+
     pub fn add_unit_class(&mut self, unit_class: UnitClass) -> Result<UnitClassId, ()> {
         let id = self.unit_classes.next_id();
         self.declare_meta_item(unit_class.names.clone(), id.into())?;
@@ -108,6 +290,7 @@ impl Instance {
 
     pub fn add_unit(&mut self, unit: Unit, prefix_type: UnitPrefixType) -> Result<UnitId, ()> {
         use UnitPrefixType::*;
+        let id = self.units.next_id();
         let variants = match prefix_type {
             None => Vec::new(),
             Metric | PartialMetric => {
@@ -120,7 +303,7 @@ impl Instance {
                         let start_char = name.chars().next();
                         if start_char.is_none() {
                             debug_assert!(false);
-                            return format!("???");
+                            return "???".to_string();
                         }
                         let start_char = start_char.unwrap();
                         let start_char_len = start_char.len_utf8();
@@ -130,7 +313,9 @@ impl Instance {
                 let partial = prefix_type == PartialMetric;
                 let start_from = if partial { SMALL_PREFIXES_START } else { 0 };
                 let mut variants = Vec::new();
-                for (pfx_name, pfx_abbreviation, pfx_factor) in &METRIC_PREFIXES[start_from..] {
+                for (pfx_index, (pfx_name, pfx_abbreviation, pfx_power)) in
+                    METRIC_PREFIXES.iter().enumerate().skip(start_from)
+                {
                     let names: Vec<_> = prefixable_names
                         .iter()
                         .map(|name| format!("{}{}", pfx_name, name))
@@ -140,19 +325,27 @@ impl Instance {
                         names,
                         class: unit.class.clone(),
                         symbol,
-                        base_ratio: unit.base_ratio * pfx_factor,
+                        base_ratio: unit.base_ratio.clone() * Number::power_of_ten(*pfx_power),
+                        prefix_origin: Some(PrefixOrigin {
+                            unit: id,
+                            prefix: pfx_index,
+                        }),
+                        kind: unit.kind.clone(),
                     };
                     variants.push(unit);
                 }
                 variants
             }
         };
+        // Every name is checked before anything is declared so that a clash can't leave the unit
+        // half added.
         for variant in &variants {
-            if self.meta_items.contains_any_key(&variant.names) {
+            if self.meta_items.contains_any_key(&variant.names)
+                || variant.names.iter().any(|name| unit.names.contains(name))
+            {
                 return Err(());
             }
         }
-        let id = self.units.next_id();
         self.declare_meta_item(unit.names.clone(), id.into())?;
         debug_assert_eq!(self.units.push(unit), id);
         for variant in variants {
@@ -201,29 +394,367 @@ impl Instance {
         Ok(())
     }
 
-    pub fn lookup_item(&self, name: &String) -> AmbiguousItem {
+    /// Declares a label for the unit class of a kind of quantity.
+    fn declare_kind(&mut self, names: Vec<String>, data: Data) -> Result<(), ()> {
+        if !matches!(data, Data::Meta(MetaData::UnitClass(..))) {
+            return Err(());
+        }
+        self.declare_label(names.clone(), data)?;
+        let kind = names[0].clone();
+        self.kinds.insert(names, kind);
+        Ok(())
+    }
+
+    /// If the expression names a kind of quantity, returns the name its scalars are tagged with
+    /// and its unit class.
+    pub fn kind_named(
+        &self,
+        expression: &Expression,
+        bindings: &Bindings,
+    ) -> Option<(&str, &CompositeUnitClass)> {
+        let name = match expression {
+            Expression::LookupName(name) if !bindings.contains_key(name) => name,
+            _ => return None,
+        };
+        match (self.kinds.get(name), self.labels.get(name)) {
+            (Some(kind), Some(Data::Meta(MetaData::UnitClass(class)))) => Some((kind, class)),
+            _ => None,
+        }
+    }
+
+    /// Records why the current expression failed, for when it returns Err(()).
+    pub(crate) fn fail(&self, message: String) {
+        self.error.replace(Some(message));
+    }
+
+    /// Returns and clears the explanation of why the last expression or statement failed, if
+    /// there is one.
+    pub fn take_error(&self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Returns and clears the warnings given by the statements run since this was last called.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Returns and clears the reports of the import statements run since this was last called.
+    pub fn take_import_reports(&mut self) -> Vec<ImportReport> {
+        std::mem::take(&mut self.import_reports)
+    }
+
+    pub fn lookup_item(&self, name: &String) -> AmbiguousItem<'_> {
         AmbiguousItem {
             as_meta: self.meta_items.get(name),
             as_value: self.values.get(name),
             as_label: self.labels.get_key_value(name),
         }
     }
+
+    pub fn unit_classes(&self) -> impl Iterator<Item = (UnitClassId, &UnitClass)> {
+        self.unit_classes.iter()
+    }
+
+    /// Does not include units which have been forgotten.
+    pub fn units(&self) -> impl Iterator<Item = (UnitId, &Unit)> {
+        self.units.iter()
+    }
+
+    /// Returns every label along with all of its names.
+    pub fn labels(&self) -> Vec<(Vec<&String>, &Data)> {
+        self.labels.entries()
+    }
 }
 
+impl Instance {
+    /// Replaces the value with the given name by a changed copy, which is checked against its
+    /// classes, then recomputes everything defined in terms of the value. Nothing changes if
+    /// either step fails.
+    pub fn update_value(
+        &mut self,
+        name: &String,
+        update: impl FnOnce(&mut Entity),
+    ) -> Result<(), ()> {
+        let mut entity = self.values.get(name).ok_or(())?.clone();
+        update(&mut entity);
+        let entity = self
+            .complete_entity(entity)
+            .map_err(|message| self.fail(message))?;
+        self.transaction(|instance| {
+            *instance.values.get_mut(name).ok_or(())? = entity;
+            let item = instance.item_named(name).ok_or(())?;
+            instance.recompute_dependants(&item)
+        })
+    }
+
+    /// Returns every item whose definition referred to one of the given names, sorted.
+    pub fn dependants_of(&self, names: &[String]) -> Vec<ItemRef> {
+        self.dependencies
+            .iter()
+            .filter(|(_, used)| names.iter().any(|name| used.contains(name)))
+            .map(|(item, _)| item.clone())
+            .collect()
+    }
+
+    /// Every name the item can be referred to by. A unit's names include those of the variants
+    /// generated for it with metric prefixes.
+    pub fn names_of(&self, item: &ItemRef) -> Vec<String> {
+        let names = match item.kind {
+            ItemKind::Label => self.labels.keys_of(&item.name),
+            ItemKind::Value => self.values.keys_of(&item.name),
+            ItemKind::Unit => {
+                let id = match self.meta_items.get(&item.name) {
+                    Some(MetaData::Unit(unit)) => unit.as_single(),
+                    _ => None,
+                };
+                let id = match id {
+                    Some(id) => id,
+                    None => return Vec::new(),
+                };
+                self.units
+                    .iter()
+                    .filter(|(other, unit)| {
+                        *other == id || unit.prefix_origin.map(|origin| origin.unit) == Some(id)
+                    })
+                    .flat_map(|(_, unit)| &unit.names)
+                    .collect()
+            }
+        };
+        let mut names: Vec<String> = names.into_iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// The item a name refers to, preferring values, then units, then labels like expressions
+    /// do. Items are referred to by the first name they were declared with where that's known.
+    pub fn item_named(&self, name: &String) -> Option<ItemRef> {
+        let found = self.lookup_item(name);
+        let kind = if found.as_value.is_some() {
+            ItemKind::Value
+        } else if let Some(MetaData::Unit(..)) = found.as_meta {
+            ItemKind::Unit
+        } else if found.as_label.is_some() {
+            ItemKind::Label
+        } else {
+            return None;
+        };
+        let declared = self
+            .dependencies
+            .keys()
+            .find(|item| item.kind == kind && self.names_of(item).contains(name));
+        Some(declared.cloned().unwrap_or(ItemRef {
+            kind,
+            name: name.clone(),
+        }))
+    }
+
+    /// The items the item's definition used, sorted.
+    pub fn dependencies_of(&self, item: &ItemRef) -> Vec<ItemRef> {
+        let used = match self.dependencies.get(item) {
+            Some(used) => used,
+            None => return Vec::new(),
+        };
+        let items: BTreeSet<_> = used
+            .iter()
+            .filter_map(|name| self.item_named(name))
+            .collect();
+        items.into_iter().collect()
+    }
+
+    /// The items whose definitions used the item directly, sorted.
+    pub fn direct_dependants(&self, item: &ItemRef) -> Vec<ItemRef> {
+        self.dependants_of(&self.names_of(item))
+    }
+
+    /// Every item which depends on the given one, directly or through other items, ordered so that
+    /// each comes after everything it depends on. If the item depends on itself, returns the
+    /// cycle instead, starting and ending with the item.
+    pub fn recomputation_order(&self, item: &ItemRef) -> Result<Vec<ItemRef>, Vec<ItemRef>> {
+        let mut finished = Vec::new();
+        self.visit_dependants(&mut vec![item.clone()], &mut finished)?;
+        finished.reverse();
+        Ok(finished)
+    }
+
+    /// Depth first search which adds each dependant after all of its own dependants.
+    fn visit_dependants(
+        &self,
+        path: &mut Vec<ItemRef>,
+        finished: &mut Vec<ItemRef>,
+    ) -> Result<(), Vec<ItemRef>> {
+        let current = path[path.len() - 1].clone();
+        for dependant in self.direct_dependants(&current) {
+            if let Some(start) = path.iter().position(|item| item == &dependant) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(dependant);
+                return Err(cycle);
+            }
+            if finished.contains(&dependant) {
+                continue;
+            }
+            path.push(dependant);
+            self.visit_dependants(path, finished)?;
+            finished.extend(path.pop());
+        }
+        Ok(())
+    }
+
+    /// Runs the definitions of everything that depends on the item again. Fails if that would make
+    /// the item depend on itself or if any dependant can't be recomputed, in which case every
+    /// dependant keeps its old value.
+    fn recompute_dependants(&mut self, item: &ItemRef) -> Result<(), ()> {
+        // Replaying a definition changes its item, but everything depending on that item is
+        // already later in the order being replayed.
+        if self.replaying {
+            return Ok(());
+        }
+        let order = self.recomputation_order(item).map_err(|cycle| {
+            let cycle: Vec<_> = cycle.iter().map(ToString::to_string).collect();
+            self.fail(format!(
+                "{} would depend on itself: {}",
+                item,
+                cycle.join(" -> ")
+            ));
+        })?;
+        if order.is_empty() {
+            return Ok(());
+        }
+        let mut failed = None;
+        self.replaying = true;
+        let result = self.transaction(|instance| {
+            for dependant in &order {
+                failed = Some(dependant);
+                let definition = instance.definitions.get(dependant).cloned().ok_or(())?;
+                instance.forget(dependant.kind, &dependant.name)?;
+                for statement in definition {
+                    instance.execute_statement(statement)?;
+                }
+            }
+            Ok(())
+        });
+        self.replaying = false;
+        if let (Err(()), Some(dependant)) = (result, failed) {
+            let reason = self
+                .take_error()
+                .map(|reason| format!(": {}", reason))
+                .unwrap_or_default();
+            self.fail(format!(
+                "{} is defined in terms of {} and could not be recomputed after it changed{}",
+                dependant, item, reason
+            ));
+        }
+        result
+    }
+
+    /// Removes a unit along with any variants that were generated for it by adding metric
+    /// prefixes. Existing values using the unit keep working, but it can no longer be referred to
+    /// by name. Returns Err(()) if the name does not refer to a unit, otherwise returns the items
+    /// which were defined in terms of the removed units.
+    pub fn forget_unit(&mut self, name: &String) -> Result<Vec<ItemRef>, ()> {
+        let id = match self.meta_items.get(name) {
+            Some(MetaData::Unit(unit)) => unit.as_single().ok_or(())?,
+            _ => return Err(()),
+        };
+        let variants: Vec<_> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| unit.prefix_origin.map(|origin| origin.unit) == Some(id))
+            .map(|(variant_id, _)| variant_id)
+            .collect();
+        let mut removed_names = Vec::new();
+        for id in std::iter::once(id).chain(variants) {
+            if let Some((names, _)) = self.meta_items.remove(&self.units[id].names[0]) {
+                removed_names.extend(names);
+            }
+            self.units.remove(id);
+        }
+        Ok(self.forget_dependencies(ItemKind::Unit, &removed_names))
+    }
+
+    /// Returns Err(()) if the name does not refer to a label, otherwise returns the items which
+    /// were defined in terms of the label.
+    pub fn forget_label(&mut self, name: &String) -> Result<Vec<ItemRef>, ()> {
+        let (names, _) = self.labels.remove(name).ok_or(())?;
+        self.kinds.remove(name);
+        Ok(self.forget_dependencies(ItemKind::Label, &names))
+    }
+
+    /// Returns Err(()) if the name does not refer to a value, otherwise returns the items which
+    /// were defined in terms of the value.
+    pub fn forget_value(&mut self, name: &String) -> Result<Vec<ItemRef>, ()> {
+        let (names, _) = self.values.remove(name).ok_or(())?;
+        Ok(self.forget_dependencies(ItemKind::Value, &names))
+    }
+
+    pub fn forget(&mut self, kind: ItemKind, name: &String) -> Result<Vec<ItemRef>, ()> {
+        match kind {
+            ItemKind::Unit => self.forget_unit(name),
+            ItemKind::Label => self.forget_label(name),
+            ItemKind::Value => self.forget_value(name),
+        }
+    }
+
+    /// Drops the recorded dependencies of the items that used to have the given names and
+    /// returns the items that depended on any of those names.
+    fn forget_dependencies(&mut self, kind: ItemKind, removed_names: &[String]) -> Vec<ItemRef> {
+        for name in removed_names {
+            let item = ItemRef {
+                kind,
+                name: name.clone(),
+            };
+            self.dependencies.remove(&item);
+            self.definitions.remove(&item);
+        }
+        self.dependants_of(removed_names)
+    }
+
+    /// Replaces the unit that has the first of the given names. Returns Err(()) and leaves
+    /// everything unchanged if there is no such unit or the new unit can't be added.
+    pub fn redefine_unit(
+        &mut self,
+        unit: Unit,
+        prefix_type: UnitPrefixType,
+    ) -> Result<(UnitId, Vec<ItemRef>), ()> {
+        self.transaction(|instance| {
+            let dependants = instance.forget_unit(&unit.names[0])?;
+            let id = instance.add_unit(unit, prefix_type)?;
+            Ok((id, dependants))
+        })
+    }
+
+    /// Replaces the label that has the first of the given names. Returns Err(()) and leaves
+    /// everything unchanged if there is no such label or another name is taken.
+    pub fn redefine_label(&mut self, names: Vec<String>, data: Data) -> Result<Vec<ItemRef>, ()> {
+        self.transaction(|instance| {
+            let dependants = instance.forget_label(&names[0])?;
+            instance.declare_label(names, data)?;
+            Ok(dependants)
+        })
+    }
+
+    /// Replaces the value that has the first of the given names. Returns Err(()) and leaves
+    /// everything unchanged if there is no such value or another name is taken.
+    pub fn redefine_value(&mut self, names: Vec<String>, data: Entity) -> Result<Vec<ItemRef>, ()> {
+        self.transaction(|instance| {
+            let dependants = instance.forget_value(&names[0])?;
+            instance.declare_value(names, data)?;
+            Ok(dependants)
+        })
+    }
+}
+
+/// Data given to names for the duration of one evaluation, like the variables of a table.
+pub type Bindings = BTreeMap<String, Data>;
+
 /// Tells the instance how it should deal with multiple items that have the same name. E.G. should
 /// it prefer meta items or values.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub enum AmbiguityResolutionContext {
     PreferMetaItems,
+    #[default]
     PreferValues,
 }
 
-impl Default for AmbiguityResolutionContext {
-    fn default() -> Self {
-        Self::PreferValues
-    }
-}
-
 impl AmbiguityResolutionContext {
     pub fn resolve(self, item: &AmbiguousItem) -> Option<Data> {
         match self {
@@ -258,11 +789,29 @@ impl Instance {
         match op {
             Negate => match rhs {
                 Value(Scalar(data)) => Ok((-data).into()),
+                Value(Matrix(data)) => Ok(data.map(|x| Ok(-x.clone()))?.into()),
                 _ => Err(()),
             },
         }
     }
 
+    /// Applies an operator to one component of a matrix, which must produce another scalar.
+    fn resolve_component(&self, lhs: Data, op: BinaryOp, rhs: Data) -> Result<Scalar, ()> {
+        match self.resolve_binary_expression(lhs, op, rhs)? {
+            Data::Value(ValueData::Scalar(scalar)) => Ok(scalar),
+            _ => Err(()),
+        }
+    }
+
+    /// Indexes are dimensionless, non-negative integers.
+    fn index_value(&self, index: &Scalar) -> Result<usize, ()> {
+        let index = index.as_exponent(self)?;
+        if !index.is_integer() || index < Exponent::from_integer(0) {
+            return Err(());
+        }
+        usize::try_from(index.to_integer()).map_err(|_| ())
+    }
+
     fn resolve_binary_expression(&self, lhs: Data, op: BinaryOp, rhs: Data) -> Result<Data, ()> {
         use BinaryOp::*;
         use Data::*;
@@ -277,34 +826,44 @@ impl Instance {
             (Value(Bool(..)), _) => Err(()),
 
             (Value(Entity(lhs)), Meta(EntityClass(rhs))) => match op {
-                IsClass => Ok((lhs.classes.contains(&rhs)).into()),
+                IsClass => Ok(self.entity_is_a(&lhs, rhs).into()),
                 _ => Err(()),
             },
             (Value(Entity(..)), _) => Err(()),
             (_, Meta(EntityClass(..))) => Err(()),
 
             (Meta(Unit(lhs)), Meta(UnitClass(rhs))) => match op {
-                IsClass => Ok((lhs.unit_class(self) == rhs).into()),
+                IsClass => Ok((lhs.unit_class(self)? == rhs).into()),
                 _ => Err(()),
             },
             (Meta(UnitClass(..)), Meta(Unit(..))) => Err(()),
 
             (Meta(Unit(lhs)), Meta(Unit(rhs))) => match op {
-                Mul => Ok((lhs * rhs).into()),
-                Div => Ok((lhs / rhs).into()),
+                Mul => Ok((lhs * rhs)?.into()),
+                Div => Ok((lhs / rhs)?.into()),
                 _ => Err(()),
             },
             (Meta(UnitClass(lhs)), Meta(UnitClass(rhs))) => match op {
-                Mul => Ok((lhs * rhs).into()),
-                Div => Ok((lhs / rhs).into()),
+                Mul => Ok((lhs * rhs)?.into()),
+                Div => Ok((lhs / rhs)?.into()),
                 _ => Err(()),
             },
 
             (Value(Scalar(lhs)), Meta(Unit(rhs))) => match op {
-                Mul => Ok((lhs * rhs.as_scalar(self)).into()),
-                Div => Ok((lhs / rhs.as_scalar(self)).into()),
+                Mul => Ok((lhs * rhs.as_scalar(self)?)?.into()),
+                Div => Ok((lhs / rhs.as_scalar(self)?)?.into()),
                 InUnits => {
+                    // Torque can't be shown in joules without casting it to energy first.
+                    if let (Some(kind), Some(unit_kind)) = (lhs.kind(), rhs.kind(self)) {
+                        if kind != unit_kind {
+                            return Err(());
+                        }
+                    }
                     let mut lhs = lhs;
+                    let class = rhs.unit_class(self)?;
+                    if lhs.unit() != &class && lhs.unit().matches_without_angles(&class, self) {
+                        lhs = lhs.without_angles(self)?;
+                    }
                     lhs.set_display_unit(rhs);
                     Ok(lhs.into())
                 }
@@ -312,11 +871,11 @@ impl Instance {
                 _ => Err(()),
             },
             (Meta(Unit(lhs)), Value(Scalar(rhs))) => match op {
-                Mul => Ok((lhs.as_scalar(self) * rhs).into()),
-                Div => Ok((lhs.as_scalar(self) / rhs).into()),
+                Mul => Ok((lhs.as_scalar(self)? * rhs)?.into()),
+                Div => Ok((lhs.as_scalar(self)? / rhs)?.into()),
                 Pow => {
                     let mut lhs = lhs;
-                    lhs.pow(rhs.display_value(self));
+                    lhs.pow(rhs.as_exponent(self)?)?;
                     Ok(lhs.into())
                 }
                 _ => Err(()),
@@ -324,23 +883,73 @@ impl Instance {
             (Meta(UnitClass(lhs)), Value(Scalar(rhs))) => match op {
                 Pow => {
                     let mut lhs = lhs;
-                    lhs.pow(rhs.display_value(self));
+                    lhs.pow(rhs.as_exponent(self)?)?;
                     Ok(lhs.into())
                 }
                 _ => Err(()),
             },
             (Value(Scalar(lhs)), Meta(UnitClass(rhs))) => match op {
-                IsClass => Ok((lhs.unit() == &rhs).into()),
+                IsClass => Ok(lhs.unit().matches_without_angles(&rhs, self).into()),
                 _ => Err(()),
             },
 
-            (Value(Scalar(lhs)), Value(Scalar(rhs))) => match op {
+            (Value(Matrix(lhs)), Meta(Unit(rhs))) => match op {
+                Mul | Div | InUnits => {
+                    lhs.map(|x| self.resolve_component(x.clone().into(), op, rhs.clone().into()))
+                }
+                _ => Err(()),
+            }
+            .map(Into::into),
+            (Meta(Unit(lhs)), Value(Matrix(rhs))) => match op {
+                Mul => {
+                    let lhs = lhs.as_scalar(self)?;
+                    rhs.map(|x| lhs.clone() * x.clone())
+                }
+                _ => Err(()),
+            }
+            .map(Into::into),
+            // Operators between scalars are broadcast over every component.
+            (Value(Matrix(lhs)), Value(Scalar(rhs))) => match op {
+                Index => lhs.index(self.index_value(&rhs)?),
                 IsClass | InUnits => Err(()),
-                Add => lhs.add(&rhs).map(Into::into),
-                Sub => lhs.sub(&rhs).map(Into::into),
-                Mul => Ok((lhs * rhs).into()),
-                Div => Ok((lhs / rhs).into()),
+                _ => lhs
+                    .map(|x| self.resolve_component(x.clone().into(), op, rhs.clone().into()))
+                    .map(Into::into),
+            },
+            (Value(Scalar(lhs)), Value(Matrix(rhs))) => match op {
+                IsClass | InUnits | Index => Err(()),
+                _ => rhs
+                    .map(|x| self.resolve_component(lhs.clone().into(), op, x.clone().into()))
+                    .map(Into::into),
+            },
+            (Value(Matrix(lhs)), Value(Matrix(rhs))) => match op {
+                Add => lhs.zip(&rhs, |lhs, rhs| lhs.add(rhs)),
+                Sub => lhs.zip(&rhs, |lhs, rhs| lhs.sub(rhs)),
+                // Two vectors can't be multiplied as matrices, so they are multiplied per
+                // component like lists.
+                Mul if lhs.is_vector() && rhs.is_vector() => {
+                    lhs.zip(&rhs, |lhs, rhs| lhs.clone() * rhs.clone())
+                }
+                Mul => lhs.product(&rhs),
+                Div => lhs.zip(&rhs, |lhs, rhs| lhs.clone() / rhs.clone()),
+                _ => Err(()),
+            }
+            .map(Into::into),
+            (Value(Matrix(..)), _) | (_, Value(Matrix(..))) => Err(()),
+
+            (Value(Scalar(lhs)), Value(Scalar(rhs))) => match op {
+                IsClass | InUnits | Index => Err(()),
+                Add | Sub => {
+                    let (lhs, rhs) = self.match_angles(lhs, rhs)?;
+                    match op {
+                        Add => lhs.add(&rhs).map(Into::into),
+                        _ => lhs.sub(&rhs).map(Into::into),
+                    }
+                }
+                Mul => Ok((lhs * rhs)?.into()),
+                Div => Ok((lhs / rhs)?.into()),
                 Pow => lhs.pow(&rhs, self).map(Into::into),
+                Polar => crate::scalar::Scalar::from_polar(&lhs, &rhs, self).map(Into::into),
             },
         }
     }
@@ -349,52 +958,143 @@ impl Instance {
         &self,
         expression: &Expression,
         context: AmbiguityResolutionContext,
+    ) -> Result<Data, ()> {
+        self.resolve_expression_with(expression, context, &Bindings::new())
+    }
+
+    /// Like resolve_expression, but the names in `bindings` refer to the given data instead of
+    /// any items with the same names.
+    pub fn resolve_expression_with(
+        &self,
+        expression: &Expression,
+        context: AmbiguityResolutionContext,
+        bindings: &Bindings,
     ) -> Result<Data, ()> {
         Ok(match &expression {
             Expression::NumericLiteral(value) => Scalar::new(
-                *value,
+                value.clone(),
                 Precision::Exact,
                 CompositeUnitClass::identity(),
                 CompositeUnit::identity(),
             )
             .into(),
+            Expression::ImaginaryLiteral(value) => Scalar::new(
+                Number::from_integer(0),
+                Precision::Exact,
+                CompositeUnitClass::identity(),
+                CompositeUnit::identity(),
+            )
+            .with_imaginary(value.clone())
+            .into(),
             Expression::StringLiteral(value) => value.clone().into(),
-            Expression::ApplyFunction { .. } => unimplemented!(),
+            Expression::ListLiteral(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.resolve_expression_with(item, Default::default(), bindings))
+                    .collect::<Result<Vec<_>, ()>>()?;
+                let scalars: Option<Vec<_>> = items
+                    .iter()
+                    .map(|item| match item {
+                        Data::Value(ValueData::Scalar(scalar)) => Some(scalar.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(scalars) = scalars {
+                    Matrix::vector(scalars).into()
+                } else {
+                    let rows = items
+                        .into_iter()
+                        .map(|item| match item {
+                            Data::Value(ValueData::Matrix(row)) => Ok(row),
+                            _ => Err(()),
+                        })
+                        .collect::<Result<Vec<_>, ()>>()?;
+                    Matrix::from_rows(rows)?.into()
+                }
+            }
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => {
+                let name = match &**function {
+                    Expression::LookupName(name) => name,
+                    _ => return Err(()),
+                };
+                // These need the expression they work on rather than its value.
+                if name == "derivative" || name == "integrate" {
+                    return self.resolve_calculus(name, arguments, bindings);
+                }
+                if name == "as_kind" {
+                    return self.resolve_as_kind(arguments, bindings);
+                }
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| {
+                        self.resolve_expression_with(argument, Default::default(), bindings)
+                    })
+                    .collect::<Result<Vec<_>, ()>>()?;
+                self.apply_function(name, arguments)?
+            }
             Expression::UnaryExpr(op, rhs) => {
-                let rhs = self.resolve_expression(rhs, context)?;
+                let rhs = self.resolve_expression_with(rhs, context, bindings)?;
                 self.resolve_unary_expression(*op, rhs)?
             }
             Expression::BinaryExpr(lhs, op, rhs) => {
-                let lhs = self.resolve_expression(lhs, context)?;
-                let rhs = self.resolve_expression(rhs, context)?;
+                let kind = match op {
+                    BinaryOp::IsClass => self.kind_named(rhs, bindings),
+                    _ => None,
+                };
+                let lhs = self.resolve_expression_with(lhs, context, bindings)?;
+                if let (Some((kind, _)), Data::Value(ValueData::Scalar(scalar))) = (kind, &lhs) {
+                    // A scalar of another kind isn't one of these even with the same unit class.
+                    if scalar.kind().is_some_and(|other| other != kind) {
+                        return Ok(false.into());
+                    }
+                }
+                let rhs = self.resolve_expression_with(rhs, context, bindings)?;
                 self.resolve_binary_expression(lhs, *op, rhs)?
             }
+            Expression::Range { start, end, step } => {
+                let scalar = |expression: &Expression| match self.resolve_expression_with(
+                    expression,
+                    Default::default(),
+                    bindings,
+                )? {
+                    Data::Value(ValueData::Scalar(scalar)) => Ok(scalar),
+                    _ => Err(()),
+                };
+                let (start, end) = (scalar(start)?, scalar(end)?);
+                let step = match step {
+                    Some(step) => scalar(step)?,
+                    // One of whatever unit the range starts in.
+                    None => Scalar::new(
+                        start.display_unit().base_ratio(self),
+                        Precision::Exact,
+                        start.unit().clone(),
+                        start.display_unit().clone(),
+                    ),
+                };
+                Matrix::range(&start, &end, &step)?.into()
+            }
             Expression::BuildEntity {
                 properties,
                 class_names,
             } => {
-                let mut classes = HashSet::new();
-                for name in class_names {
-                    if let Some(MetaData::EntityClass(class_id)) = self.meta_items.get(name) {
-                        classes.insert(*class_id);
-                    } else {
-                        return Err(());
-                    }
-                }
-                let properties = properties
-                    .iter()
-                    .map(|(name, value)| {
-                        self.resolve_expression(value, context)
-                            .map(|data| (name.clone(), data))
-                    })
-                    .collect::<Result<_, _>>()?;
-                Entity {
-                    properties,
-                    classes,
-                }
-                .into()
+                let entity = self.build_entity(properties, class_names, context, bindings)?;
+                self.complete_entity(entity)
+                    .map_err(|message| self.fail(message))?
+                    .into()
+            }
+            Expression::Annotated { value, annotation } => {
+                let value = self.resolve_expression_with(value, context, bindings)?;
+                self.check_annotation(value, annotation, bindings)
+                    .map_err(|message| self.fail(message))?
             }
+            Expression::LookupName(name) if bindings.contains_key(name) => bindings[name].clone(),
             Expression::LookupName(name) => {
+                if let Some(used_names) = self.used_names.borrow_mut().as_mut() {
+                    used_names.insert(name.clone());
+                }
                 let item = self.lookup_item(name);
                 if let Some(data) = context.resolve(&item) {
                     data
@@ -406,6 +1106,144 @@ impl Instance {
     }
 }
 
+impl Instance {
+    /// Builds an entity from exactly the classes and properties given, without the defaults of
+    /// its classes.
+    fn build_entity(
+        &self,
+        properties: &[(String, Expression)],
+        class_names: &[String],
+        context: AmbiguityResolutionContext,
+        bindings: &Bindings,
+    ) -> Result<Entity, ()> {
+        let mut classes = HashSet::new();
+        for name in class_names {
+            if let Some(MetaData::EntityClass(class_id)) = self.meta_items.get(name) {
+                classes.insert(*class_id);
+            } else {
+                return Err(());
+            }
+        }
+        let properties = properties
+            .iter()
+            .map(|(name, value)| {
+                self.resolve_expression_with(value, context, bindings)
+                    .map(|data| (name.clone(), data))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Entity {
+            properties,
+            classes,
+        })
+    }
+
+    /// Checks that the data has the unit class or entity class the annotation names. Fails with a
+    /// message naming the annotation and what was found instead.
+    pub fn check_annotation(
+        &self,
+        data: Data,
+        annotation: &Expression,
+        bindings: &Bindings,
+    ) -> Result<Data, String> {
+        let annotation = self.resolve_annotation(annotation, bindings)?;
+        self.check_type(data, &annotation)
+    }
+
+    /// Finds the unit class or entity class an annotation names.
+    pub fn resolve_annotation(
+        &self,
+        annotation: &Expression,
+        bindings: &Bindings,
+    ) -> Result<Annotation, String> {
+        let not_a_class = || format!("{} is not a unit class or entity class", annotation);
+        let expected = self
+            .resolve_expression_with(annotation, Default::default(), bindings)
+            .map_err(|_| not_a_class())?;
+        match expected {
+            Data::Meta(MetaData::UnitClass(..)) | Data::Meta(MetaData::EntityClass(..)) => (),
+            _ => return Err(not_a_class()),
+        }
+        let names_label = match annotation {
+            Expression::LookupName(name) if !bindings.contains_key(name) => {
+                self.lookup_item(name).as_label.is_some()
+            }
+            _ => false,
+        };
+        Ok(Annotation {
+            text: annotation.to_string(),
+            names_label,
+            expected,
+            kind: self
+                .kind_named(annotation, bindings)
+                .map(|(kind, _)| kind.to_owned()),
+        })
+    }
+
+    /// Checks that the data has the class an annotation names. A scalar annotated with a kind of
+    /// quantity must be of that kind, or of no kind in which case it becomes one.
+    pub fn check_type(&self, mut data: Data, annotation: &Annotation) -> Result<Data, String> {
+        let expected = &annotation.expected;
+        let mut expected_description = String::new();
+        expected.describe(&mut expected_description, self);
+        let kind = annotation.kind.as_deref();
+        let mut found = String::new();
+        let matches = match (expected, &data) {
+            (Data::Meta(MetaData::UnitClass(class)), Data::Value(ValueData::Scalar(scalar))) => {
+                match (kind, scalar.kind()) {
+                    (Some(kind), Some(other)) if scalar.unit() == class && kind != other => {
+                        found.push_str(other);
+                        false
+                    }
+                    _ => {
+                        scalar.unit().describe(&mut found, self);
+                        scalar.unit().matches_without_angles(class, self)
+                    }
+                }
+            }
+            (Data::Meta(MetaData::UnitClass(class)), Data::Value(ValueData::Matrix(matrix))) => {
+                let mismatch = matrix.components().iter().find(|x| x.unit() != class);
+                match mismatch {
+                    Some(component) => component.unit().describe(&mut found, self),
+                    None => found.push_str("a matrix"),
+                }
+                mismatch.is_none()
+            }
+            (Data::Meta(MetaData::EntityClass(class)), Data::Value(ValueData::Entity(entity))) => {
+                data.describe(&mut found, self);
+                self.entity_is_a(entity, *class)
+            }
+            _ => {
+                data.describe(&mut found, self);
+                false
+            }
+        };
+        if matches {
+            if let (
+                Data::Meta(MetaData::UnitClass(class)),
+                Data::Value(ValueData::Scalar(scalar)),
+            ) = (expected, &mut data)
+            {
+                if scalar.unit() != class {
+                    *scalar = scalar.without_angles(self).map_err(|_| {
+                        format!("{} has angles which can't be dropped", annotation.text)
+                    })?;
+                }
+            }
+            if let (Some(kind), Data::Value(ValueData::Scalar(scalar))) = (kind, &mut data) {
+                scalar.set_kind(Some(kind.to_owned()));
+            }
+            return Ok(data);
+        }
+        // Labels are named along with the class they stand for.
+        let expected = if annotation.names_label {
+            format!("{} ({})", annotation.text, expected_description)
+        } else {
+            expected_description
+        };
+        Err(format!("expected {}, found {}", expected, found))
+    }
+}
+
 macro_rules! make_properties_struct {
     (__impl store $into:ident from CompositeUnitClass) => {
         Data::Meta(MetaData::UnitClass($into))
@@ -489,10 +1327,51 @@ make_properties_struct! {
 }
 
 impl Instance {
+    /// If the statement fails, the instance is left unchanged. Statements which declare or change
+    /// an item are remembered along with the names they used, so that the item can be recomputed
+    /// when one of those changes.
     pub fn execute_statement(&mut self, statement: Statement) -> Result<(), ()> {
+        let declared = statement.declared_item().map(|(item, _)| item);
+        let updated = statement
+            .updated_value()
+            .and_then(|name| self.item_named(name));
+        let definition = if declared.is_some() || updated.is_some() {
+            Some(statement.clone())
+        } else {
+            None
+        };
+        self.error.replace(None);
+        let outer = self.used_names.replace(Some(BTreeSet::new()));
+        let result = self.run_statement(statement);
+        let used_names = self.used_names.replace(outer).unwrap_or_default();
+        result?;
+        if let (Some(item), Some(definition)) = (declared, definition.clone()) {
+            self.dependencies.insert(item.clone(), used_names);
+            self.definitions.insert(item, vec![definition]);
+        } else if let (Some(item), Some(definition)) = (updated, definition) {
+            // Without the statement that declared the value, replaying the change alone would
+            // lose everything else about it.
+            if let Some(definitions) = self.definitions.get_mut(&item) {
+                definitions.push(definition);
+            }
+            self.dependencies
+                .entry(item)
+                .or_default()
+                .extend(used_names);
+        }
+        Ok(())
+    }
+
+    fn run_statement(&mut self, statement: Statement) -> Result<(), ()> {
         match statement {
             Statement::MakeUnitClass(names) => {
-                self.add_unit_class(UnitClass { names })?;
+                self.add_unit_class(UnitClass {
+                    names,
+                    angle: false,
+                })?;
+            }
+            Statement::MakeAngleClass(names) => {
+                self.add_unit_class(UnitClass { names, angle: true })?;
             }
             Statement::MakeBaseUnit(names, properties) => {
                 let properties = self.resolve_expression(&properties, Default::default())?;
@@ -501,7 +1380,9 @@ impl Instance {
                     names,
                     class: properties.class,
                     symbol: properties.symbol,
-                    base_ratio: 1.0,
+                    base_ratio: Number::from_integer(1),
+                    prefix_origin: None,
+                    kind: None,
                 };
                 let prefix_type = match (properties.has_metric, properties.has_partial_metric) {
                     (false, false) => UnitPrefixType::None,
@@ -514,11 +1395,16 @@ impl Instance {
             Statement::MakeDerivedUnit(names, properties) => {
                 let properties = self.resolve_expression(&properties, Default::default())?;
                 let properties = DerivedUnitProperties::from_data(properties, self)?;
+                if properties.value.is_complex() {
+                    return Err(());
+                }
                 let unit = Unit {
                     names,
                     class: properties.value.unit().clone(),
                     symbol: properties.symbol,
-                    base_ratio: properties.value.raw_value(),
+                    base_ratio: properties.value.raw_value().clone(),
+                    prefix_origin: None,
+                    kind: properties.value.kind().map(str::to_owned),
                 };
                 let prefix_type = match (properties.has_metric, properties.has_partial_metric) {
                     (false, false) => UnitPrefixType::None,
@@ -528,14 +1414,17 @@ impl Instance {
                 };
                 self.add_unit(unit, prefix_type)?;
             }
-            Statement::MakeEntityClass(names, _properties) => {
-                let class = EntityClass { names };
-                self.add_entity_class(class)?;
+            Statement::MakeEntityClass(names, properties) => {
+                self.define_entity_class(names, &properties)?;
             }
             Statement::MakeLabel(names, value) => {
                 let data = self.resolve_expression(&value, Default::default())?;
                 self.declare_label(names, data)?;
             }
+            Statement::MakeKind(names, value) => {
+                let data = self.resolve_expression(&value, Default::default())?;
+                self.declare_kind(names, data)?;
+            }
             Statement::MakeValue(names, value) => {
                 let data = self.resolve_expression(&value, Default::default())?;
                 if let Data::Value(ValueData::Entity(data)) = data {
@@ -544,13 +1433,488 @@ impl Instance {
                     return Err(());
                 }
             }
+            Statement::Redefine(statement) => {
+                let (item, _) = statement.declared_item().ok_or(())?;
+                self.transaction(|instance| {
+                    instance.forget(item.kind, &item.name)?;
+                    instance.execute_statement(*statement)?;
+                    instance.recompute_dependants(&item)
+                })?;
+            }
+            Statement::Forget(kind, name) => {
+                let dependants = self.forget(kind, &name)?;
+                for dependant in dependants {
+                    self.warnings.push(format!(
+                        "{} was defined in terms of {} {}, which has been forgotten. It keeps its \
+                        old value.",
+                        dependant, kind, name
+                    ));
+                }
+            }
+            Statement::SetProperty(value, property, expression) => {
+                let data = self.resolve_expression(&expression, Default::default())?;
+                let update = |entity: &mut Entity| {
+                    entity.properties.insert(property, data);
+                };
+                self.update_value(&value, update)?;
+            }
+            Statement::UpdateValue(value, expression) => {
+                let additions = match &expression {
+                    Expression::BuildEntity {
+                        properties,
+                        class_names,
+                    } => self.build_entity(
+                        properties,
+                        class_names,
+                        Default::default(),
+                        &Default::default(),
+                    )?,
+                    _ => return Err(()),
+                };
+                let update = |entity: &mut Entity| {
+                    entity.classes.extend(additions.classes);
+                    entity.properties.extend(additions.properties);
+                };
+                self.update_value(&value, update)?;
+            }
+            Statement::Export(format, path) => {
+                self.unit_table().write(format, &path).map_err(|error| {
+                    self.fail(format!("could not export to {}: {}", path, error))
+                })?;
+            }
+            Statement::Import(format, path) => {
+                let contents = std::fs::read_to_string(path).map_err(|_| ())?;
+                let report = match format {
+                    ImportFormat::Ucum => {
+                        self.import_ucum(&contents, &BaseDimension::ucum_defaults())?
+                    }
+                    ImportFormat::Qudt => {
+                        self.import_qudt(&contents, &BaseDimension::qudt_defaults())?
+                    }
+                };
+                self.import_reports.push(report);
+            }
             Statement::Show(value) => {
                 let value = self.resolve_expression(&value, Default::default())?;
                 let mut description = String::new();
                 value.describe(&mut description, self);
                 println!("{}", description);
             }
+            Statement::Simplify(expression) => {
+                println!("{}", crate::symbolic::simplify(&expression));
+            }
+            Statement::Rearrange(lhs, rhs, variable) => {
+                let solution = crate::symbolic::rearrange(&lhs, &rhs, &variable)?;
+                println!("{} = {}", variable, solution);
+            }
+            Statement::Solve(lhs, rhs, variable, start) => {
+                let solution = self.solve(&lhs, &rhs, &variable, &start)?;
+                let mut description = String::new();
+                solution.describe(&mut description, self);
+                println!("{} = {}", variable, description);
+            }
+            Statement::SolveSystem(equations, unknowns) => {
+                let solutions = self
+                    .solve_system(&equations, &unknowns)
+                    .map_err(|error| self.fail(error.to_string()))?;
+                for ((name, _), solution) in unknowns.iter().zip(solutions) {
+                    let mut description = String::new();
+                    solution.describe(&mut description, self);
+                    println!("{} = {}", name, description);
+                }
+            }
+            Statement::Table(column, expression, sweeps, format) => {
+                let table = self.table(&column, &expression, &sweeps)?;
+                print!("{}", table.render(format));
+            }
+            Statement::DimensionlessGroups(names) => {
+                for group in self.dimensionless_groups(&names)? {
+                    println!("{}", crate::analysis::describe_group(&group));
+                }
+            }
+            Statement::SolveForUnits(target, names) => {
+                for combination in self.solve_for_units(&target, &names)? {
+                    println!("{}", crate::analysis::describe_group(&combination));
+                }
+            }
+            Statement::Plot(series, variable, scales, path) => {
+                let plot = self.plot(&series, &variable, scales)?;
+                std::fs::write(path, plot.to_svg()).map_err(|_| ())?;
+            }
         }
         Ok(())
     }
 }
+
+/// A saved copy of everything an instance has defined, which the instance can later be rolled
+/// back to.
+#[derive(Clone, Debug)]
+pub struct Checkpoint(Instance);
+
+impl Instance {
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.clone())
+    }
+
+    /// Discards every change made since the checkpoint was taken.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        // The explanation of the failure being rolled back is kept.
+        let error = self.error.take();
+        *self = checkpoint.0;
+        self.error.replace(error);
+    }
+
+    /// Runs the body, undoing all of its changes to the instance if it returns an error. This
+    /// means a failed operation always leaves the instance exactly as it was before.
+    pub fn transaction<T>(
+        &mut self,
+        body: impl FnOnce(&mut Self) -> Result<T, ()>,
+    ) -> Result<T, ()> {
+        let checkpoint = self.checkpoint();
+        let result = body(self);
+        if result.is_err() {
+            self.rollback(checkpoint);
+        }
+        result
+    }
+
+    /// Executes the statements in order. If any of them fail, none of them will have any effect.
+    pub fn execute_statements(
+        &mut self,
+        statements: impl IntoIterator<Item = Statement>,
+    ) -> Result<(), ()> {
+        self.transaction(|instance| {
+            for statement in statements {
+                instance.execute_statement(statement)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{evaluate, make_instance, run};
+
+    #[test]
+    fn failed_script_changes_nothing() {
+        let mut instance = Instance::new();
+        run(&mut instance, "make unit_class called Length").unwrap();
+        let result = run(
+            &mut instance,
+            r#"
+            make unit_class called Time
+            make label called Speed for Length / Time
+            make unit_class called Length
+            "#,
+        );
+        assert!(result.is_err());
+        assert!(instance.lookup_item(&"Length".to_owned()).as_meta.is_some());
+        assert!(instance.lookup_item(&"Time".to_owned()).as_meta.is_none());
+        assert!(instance.lookup_item(&"Speed".to_owned()).as_label.is_none());
+        // The rolled back names are free to be declared again.
+        run(&mut instance, "make unit_class called Time").unwrap();
+    }
+
+    const UNITS: &str = r#"
+        make derived_unit called Foot, Feet { symbol: "ft", value: 0.3048 * Meters, }
+        make label called Stride for 2 * Feet
+    "#;
+
+    fn describe(instance: &Instance, name: &str) -> String {
+        let data = instance
+            .resolve_expression(&Expression::LookupName(name.to_owned()), Default::default())
+            .unwrap();
+        let mut description = String::new();
+        data.describe(&mut description, instance);
+        description
+    }
+
+    #[test]
+    fn forget_unit_and_its_prefixes() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        let dependants = instance.forget_unit(&"Meters".to_owned()).unwrap();
+        let foot = ItemRef {
+            kind: ItemKind::Unit,
+            name: "Foot".to_owned(),
+        };
+        assert_eq!(dependants, vec![foot]);
+        for name in &["Meter", "Meters", "Kilometer", "Millimeters"] {
+            assert!(instance.lookup_item(&name.to_string()).as_meta.is_none());
+        }
+        // Things defined in terms of the unit keep working.
+        assert_eq!(describe(&instance, "Stride"), "2ft");
+        assert!(instance.forget_unit(&"Meter".to_owned()).is_err());
+        // The names can be used again.
+        run(
+            &mut instance,
+            r#"make base_unit called Meter { class: Length, symbol: "m", metric, }"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn forgetting_warns_about_dependants() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        run(&mut instance, "forget label Stride").unwrap();
+        assert!(instance.take_warnings().is_empty());
+        run(&mut instance, "forget unit Meter").unwrap();
+        assert_eq!(
+            instance.take_warnings(),
+            vec![
+                "unit Foot was defined in terms of unit Meter, which has been forgotten. It keeps \
+                its old value."
+            ]
+        );
+        assert!(instance.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn redefine_label() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        run(&mut instance, "redefine label called Stride for 3 * Feet").unwrap();
+        assert_eq!(describe(&instance, "Stride"), "3ft");
+        // Only existing items can be redefined.
+        assert!(run(&mut instance, "redefine label called Step for 1 * Feet").is_err());
+        assert!(instance.lookup_item(&"Step".to_owned()).as_label.is_none());
+        run(&mut instance, "forget label Stride").unwrap();
+        assert!(instance
+            .lookup_item(&"Stride".to_owned())
+            .as_label
+            .is_none());
+    }
+
+    fn parse(expression: &str) -> Expression {
+        let (rest, expression) = crate::expression::parse_expression(expression).unwrap();
+        assert_eq!(rest, "");
+        expression
+    }
+
+    #[test]
+    fn rational_powers() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        assert_eq!(evaluate(&instance, "(4 * Meters^2)^0.5").unwrap(), "2m");
+        assert_eq!(
+            evaluate(&instance, "((8 * Meters)^(1/3))^3 is Length").unwrap(),
+            "true"
+        );
+        assert_eq!(evaluate(&instance, "Length^(1/2)").unwrap(), "Length^(1/2)");
+        assert_eq!(
+            evaluate(&instance, "2^0.5").unwrap(),
+            2f64.sqrt().to_string()
+        );
+        assert!(evaluate(&instance, "(2 * Meters)^3.14159").is_err());
+        assert!(evaluate(&instance, "2^(1 * Meter)").is_err());
+    }
+
+    #[test]
+    fn annotations() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        run(
+            &mut instance,
+            r#"
+            make label called Velocity for Length / Time
+            make entity_class called Car
+            make value called MyCar { Car, speed: Velocity = 30 * Meters / Second, }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            evaluate(&instance, "{ trip: Length / Time = 2 * Feet / Second }").unwrap(),
+            "{ trip: 2ft/s }"
+        );
+        assert_eq!(
+            evaluate(&instance, "{ car: Car = MyCar }").unwrap(),
+            "{ car: { Car, speed: 30m/s } }"
+        );
+        // Library callers can find out why an expression failed.
+        assert!(evaluate(&instance, "{ trip: Velocity = 2 * Feet }").is_err());
+        assert_eq!(
+            instance.take_error().unwrap(),
+            "expected Velocity (Length/Time), found Length"
+        );
+        assert_eq!(instance.take_error(), None);
+        let check = |source: &str, annotation: &str| {
+            let data = instance.resolve_expression(&parse(source), Default::default());
+            instance.check_annotation(data.unwrap(), &parse(annotation), &Bindings::new())
+        };
+        assert_eq!(
+            check("30 * Meters", "Velocity").unwrap_err(),
+            "expected Velocity (Length/Time), found Length"
+        );
+        assert_eq!(
+            check("2 * Seconds", "Length").unwrap_err(),
+            "expected Length, found Time"
+        );
+        assert_eq!(
+            check("2 * Seconds", "Length / Time").unwrap_err(),
+            "expected Length/Time, found Time"
+        );
+        assert_eq!(
+            check("2 * Seconds", "Length * Time").unwrap_err(),
+            "expected Length * Time, found Time"
+        );
+        assert!(check("MyCar", "Velocity").is_err());
+        assert!(check("2 * Seconds", "Seconds").is_err());
+        assert!(run(
+            &mut instance,
+            "make value called Slow { Car, speed: Velocity = 30 * Meters, }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn update_values() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        run(
+            &mut instance,
+            r#"
+            make entity_class called Circle { Radius: Length }
+            make entity_class called Pizza { Circle, Topping: "none" }
+            make value called MyPizza, Dinner { Pizza, Radius: 1 * Feet }
+            set MyPizza.Radius = 0.5 * Feet
+            Dinner += { Topping: "cheese", Slices: 8 }
+            "#,
+        )
+        .unwrap();
+        let pizza = |instance: &Instance, property: &str| {
+            let entity = instance.values.get(&"MyPizza".to_owned()).unwrap();
+            let mut description = String::new();
+            entity.properties[property].describe(&mut description, instance);
+            description
+        };
+        assert_eq!(pizza(&instance, "Radius"), "0.5ft");
+        assert_eq!(pizza(&instance, "Topping"), "cheese");
+        assert_eq!(pizza(&instance, "Slices"), "8");
+        // Changes are checked against the value's classes.
+        assert!(run(&mut instance, "set MyPizza.Radius = 2").is_err());
+        assert!(run(&mut instance, "MyPizza += { Circle, Radius: 2 }").is_err());
+        assert!(run(&mut instance, "set Nothing.Radius = 1 * Feet").is_err());
+        assert_eq!(pizza(&instance, "Radius"), "0.5ft");
+        run(&mut instance, "set MyPizza.Radius = Stride").unwrap();
+        assert_eq!(
+            instance.dependants_of(&["Stride".to_owned()]),
+            vec![ItemRef {
+                kind: ItemKind::Value,
+                name: "MyPizza".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn recompute_dependants() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        run(
+            &mut instance,
+            r#"
+            make label called Leg for 2 * Stride
+            make label called Walk for Leg + Stride
+            make entity_class called Walker
+            make value called Me { Walker, stride: Stride }
+            "#,
+        )
+        .unwrap();
+        run(&mut instance, "redefine label called Stride for 3 * Feet").unwrap();
+        assert_eq!(describe(&instance, "Leg"), "6ft");
+        assert_eq!(describe(&instance, "Walk"), "9ft");
+        let me = instance.values.get(&"Me".to_owned()).unwrap();
+        let mut stride = String::new();
+        me.properties["stride"].describe(&mut stride, &instance);
+        assert_eq!(stride, "3ft");
+        // Redefining a unit recomputes what was measured in it.
+        run(
+            &mut instance,
+            r#"redefine derived_unit called Foot, Feet { symbol: "ft", value: 0.5 * Meters, }"#,
+        )
+        .unwrap();
+        assert_eq!(evaluate(&instance, "Walk in Meters").unwrap(), "4.5m");
+        // Replaying a change to a value doesn't recompute its dependants a second time.
+        run(
+            &mut instance,
+            r#"
+            set Me.stride = Leg
+            make label called Check for Stride + 1 * Feet
+            "#,
+        )
+        .unwrap();
+        run(&mut instance, "redefine label called Stride for 1 * Feet").unwrap();
+        assert_eq!(describe(&instance, "Leg"), "2ft");
+        assert_eq!(describe(&instance, "Check"), "2ft");
+        // If one dependant can't be recomputed, the change is refused.
+        assert!(run(&mut instance, "redefine label called Stride for 3").is_err());
+        assert_eq!(
+            instance.take_error().unwrap(),
+            "label Check is defined in terms of label Stride and could not be recomputed after \
+            it changed"
+        );
+        assert_eq!(describe(&instance, "Stride"), "1ft");
+        assert_eq!(describe(&instance, "Leg"), "2ft");
+        assert_eq!(describe(&instance, "Check"), "2ft");
+    }
+
+    #[test]
+    fn dependency_graph() {
+        let mut instance = make_instance();
+        run(&mut instance, UNITS).unwrap();
+        run(
+            &mut instance,
+            r#"
+            make label called Leg, Legs for 2 * Stride
+            make label called Walk for Legs + Stride
+            "#,
+        )
+        .unwrap();
+        let label = |name: &str| ItemRef {
+            kind: ItemKind::Label,
+            name: name.to_owned(),
+        };
+        let unit = |name: &str| ItemRef {
+            kind: ItemKind::Unit,
+            name: name.to_owned(),
+        };
+        assert_eq!(
+            instance.dependencies_of(&label("Walk")),
+            vec![label("Leg"), label("Stride")]
+        );
+        assert_eq!(
+            instance.dependencies_of(&label("Stride")),
+            vec![unit("Foot")]
+        );
+        assert_eq!(
+            instance.direct_dependants(&label("Leg")),
+            vec![label("Walk")]
+        );
+        assert_eq!(
+            instance.recomputation_order(&unit("Foot")).unwrap(),
+            vec![label("Stride"), label("Leg"), label("Walk")]
+        );
+        // Stride can't be defined in terms of something that depends on it.
+        assert!(run(&mut instance, "redefine label called Stride for Walk / 3").is_err());
+        assert_eq!(
+            instance.take_error().unwrap(),
+            "label Stride would depend on itself: label Stride -> label Leg -> label Walk -> \
+            label Stride"
+        );
+        assert_eq!(describe(&instance, "Stride"), "2ft");
+        assert_eq!(describe(&instance, "Walk"), "6ft");
+    }
+
+    #[test]
+    fn rollback_to_checkpoint() {
+        let mut instance = Instance::new();
+        run(&mut instance, "make unit_class called Length").unwrap();
+        let checkpoint = instance.checkpoint();
+        run(&mut instance, "make unit_class called Time").unwrap();
+        instance.rollback(checkpoint);
+        assert!(instance.lookup_item(&"Time".to_owned()).as_meta.is_none());
+        assert!(instance.lookup_item(&"Length".to_owned()).as_meta.is_some());
+    }
+}
@@ -0,0 +1,422 @@
+//! Intervals which are guaranteed to contain the true value of a quantity. Whenever the result of
+//! an operation on a bound can't be represented exactly, the bound is rounded outward by one unit
+//! in the last place so that the interval never shrinks past the true result.
+
+use crate::units::Exponent;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::{
+    f64::consts::{FRAC_PI_2, PI, TAU},
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interval {
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::float"))]
+    pub lower: f64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::float"))]
+    pub upper: f64,
+}
+
+fn sum_is_exact(a: f64, b: f64, sum: f64) -> bool {
+    if !sum.is_finite() {
+        return !a.is_finite() || !b.is_finite();
+    }
+    // Knuth's TwoSum, which finds the rounding error of the addition exactly.
+    let b_part = sum - a;
+    let error = (a - (sum - b_part)) + (b - b_part);
+    error == 0.0
+}
+
+fn product_is_exact(a: f64, b: f64, product: f64) -> bool {
+    if !product.is_finite() {
+        return !a.is_finite() || !b.is_finite();
+    }
+    a.mul_add(b, -product) == 0.0
+}
+
+fn quotient_is_exact(a: f64, b: f64, quotient: f64) -> bool {
+    if !quotient.is_finite() {
+        return !a.is_finite() || b == 0.0;
+    }
+    quotient.mul_add(b, -a) == 0.0
+}
+
+fn down(value: f64, exact: bool) -> f64 {
+    if exact {
+        value
+    } else {
+        value.next_down()
+    }
+}
+
+fn up(value: f64, exact: bool) -> f64 {
+    if exact {
+        value
+    } else {
+        value.next_up()
+    }
+}
+
+fn add_down(a: f64, b: f64) -> f64 {
+    let sum = a + b;
+    down(sum, sum_is_exact(a, b, sum))
+}
+
+fn add_up(a: f64, b: f64) -> f64 {
+    let sum = a + b;
+    up(sum, sum_is_exact(a, b, sum))
+}
+
+fn mul_down(a: f64, b: f64) -> f64 {
+    let product = a * b;
+    down(product, product_is_exact(a, b, product))
+}
+
+fn mul_up(a: f64, b: f64) -> f64 {
+    let product = a * b;
+    up(product, product_is_exact(a, b, product))
+}
+
+pub(crate) fn div_down(a: f64, b: f64) -> f64 {
+    let quotient = a / b;
+    down(quotient, quotient_is_exact(a, b, quotient))
+}
+
+pub(crate) fn div_up(a: f64, b: f64) -> f64 {
+    let quotient = a / b;
+    up(quotient, quotient_is_exact(a, b, quotient))
+}
+
+/// Returns true if `offset + k * period` lies in the interval for some integer k.
+fn contains_periodic_point(interval: Interval, offset: f64, period: f64) -> bool {
+    // Since the constants are themselves rounded, err on the side of finding a point.
+    let k = ((interval.lower - offset) / period).floor();
+    let point = offset + k * period;
+    let slack = 1e-12 * (1.0 + point.abs());
+    point >= interval.lower - slack || point + period <= interval.upper + slack
+}
+
+impl Interval {
+    pub const ENTIRE: Self = Self {
+        lower: f64::NEG_INFINITY,
+        upper: f64::INFINITY,
+    };
+
+    /// Returns Err(()) if the lower bound is above the upper bound.
+    pub fn new(lower: f64, upper: f64) -> Result<Self, ()> {
+        if lower <= upper {
+            Ok(Self { lower, upper })
+        } else {
+            Err(())
+        }
+    }
+
+    pub fn point(value: f64) -> Self {
+        Self {
+            lower: value,
+            upper: value,
+        }
+    }
+
+    /// The interval `value ± radius`.
+    pub fn around(value: f64, radius: f64) -> Self {
+        let radius = radius.abs();
+        Self {
+            lower: add_down(value, -radius),
+            upper: add_up(value, radius),
+        }
+    }
+
+    pub fn contains(self, value: f64) -> bool {
+        self.lower <= value && value <= self.upper
+    }
+
+    fn combine(
+        self,
+        other: Self,
+        lower: impl Fn(f64, f64) -> f64,
+        upper: impl Fn(f64, f64) -> f64,
+    ) -> Self {
+        let pairs = [
+            (self.lower, other.lower),
+            (self.lower, other.upper),
+            (self.upper, other.lower),
+            (self.upper, other.upper),
+        ];
+        Self {
+            // f64::min and max skip the NaNs produced by things like zero times infinity.
+            lower: pairs
+                .iter()
+                .map(|&(a, b)| lower(a, b))
+                .fold(f64::INFINITY, f64::min),
+            upper: pairs
+                .iter()
+                .map(|&(a, b)| upper(a, b))
+                .fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    /// Raises every non-negative number in the interval to a positive integer power.
+    fn powi_non_negative(self, exp: u32) -> Self {
+        let mut result = Self::point(1.0);
+        for _ in 0..exp {
+            result = Self {
+                lower: mul_down(result.lower, self.lower),
+                upper: mul_up(result.upper, self.upper),
+            };
+        }
+        result
+    }
+
+    /// Returns Err(()) if some number in the interval can't be raised to the power, like a
+    /// negative number to the power of one half.
+    pub fn pow(self, exp: Exponent) -> Result<Self, ()> {
+        if exp.is_zero() {
+            return Ok(Self::point(1.0));
+        }
+        if exp.is_negative() {
+            return Ok(Self::point(1.0) / self.pow(-exp)?);
+        }
+        if exp.is_integer() {
+            let power = exp.to_integer().to_u32().ok_or(())?;
+            let odd = power % 2 == 1;
+            return Ok(if self.lower >= 0.0 {
+                self.powi_non_negative(power)
+            } else if self.upper <= 0.0 {
+                let result = -self.powi_non_negative(power);
+                if odd {
+                    -result
+                } else {
+                    result
+                }
+            } else if odd {
+                let below = Self::new(0.0, -self.lower)?.powi_non_negative(power);
+                let above = Self::new(0.0, self.upper)?.powi_non_negative(power);
+                Self {
+                    lower: -below.upper,
+                    upper: above.upper,
+                }
+            } else {
+                let magnitude = self.lower.abs().max(self.upper);
+                Self::new(0.0, magnitude)?.powi_non_negative(power)
+            });
+        }
+        if self.lower < 0.0 {
+            return Err(());
+        }
+        if exp == Exponent::new(1, 2) {
+            let root = |value: f64, outward: fn(f64, bool) -> f64| {
+                let result = value.sqrt();
+                outward(result, result.mul_add(result, -value) == 0.0)
+            };
+            return Ok(Self {
+                lower: root(self.lower, down).max(0.0),
+                upper: root(self.upper, up),
+            });
+        }
+        self.powf(exp.to_f64().ok_or(())?)
+    }
+
+    /// Returns Err(()) if the interval contains negative numbers.
+    pub fn powf(self, exp: f64) -> Result<Self, ()> {
+        if self.lower < 0.0 {
+            return Err(());
+        }
+        let result = if exp >= 0.0 {
+            self.map_increasing(|value| value.powf(exp))
+        } else {
+            -self.map_increasing(|value| (-value).powf(exp))
+        };
+        Ok(result.clamp_below(0.0))
+    }
+
+    /// Applies a function which never decreases. Results are always widened by one unit in the
+    /// last place since library functions like exp are not guaranteed to round correctly.
+    pub fn map_increasing(self, f: impl Fn(f64) -> f64) -> Self {
+        Self {
+            lower: f(self.lower).next_down(),
+            upper: f(self.upper).next_up(),
+        }
+    }
+
+    fn clamp_below(self, minimum: f64) -> Self {
+        Self {
+            lower: self.lower.max(minimum),
+            upper: self.upper.max(minimum),
+        }
+    }
+
+    fn clamp_to_unit(self) -> Self {
+        Self {
+            lower: self.lower.max(-1.0),
+            upper: self.upper.min(1.0),
+        }
+    }
+
+    pub fn abs(self) -> Self {
+        if self.lower >= 0.0 {
+            self
+        } else if self.upper <= 0.0 {
+            -self
+        } else {
+            Self {
+                lower: 0.0,
+                upper: self.upper.max(-self.lower),
+            }
+        }
+    }
+
+    pub fn exp(self) -> Self {
+        self.map_increasing(f64::exp).clamp_below(0.0)
+    }
+
+    /// Returns Err(()) if the interval contains no positive numbers.
+    pub fn ln(self) -> Result<Self, ()> {
+        if self.upper <= 0.0 {
+            return Err(());
+        }
+        let lower = if self.lower <= 0.0 {
+            f64::NEG_INFINITY
+        } else {
+            self.lower.ln().next_down()
+        };
+        Ok(Self {
+            lower,
+            upper: self.upper.ln().next_up(),
+        })
+    }
+
+    /// Also true if the width is undefined, like for an interval from -infinity to infinity.
+    fn is_at_least_as_wide_as(self, width: f64) -> bool {
+        let own_width = self.upper - self.lower;
+        own_width.is_nan() || own_width >= width
+    }
+
+    /// Finds the range of a function with a period of 2 pi, given where it reaches its maximum
+    /// and minimum of 1 and -1 and that it is monotonic in between.
+    fn periodic(self, f: impl Fn(f64) -> f64, maximum_at: f64, minimum_at: f64) -> Self {
+        if self.is_at_least_as_wide_as(TAU) {
+            return Self::new(-1.0, 1.0).unwrap();
+        }
+        let (a, b) = (f(self.lower), f(self.upper));
+        let mut result = Self {
+            lower: a.min(b).next_down(),
+            upper: a.max(b).next_up(),
+        };
+        if contains_periodic_point(self, maximum_at, TAU) {
+            result.upper = 1.0;
+        }
+        if contains_periodic_point(self, minimum_at, TAU) {
+            result.lower = -1.0;
+        }
+        result.clamp_to_unit()
+    }
+
+    pub fn sin(self) -> Self {
+        self.periodic(f64::sin, FRAC_PI_2, -FRAC_PI_2)
+    }
+
+    pub fn cos(self) -> Self {
+        self.periodic(f64::cos, 0.0, PI)
+    }
+
+    pub fn tan(self) -> Self {
+        if self.is_at_least_as_wide_as(PI) || contains_periodic_point(self, FRAC_PI_2, PI) {
+            return Self::ENTIRE;
+        }
+        self.map_increasing(f64::tan)
+    }
+}
+
+impl Add for Interval {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            lower: add_down(self.lower, rhs.lower),
+            upper: add_up(self.upper, rhs.upper),
+        }
+    }
+}
+
+impl Sub for Interval {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Mul for Interval {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        self.combine(rhs, mul_down, mul_up)
+    }
+}
+
+impl Div for Interval {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        if rhs.contains(0.0) {
+            return Self::ENTIRE;
+        }
+        self.combine(rhs, div_down, div_up)
+    }
+}
+
+impl Neg for Interval {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self {
+            lower: -self.upper,
+            upper: -self.lower,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_round_outward() {
+        let tenth = Interval::point(0.1);
+        let sum = tenth + Interval::point(0.2);
+        // 0.1 + 0.2 can't be represented exactly, so the bounds must differ.
+        assert!(sum.lower < sum.upper);
+        assert!(sum.contains(0.1 + 0.2));
+        // Exact operations are left alone.
+        let exact = Interval::new(1.0, 2.0).unwrap() * Interval::point(0.5);
+        assert_eq!(exact, Interval::new(0.5, 1.0).unwrap());
+    }
+
+    #[test]
+    fn signs_and_powers() {
+        let interval = Interval::new(-2.0, 3.0).unwrap();
+        assert_eq!(interval * interval, Interval::new(-6.0, 9.0).unwrap());
+        assert_eq!(
+            interval.pow(2.into()).unwrap(),
+            Interval::new(0.0, 9.0).unwrap()
+        );
+        assert_eq!(
+            interval.pow(3.into()).unwrap(),
+            Interval::new(-8.0, 27.0).unwrap()
+        );
+        assert_eq!(Interval::point(1.0) / interval, Interval::ENTIRE);
+        assert!(interval.pow(Exponent::new(1, 2)).is_err());
+        assert_eq!(
+            Interval::new(4.0, 9.0)
+                .unwrap()
+                .pow(Exponent::new(1, 2))
+                .unwrap(),
+            Interval::new(2.0, 3.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn trigonometry() {
+        let sine = Interval::new(0.0, 3.0).unwrap().sin();
+        assert_eq!(sine.upper, 1.0);
+        assert!(sine.lower <= 0.0 && sine.lower > -1e-300);
+        assert_eq!(Interval::new(1.0, 2.0).unwrap().tan(), Interval::ENTIRE);
+    }
+}
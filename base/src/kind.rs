@@ -0,0 +1,98 @@
+//! Kinds of quantity, like torque and energy, which share a unit class but shouldn't be mixed up.
+//! `make kind called Torque for Force * Length` declares one, `as_kind(x, Torque)` tags a value
+//! with it and `drop_kind(x)` removes the tag again.
+
+use crate::{
+    data::{Data, MetaData, ValueData},
+    expression::Expression,
+    prelude::*,
+};
+
+impl Instance {
+    /// Resolves `as_kind(value, Kind)`. The value must already have the kind's unit class.
+    pub(crate) fn resolve_as_kind(
+        &self,
+        arguments: &[Expression],
+        bindings: &Bindings,
+    ) -> Result<Data, ()> {
+        let (value, kind) = match arguments {
+            [value, kind] => (value, kind),
+            _ => return Err(()),
+        };
+        let (kind, class) = self.kind_named(kind, bindings).ok_or(())?;
+        let tag = |scalar: &Scalar| {
+            if scalar.unit() != class {
+                return Err(());
+            }
+            let mut scalar = scalar.clone();
+            scalar.set_kind(Some(kind.to_owned()));
+            Ok(scalar)
+        };
+        match self.resolve_expression_with(value, Default::default(), bindings)? {
+            Data::Value(ValueData::Scalar(scalar)) => Ok(tag(&scalar)?.into()),
+            Data::Value(ValueData::Matrix(matrix)) => Ok(matrix.map(tag)?.into()),
+            Data::Meta(MetaData::Unit(unit)) => Ok(tag(&unit.as_scalar(self)?)?.into()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expression::parse_expression,
+        test_util::{evaluate, instance_with},
+    };
+
+    const DEFINITIONS: &str = r#"
+        make unit_class called Mass
+        make base_unit called Gram, Grams { class: Mass, symbol: "g", metric, }
+        make derived_unit called Newton, Newtons {
+            symbol: "N", value: 1 * Kilograms * Meters / Seconds^2, metric,
+        }
+        make kind called Energy for Mass * Length^2 / Time^2
+        make kind called Torque for Mass * Length^2 / Time^2
+        make derived_unit called Joule, Joules {
+            symbol: "J", value: as_kind(Newton * Meter, Energy), metric,
+        }
+        make label called torque for as_kind(3 * Newtons * Meters, Torque)
+    "#;
+
+    #[test]
+    fn kinds_are_kept_apart() {
+        let instance = instance_with(DEFINITIONS);
+        assert!(evaluate(&instance, "torque + 2 * Joules").is_err());
+        assert!(evaluate(&instance, "torque in Joules").is_err());
+        assert_eq!(
+            evaluate(&instance, "as_kind(drop_kind(torque), Energy) in Joules").unwrap(),
+            "3J"
+        );
+        assert_eq!(
+            evaluate(&instance, "(2 * Newtons * Meters + 2 * Joules) in Joules").unwrap(),
+            "4J"
+        );
+        assert_eq!(evaluate(&instance, "torque is Torque").unwrap(), "true");
+        assert_eq!(evaluate(&instance, "torque is Energy").unwrap(), "false");
+        assert!(evaluate(&instance, "as_kind(3 * Newtons, Torque)").is_err());
+        assert!(evaluate(&instance, "{ work: Energy = torque }").is_err());
+        assert!(evaluate(&instance, "{ work: Energy = 2 * Newtons * Meters }").is_ok());
+    }
+
+    #[test]
+    fn arithmetic_keeps_kinds() {
+        let instance = instance_with(DEFINITIONS);
+        let kind = |source: &str| {
+            let (_, expression) = parse_expression(source).unwrap();
+            match instance.resolve_expression(&expression, Default::default()) {
+                Ok(Data::Value(ValueData::Scalar(scalar))) => scalar.kind().map(str::to_owned),
+                _ => panic!("{} is not a scalar", source),
+            }
+        };
+        assert_eq!(kind("5 * Joules * 2").as_deref(), Some("Energy"));
+        assert_eq!(kind("torque / 3").as_deref(), Some("Torque"));
+        assert_eq!(kind("torque * 2 * Seconds"), None);
+        assert_eq!(kind("torque / torque"), None);
+        assert_eq!(kind("1 * Kilojoules").as_deref(), Some("Energy"));
+    }
+}
@@ -1,14 +1,38 @@
+// Errors are reported as Err(()) throughout the crate for now.
+#![allow(clippy::result_unit_err)]
+
+pub mod analysis;
+pub mod angle;
+pub mod calculus;
 pub mod data;
 pub mod entity;
+pub mod export;
 pub mod expression;
+pub mod functions;
+pub mod import;
 pub mod instance;
+pub mod interval;
+pub mod kind;
+pub mod matrix;
+pub mod number;
+pub mod plot;
 pub mod scalar;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+pub mod solve;
 pub mod statement;
 mod storage;
+pub mod symbolic;
+pub mod table;
+#[cfg(test)]
+pub mod test_util;
 pub mod units;
 
 pub mod prelude {
     pub use crate::instance::*;
+    pub use crate::interval::Interval;
+    pub use crate::matrix::Matrix;
+    pub use crate::number::*;
     pub use crate::scalar::*;
     pub use crate::units::*;
     pub use std::cell::RefCell;
@@ -0,0 +1,291 @@
+//! Vectors and matrices of scalars. Every component keeps its own unit, so a matrix can mix
+//! units, like an inertia tensor written in different units per axis. Vectors are stored as
+//! matrices with a single column, and double as lists for aggregates like `sum` and `mean`.
+
+use crate::{
+    data::{Data, Describe},
+    prelude::*,
+};
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    /// Row-major.
+    components: Vec<Scalar>,
+}
+
+/// Adds up scalars which all have the same unit class. Fails if there are none.
+pub(crate) fn sum(mut terms: impl Iterator<Item = Scalar>) -> Result<Scalar, ()> {
+    let first = terms.next().ok_or(())?;
+    terms.try_fold(first, |total, term| total.add(&term))
+}
+
+/// The most scalars a range can produce.
+const MAX_RANGE_LENGTH: usize = 100_000;
+
+/// A dimensionless scalar holding a number of items.
+pub(crate) fn count(count: usize) -> Scalar {
+    Scalar::new(
+        Number::from_integer(count as i64),
+        Precision::Exact,
+        CompositeUnitClass::identity(),
+        CompositeUnit::identity(),
+    )
+}
+
+impl Matrix {
+    pub fn new(rows: usize, columns: usize, components: Vec<Scalar>) -> Result<Self, ()> {
+        if components.len() != rows * columns {
+            return Err(());
+        }
+        Ok(Self {
+            rows,
+            columns,
+            components,
+        })
+    }
+
+    pub fn vector(components: Vec<Scalar>) -> Self {
+        Self {
+            rows: components.len(),
+            columns: 1,
+            components,
+        }
+    }
+
+    /// Stacks vectors of the same length as the rows of a matrix.
+    pub fn from_rows(rows: Vec<Matrix>) -> Result<Self, ()> {
+        let columns = rows.first().map(|row| row.rows).unwrap_or(0);
+        if rows
+            .iter()
+            .any(|row| !row.is_vector() || row.rows != columns)
+        {
+            return Err(());
+        }
+        let count = rows.len();
+        let components = rows.into_iter().flat_map(|row| row.components).collect();
+        Self::new(count, columns, components)
+    }
+
+    /// Lists the scalars from `start` to `end`, including `end` if a whole number of steps reaches
+    /// it. The step must head from `start` towards `end`.
+    pub fn range(start: &Scalar, end: &Scalar, step: &Scalar) -> Result<Self, ()> {
+        if start.unit() != end.unit() || start.is_complex() || end.is_complex() {
+            return Err(());
+        }
+        let span = end.sub(start)?.raw_value().to_f64();
+        let steps = span / step.raw_value().to_f64();
+        // Allows for rounding error in steps like 0.1 which can't be represented exactly.
+        let steps = (steps + 1e-9).floor();
+        if !(0.0..=MAX_RANGE_LENGTH as f64).contains(&steps) {
+            return Err(());
+        }
+        let components = (0..=steps as usize)
+            .map(|index| start.add(&(step.clone() * count(index))?))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::vector(components))
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.columns == 1
+    }
+
+    pub fn components(&self) -> &[Scalar] {
+        &self.components
+    }
+
+    pub fn component(&self, row: usize, column: usize) -> &Scalar {
+        &self.components[row * self.columns + column]
+    }
+
+    /// A component of a vector, or a row of a matrix as a vector.
+    pub fn index(&self, index: usize) -> Result<Data, ()> {
+        if index >= self.rows {
+            return Err(());
+        }
+        if self.is_vector() {
+            return Ok(self.components[index].clone().into());
+        }
+        let start = index * self.columns;
+        let row = self.components[start..start + self.columns].to_vec();
+        Ok(Self::vector(row).into())
+    }
+
+    pub fn map(&self, function: impl FnMut(&Scalar) -> Result<Scalar, ()>) -> Result<Self, ()> {
+        Ok(Self {
+            rows: self.rows,
+            columns: self.columns,
+            components: self
+                .components
+                .iter()
+                .map(function)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Combines the components of two matrices of the same shape.
+    pub fn zip(
+        &self,
+        other: &Self,
+        mut function: impl FnMut(&Scalar, &Scalar) -> Result<Scalar, ()>,
+    ) -> Result<Self, ()> {
+        if (self.rows, self.columns) != (other.rows, other.columns) {
+            return Err(());
+        }
+        let components = self.components.iter().zip(other.components.iter());
+        Ok(Self {
+            rows: self.rows,
+            columns: self.columns,
+            components: components
+                .map(|(lhs, rhs)| function(lhs, rhs))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The matrix product. Each sum in the product must add up scalars of the same unit class.
+    pub fn product(&self, other: &Self) -> Result<Self, ()> {
+        if self.columns != other.rows || self.columns == 0 {
+            return Err(());
+        }
+        let mut components = Vec::with_capacity(self.rows * other.columns);
+        for row in 0..self.rows {
+            for column in 0..other.columns {
+                let products = (0..self.columns)
+                    .map(|k| self.component(row, k).clone() * other.component(k, column).clone())
+                    .collect::<Result<Vec<_>, ()>>()?;
+                components.push(sum(products.into_iter())?);
+            }
+        }
+        Self::new(self.rows, other.columns, components)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut components = Vec::with_capacity(self.components.len());
+        for column in 0..self.columns {
+            for row in 0..self.rows {
+                components.push(self.component(row, column).clone());
+            }
+        }
+        Self {
+            rows: self.columns,
+            columns: self.rows,
+            components,
+        }
+    }
+
+    pub fn dot(&self, other: &Self) -> Result<Scalar, ()> {
+        if !self.is_vector() || !other.is_vector() || self.rows != other.rows {
+            return Err(());
+        }
+        let products = self.components.iter().zip(other.components.iter());
+        let products = products
+            .map(|(lhs, rhs)| lhs.clone() * rhs.clone())
+            .collect::<Result<Vec<_>, ()>>()?;
+        sum(products.into_iter())
+    }
+
+    pub fn cross(&self, other: &Self) -> Result<Self, ()> {
+        if !self.is_vector() || !other.is_vector() || self.rows != 3 || other.rows != 3 {
+            return Err(());
+        }
+        let (a, b) = (&self.components, &other.components);
+        let term = |i: usize, j: usize| -> Result<Scalar, ()> {
+            (a[i].clone() * b[j].clone())?.sub(&(a[j].clone() * b[i].clone())?)
+        };
+        Ok(Self::vector(vec![term(1, 2)?, term(2, 0)?, term(0, 1)?]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Sums every component, which must all have the same unit class.
+    pub fn sum(&self) -> Result<Scalar, ()> {
+        sum(self.components.iter().cloned())
+    }
+
+    pub fn mean(&self) -> Result<Scalar, ()> {
+        self.sum()? / count(self.len())
+    }
+
+    /// The sample standard deviation of the components.
+    pub fn stddev(&self) -> Result<Scalar, ()> {
+        if self.len() < 2 {
+            return Err(());
+        }
+        let mean = self.mean()?;
+        let squares = self
+            .components
+            .iter()
+            .map(|x| x.sub(&mean)?.pow_exponent(2.into()))
+            .collect::<Result<Vec<_>, ()>>()?;
+        let variance = (sum(squares.into_iter())? / count(self.len() - 1))?;
+        variance.pow_exponent(Exponent::new(1, 2))
+    }
+
+    /// The smallest component, compared in base units.
+    pub fn min(&self) -> Result<Scalar, ()> {
+        self.extreme(|candidate, best| candidate < best)
+    }
+
+    pub fn max(&self) -> Result<Scalar, ()> {
+        self.extreme(|candidate, best| candidate > best)
+    }
+
+    fn extreme(&self, better: impl Fn(f64, f64) -> bool) -> Result<Scalar, ()> {
+        let mut components = self.components.iter();
+        let mut best = components.next().ok_or(())?;
+        for candidate in components.chain(std::iter::once(best)) {
+            if candidate.unit() != best.unit() || candidate.is_complex() {
+                return Err(());
+            }
+            if better(candidate.raw_value().to_f64(), best.raw_value().to_f64()) {
+                best = candidate;
+            }
+        }
+        Ok(best.clone())
+    }
+
+    /// The euclidean length of a vector whose components all have the same unit class.
+    pub fn norm(&self) -> Result<Scalar, ()> {
+        self.dot(self)?.pow_exponent(Exponent::new(1, 2))
+    }
+}
+
+impl Describe for Matrix {
+    fn describe(&self, into: &mut String, instance: &Instance) {
+        into.push('[');
+        for row in 0..self.rows {
+            if row > 0 {
+                into.push_str(", ");
+            }
+            if self.is_vector() {
+                self.components[row].describe(into, instance);
+                continue;
+            }
+            into.push('[');
+            for column in 0..self.columns {
+                if column > 0 {
+                    into.push_str(", ");
+                }
+                self.component(row, column).describe(into, instance);
+            }
+            into.push(']');
+        }
+        into.push(']');
+    }
+}
@@ -0,0 +1,326 @@
+//! The numbers scalars are made of. Numbers are normally floating point. With the `exact` cargo
+//! feature, decimal literals become exact fractions which stay exact through addition,
+//! multiplication, division and integer powers. Anything else, like square roots, falls back to
+//! floating point.
+
+use crate::{
+    interval::Interval,
+    units::{pow_f64, Exponent},
+};
+#[cfg(feature = "exact")]
+use num_bigint::BigInt;
+#[cfg(feature = "exact")]
+use num_rational::BigRational;
+#[cfg(feature = "exact")]
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+/// Literals scaled by a larger power of ten than this are parsed as floats, since the exact
+/// fraction would take too long to compute.
+#[cfg(feature = "exact")]
+const MAX_EXACT_LITERAL_POWER: i32 = 400;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Number {
+    #[cfg(feature = "exact")]
+    Exact(BigRational),
+    Approximate(#[cfg_attr(feature = "serde", serde(with = "crate::snapshot::float"))] f64),
+}
+
+impl Number {
+    pub fn from_integer(value: i64) -> Self {
+        #[cfg(feature = "exact")]
+        {
+            Self::Exact(BigRational::from_integer(value.into()))
+        }
+        #[cfg(not(feature = "exact"))]
+        {
+            Self::Approximate(value as f64)
+        }
+    }
+
+    pub fn power_of_ten(power: i32) -> Self {
+        #[cfg(feature = "exact")]
+        {
+            Self::Exact(BigRational::from_integer(10.into()).pow(power))
+        }
+        #[cfg(not(feature = "exact"))]
+        {
+            Self::Approximate(10f64.powi(power))
+        }
+    }
+
+    /// Parses text like "-12.5e3". Returns None if the text is not a decimal number.
+    pub fn parse_decimal(text: &str) -> Option<Self> {
+        #[cfg(feature = "exact")]
+        {
+            let (mantissa, exponent) = match text.find(['e', 'E']) {
+                Some(index) => (&text[..index], text[index + 1..].parse::<i32>().ok()?),
+                None => (text, 0),
+            };
+            let (negative, mantissa) = match mantissa.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+            };
+            let (integer, fraction) = match mantissa.find('.') {
+                Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+                None => (mantissa, ""),
+            };
+            let digits = format!("{}{}", integer, fraction);
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let power = exponent.checked_sub(fraction.len() as i32)?;
+            if power.abs() > MAX_EXACT_LITERAL_POWER {
+                return text.parse().ok().map(Self::Approximate);
+            }
+            let digits: BigInt = digits.parse().ok()?;
+            let mut value =
+                BigRational::from_integer(digits) * BigRational::from_integer(10.into()).pow(power);
+            if negative {
+                value = -value;
+            }
+            Some(Self::Exact(value))
+        }
+        #[cfg(not(feature = "exact"))]
+        {
+            text.parse().ok().map(Self::Approximate)
+        }
+    }
+
+    /// Whether this number is known exactly. Always false without the `exact` feature.
+    pub fn is_exact(&self) -> bool {
+        #[cfg(feature = "exact")]
+        {
+            matches!(self, Self::Exact(..))
+        }
+        #[cfg(not(feature = "exact"))]
+        {
+            false
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            #[cfg(feature = "exact")]
+            Self::Exact(value) => value.is_zero(),
+            Self::Approximate(value) => *value == 0.0,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            #[cfg(feature = "exact")]
+            Self::Exact(value) => value.to_f64().unwrap_or(f64::NAN),
+            Self::Approximate(value) => *value,
+        }
+    }
+
+    /// The smallest interval known to contain this number. Numbers which may have been rounded
+    /// to the nearest float are widened by one unit in the last place in each direction.
+    pub fn enclosure(&self) -> Interval {
+        let value = self.to_f64();
+        let rounded = match self {
+            #[cfg(feature = "exact")]
+            Self::Exact(exact) => BigRational::from_float(value).as_ref() != Some(exact),
+            // Only integers this small are certain to be stored exactly.
+            Self::Approximate(..) => value.fract() != 0.0 || value.abs() > 2f64.powi(53),
+        };
+        if rounded && value.is_finite() {
+            Interval {
+                lower: value.next_down(),
+                upper: value.next_up(),
+            }
+        } else {
+            Interval::point(value)
+        }
+    }
+
+    /// Stays exact for integer powers of exact numbers.
+    pub fn pow(&self, exp: Exponent) -> Self {
+        #[cfg(feature = "exact")]
+        if let Self::Exact(value) = self {
+            // Zero to a negative power is an infinity, which can't be written as a fraction.
+            let defined = !(value.is_zero() && exp.is_negative());
+            match exp.to_integer().to_i32() {
+                Some(power) if exp.is_integer() && defined => return Self::Exact(value.pow(power)),
+                _ => (),
+            }
+        }
+        Self::Approximate(pow_f64(self.to_f64(), exp))
+    }
+
+    pub fn powf(&self, exp: f64) -> Self {
+        Self::Approximate(self.to_f64().powf(exp))
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Self::Approximate(value)
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            #[cfg(feature = "exact")]
+            (Self::Exact(lhs), Self::Exact(rhs)) => lhs == rhs,
+            (lhs, rhs) => lhs.to_f64() == rhs.to_f64(),
+        }
+    }
+}
+
+/// Writes the number as a decimal if it can be written exactly with a finite number of digits.
+#[cfg(feature = "exact")]
+fn write_exact_decimal(f: &mut Formatter<'_>, value: &BigRational) -> Option<fmt::Result> {
+    let two = BigInt::from(2);
+    let five = BigInt::from(5);
+    let mut denominator = value.denom().clone();
+    let (mut twos, mut fives) = (0usize, 0usize);
+    while (&denominator % &two).is_zero() {
+        denominator /= &two;
+        twos += 1;
+    }
+    while (&denominator % &five).is_zero() {
+        denominator /= &five;
+        fives += 1;
+    }
+    if denominator != BigInt::from(1) {
+        return None;
+    }
+    let places = twos.max(fives);
+    let scaled = value * BigRational::from_integer(10.into()).pow(places as i32);
+    let digits = scaled.to_integer().abs().to_string();
+    let sign = if value.is_negative() { "-" } else { "" };
+    Some(if places == 0 {
+        write!(f, "{}{}", sign, digits)
+    } else {
+        let digits = format!("{:0>width$}", digits, width = places + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - places);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    })
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "exact")]
+            Self::Exact(value) => match write_exact_decimal(f, value) {
+                Some(result) => result,
+                None => write!(f, "{}", self.to_f64()),
+            },
+            Self::Approximate(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+macro_rules! impl_op {
+    ($Trait:ident, $method:ident, $op:tt) => {
+        impl $Trait for Number {
+            type Output = Self;
+            fn $method(self, rhs: Self) -> Self {
+                match (self, rhs) {
+                    #[cfg(feature = "exact")]
+                    (Self::Exact(lhs), Self::Exact(rhs)) => Self::Exact(lhs $op rhs),
+                    (lhs, rhs) => Self::Approximate(lhs.to_f64() $op rhs.to_f64()),
+                }
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, +);
+impl_op!(Sub, sub, -);
+impl_op!(Mul, mul, *);
+
+impl Div for Number {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            #[cfg(feature = "exact")]
+            (Self::Exact(lhs), Self::Exact(rhs)) if !rhs.is_zero() => Self::Exact(lhs / rhs),
+            (lhs, rhs) => Self::Approximate(lhs.to_f64() / rhs.to_f64()),
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Self;
+    fn neg(self) -> Self {
+        match self {
+            #[cfg(feature = "exact")]
+            Self::Exact(value) => Self::Exact(-value),
+            Self::Approximate(value) => Self::Approximate(-value),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "exact"))]
+mod tests {
+    use super::*;
+
+    fn show(number: &Number) -> String {
+        number.to_string()
+    }
+
+    #[test]
+    fn literals_stay_exact() {
+        let foot = Number::parse_decimal("0.3048").unwrap();
+        let twelve = Number::from_integer(12);
+        assert_eq!(show(&(foot.clone() * twelve)), "3.6576");
+        assert_eq!(show(&(foot.clone() / Number::power_of_ten(-2))), "30.48");
+        assert_eq!(show(&foot.pow((-2).into()).pow((-1).into())), "0.09290304");
+        assert_eq!(show(&Number::parse_decimal("-1.5e-3").unwrap()), "-0.0015");
+        let pi = "3.14159265358979323846264338327950288419716939937510582097494459230781";
+        assert_eq!(show(&Number::parse_decimal(pi).unwrap()), pi);
+    }
+
+    #[test]
+    fn huge_literals_are_floats() {
+        let huge = Number::parse_decimal("1e999999999").unwrap();
+        assert!(!huge.is_exact());
+        assert_eq!(huge.to_f64(), f64::INFINITY);
+        assert!(!Number::parse_decimal("-2.5e-999999999").unwrap().is_exact());
+        assert!(Number::parse_decimal("1e300").unwrap().is_exact());
+    }
+
+    #[test]
+    fn inexact_operations_fall_back_to_floats() {
+        let two = Number::from_integer(2);
+        assert!(!two.pow(Exponent::new(1, 2)).is_exact());
+        assert!(!(two.clone() / Number::from_integer(0)).is_exact());
+        assert_eq!(
+            show(&(Number::from_integer(1) / Number::from_integer(3))),
+            "0.3333333333333333"
+        );
+    }
+
+    #[test]
+    fn conversions_stay_exact() {
+        use crate::{data::Describe, expression::parse_expression, prelude::*, statement};
+        let mut instance = Instance::new();
+        let (_, statements) = statement::parse_statements(
+            r#"
+            make entity_class called metric
+            make entity_class called partial_metric
+            make unit_class called Length
+            make base_unit called Meter, Meters { class: Length, symbol: "m", metric, }
+            make derived_unit called Foot, Feet { symbol: "ft", value: 0.3048 * Meters, }
+            "#,
+        )
+        .unwrap();
+        instance.execute_statements(statements).unwrap();
+        let (_, expression) = parse_expression("(12 * Feet)^2 in Centimeters^2").unwrap();
+        let value = instance
+            .resolve_expression(&expression, Default::default())
+            .unwrap();
+        let mut description = String::new();
+        value.describe(&mut description, &instance);
+        assert_eq!(description, "133780.3776cm^2");
+    }
+}
@@ -0,0 +1,388 @@
+//! Line plots of expressions over a swept variable, written as standalone SVG text.
+
+use crate::{
+    data::{Data, Describe, ValueData},
+    expression::Expression,
+    prelude::*,
+    table::sweep_values,
+};
+use std::fmt::Write;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 400.0;
+/// Space around the plotted area for tick labels and axis labels, in the order left, right, top,
+/// bottom.
+const MARGINS: (f64, f64, f64, f64) = (80.0, 20.0, 20.0, 50.0);
+const COLORS: &[&str] = &[
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b",
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Scales {
+    pub log_x: bool,
+    pub log_y: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// Values in the display units of the axes.
+    pub points: Vec<(f64, f64)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plot {
+    pub x_label: String,
+    pub y_label: String,
+    pub series: Vec<Series>,
+    pub scales: Scales,
+}
+
+/// Writes a name followed by a unit in brackets, unless the unit is dimensionless.
+fn axis_label(name: &str, unit: &CompositeUnit, instance: &Instance) -> String {
+    let mut label = name.to_owned();
+    if !unit.is_identity() {
+        label.push_str(" (");
+        unit.describe(&mut label, instance);
+        label.push(')');
+    }
+    label
+}
+
+/// Finds the value of a scalar in the unit of an axis, which is taken from the first scalar on
+/// that axis.
+fn axis_value(
+    data: Data,
+    axis_unit: &mut Option<(CompositeUnitClass, CompositeUnit)>,
+    instance: &Instance,
+) -> Result<f64, ()> {
+    let mut scalar = match data {
+        Data::Value(ValueData::Scalar(scalar)) if !scalar.is_complex() => scalar,
+        _ => return Err(()),
+    };
+    let (class, unit) =
+        axis_unit.get_or_insert_with(|| (scalar.unit().clone(), scalar.display_unit().clone()));
+    if scalar.unit() != class {
+        return Err(());
+    }
+    scalar.set_display_unit(unit.clone());
+    Ok(scalar.display_value(instance))
+}
+
+impl Instance {
+    /// Evaluates each named expression for every value of the swept variable.
+    pub fn plot(
+        &self,
+        series: &[(String, Expression)],
+        sweep: &(String, Expression),
+        scales: Scales,
+    ) -> Result<Plot, ()> {
+        let (variable, values) = sweep;
+        let values = sweep_values(self.resolve_expression(values, Default::default())?)?;
+        let (mut x_unit, mut y_unit) = (None, None);
+        let mut plotted = Vec::new();
+        for (name, expression) in series {
+            let mut points = Vec::new();
+            for value in &values {
+                let x = axis_value(value.clone(), &mut x_unit, self)?;
+                let mut bindings = Bindings::new();
+                bindings.insert(variable.clone(), value.clone());
+                let y = self.resolve_expression_with(expression, Default::default(), &bindings)?;
+                points.push((x, axis_value(y, &mut y_unit, self)?));
+            }
+            plotted.push(Series {
+                name: name.clone(),
+                points,
+            });
+        }
+        let identity = || (CompositeUnitClass::identity(), CompositeUnit::identity());
+        let names: Vec<_> = series.iter().map(|(name, _)| name.as_str()).collect();
+        Ok(Plot {
+            x_label: axis_label(variable, &x_unit.unwrap_or_else(identity).1, self),
+            y_label: axis_label(&names.join(", "), &y_unit.unwrap_or_else(identity).1, self),
+            series: plotted,
+            scales,
+        })
+    }
+}
+
+/// Maps values along one axis to a fraction of the plotted area. Logarithmic axes work with the
+/// logarithms of values.
+struct Axis {
+    lower: f64,
+    upper: f64,
+    /// The distance between ticks on a linear axis.
+    step: f64,
+    log: bool,
+}
+
+impl Axis {
+    fn new(values: impl Iterator<Item = f64>, log: bool) -> Self {
+        let (mut lower, mut upper) = (f64::INFINITY, f64::NEG_INFINITY);
+        for value in values.filter(|value| Self::plottable(*value, log)) {
+            let value = if log { value.log10() } else { value };
+            lower = lower.min(value);
+            upper = upper.max(value);
+        }
+        if lower > upper {
+            lower = 0.0;
+            upper = 1.0;
+        } else if lower == upper {
+            lower -= 1.0;
+            upper += 1.0;
+        }
+        let step = nice_step(upper - lower);
+        if log {
+            lower = lower.floor();
+            upper = upper.ceil();
+        } else {
+            lower = (lower / step).floor() * step;
+            upper = (upper / step).ceil() * step;
+        }
+        Self {
+            lower,
+            upper,
+            step,
+            log,
+        }
+    }
+
+    fn plottable(value: f64, log: bool) -> bool {
+        value.is_finite() && (!log || value > 0.0)
+    }
+
+    fn fraction(&self, value: f64) -> f64 {
+        let value = if self.log { value.log10() } else { value };
+        (value - self.lower) / (self.upper - self.lower)
+    }
+
+    /// Tick positions, with the text to label them with.
+    fn ticks(&self) -> Vec<(f64, String)> {
+        if self.log {
+            let decades = (self.upper - self.lower) as i32;
+            let stride = (decades / 8 + 1).max(1);
+            return (self.lower as i32..=self.upper as i32)
+                .step_by(stride as usize)
+                .map(|power| (10f64.powi(power), format!("{}", 10f64.powi(power))))
+                .collect();
+        }
+        let step = self.step;
+        let decimals = (-step.log10().floor()).max(0.0) as usize;
+        let first = (self.lower / step).round() as i64;
+        let last = (self.upper / step).round() as i64;
+        (first..=last)
+            .map(|index| {
+                let value = index as f64 * step;
+                (value, format!("{:.*}", decimals, value))
+            })
+            .collect()
+    }
+}
+
+/// A round step which splits the span into about five parts.
+fn nice_step(span: f64) -> f64 {
+    let rough = span / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let scaled = rough / magnitude;
+    let nice = if scaled <= 1.0 {
+        1.0
+    } else if scaled <= 2.0 {
+        2.0
+    } else if scaled <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Plot {
+    pub fn to_svg(&self) -> String {
+        let (left, right, top, bottom) = MARGINS;
+        let (plot_width, plot_height) = (WIDTH - left - right, HEIGHT - top - bottom);
+        let points = || self.series.iter().flat_map(|series| series.points.iter());
+        let x_axis = Axis::new(points().map(|point| point.0), self.scales.log_x);
+        let y_axis = Axis::new(points().map(|point| point.1), self.scales.log_y);
+        let to_x = |value: f64| left + x_axis.fraction(value) * plot_width;
+        let to_y = |value: f64| top + (1.0 - y_axis.fraction(value)) * plot_height;
+
+        let mut svg = String::new();
+        macro_rules! put {
+            ($($t:tt)*) => {
+                writeln!(svg, $($t)*).unwrap();
+            }
+        }
+        put!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+            w = WIDTH,
+            h = HEIGHT
+        );
+        put!(r#"<rect width="100%" height="100%" fill="white"/>"#);
+        for (value, text) in x_axis.ticks() {
+            let x = to_x(value);
+            put!(
+                r##"<line x1="{x:.1}" y1="{top}" x2="{x:.1}" y2="{bottom:.1}" stroke="#ddd"/>"##,
+                x = x,
+                top = top,
+                bottom = top + plot_height
+            );
+            put!(
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+                x,
+                top + plot_height + 16.0,
+                escape_xml(&text)
+            );
+        }
+        for (value, text) in y_axis.ticks() {
+            let y = to_y(value);
+            put!(
+                r##"<line x1="{left}" y1="{y:.1}" x2="{right:.1}" y2="{y:.1}" stroke="#ddd"/>"##,
+                left = left,
+                right = left + plot_width,
+                y = y
+            );
+            put!(
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+                left - 6.0,
+                y + 4.0,
+                escape_xml(&text)
+            );
+        }
+        put!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black"/>"#,
+            left,
+            top,
+            plot_width,
+            plot_height
+        );
+        put!(
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            left + plot_width / 2.0,
+            HEIGHT - 10.0,
+            escape_xml(&self.x_label)
+        );
+        let y_label_position = (16.0, top + plot_height / 2.0);
+        put!(
+            r#"<text x="{x:.1}" y="{y:.1}" text-anchor="middle" transform="rotate(-90 {x:.1} {y:.1})">{text}</text>"#,
+            x = y_label_position.0,
+            y = y_label_position.1,
+            text = escape_xml(&self.y_label)
+        );
+
+        for (index, series) in self.series.iter().enumerate() {
+            let color = COLORS[index % COLORS.len()];
+            // Points which can't be plotted, like zero on a log scale, leave a gap in the line.
+            let mut segments = vec![Vec::new()];
+            for &(x, y) in &series.points {
+                if Axis::plottable(x, x_axis.log) && Axis::plottable(y, y_axis.log) {
+                    let segment = segments.last_mut().unwrap();
+                    segment.push(format!("{:.1},{:.1}", to_x(x), to_y(y)));
+                } else if !segments.last().unwrap().is_empty() {
+                    segments.push(Vec::new());
+                }
+            }
+            for segment in segments.iter().filter(|segment| !segment.is_empty()) {
+                put!(
+                    r#"<polyline fill="none" stroke="{}" stroke-width="1.5" points="{}"/>"#,
+                    color,
+                    segment.join(" ")
+                );
+            }
+            if self.series.len() > 1 {
+                let y = top + 16.0 + index as f64 * 16.0;
+                let x = left + plot_width - 120.0;
+                put!(
+                    r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="2"/>"#,
+                    x,
+                    y - 4.0,
+                    x + 20.0,
+                    y - 4.0,
+                    color
+                );
+                put!(
+                    r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                    x + 26.0,
+                    y,
+                    escape_xml(&series.name)
+                );
+            }
+        }
+        put!("</svg>");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        statement::{parse_statements, Statement},
+        test_util::make_instance,
+    };
+
+    fn plot(instance: &Instance, source: &str) -> Result<Plot, ()> {
+        let (rest, statements) = parse_statements(source).unwrap();
+        assert_eq!(rest, "");
+        match statements.into_iter().next() {
+            Some(Statement::Plot(series, sweep, scales, path)) => {
+                assert_eq!(path, "out.svg");
+                instance.plot(&series, &sweep, scales)
+            }
+            _ => panic!("not a plot statement"),
+        }
+    }
+
+    #[test]
+    fn series_use_axis_units() {
+        let instance = make_instance();
+        let result = plot(
+            &instance,
+            r#"plot near = t * 2 * Meters / Second in Centimeters, far = t * 3 * Meters / Second
+            for t = (1..3) * Seconds to "out.svg""#,
+        )
+        .unwrap();
+        assert_eq!(result.x_label, "t (s)");
+        assert_eq!(result.y_label, "near, far (cm)");
+        assert_eq!(result.series[1].name, "far");
+        assert_eq!(
+            result.series[1].points,
+            vec![(1.0, 300.0), (2.0, 600.0), (3.0, 900.0)]
+        );
+        let svg = result.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(">near, far (cm)</text>"));
+
+        let mismatched = r#"plot a = t * Meters, b = t * Seconds for t = 1..2 to "out.svg""#;
+        assert!(plot(&instance, mismatched).is_err());
+    }
+
+    #[test]
+    fn log_scales() {
+        let instance = make_instance();
+        let result = plot(
+            &instance,
+            r#"plot y = x^2 for x = 0..100 step 10 log_x log_y to "out.svg""#,
+        )
+        .unwrap();
+        assert_eq!(
+            result.scales,
+            Scales {
+                log_x: true,
+                log_y: true
+            }
+        );
+        let svg = result.to_svg();
+        // Zero can't be shown on a log scale, so it is left out.
+        assert_eq!(svg.matches("<polyline").count(), 1);
+        assert!(svg.contains(">10000</text>"));
+        assert!(svg.contains(">100</text>"));
+    }
+}
@@ -1,14 +1,21 @@
-use crate::{data::Describe, prelude::*};
+use crate::{
+    data::Describe,
+    interval::{div_down, div_up},
+    prelude::*,
+};
 use std::{
     fmt::Write,
     ops::{Div, Mul, Neg},
 };
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Precision {
     SigFigs(i32),
-    PercentError(f64),
+    PercentError(#[cfg_attr(feature = "serde", serde(with = "crate::snapshot::float"))] f64),
     Exact,
+    /// Bounds, in base units, which are guaranteed to contain the true value.
+    Interval(Interval),
 }
 
 impl Precision {
@@ -21,17 +28,80 @@ impl Precision {
             }
             Self::PercentError(p) => p,
             Self::Exact => 0.0,
+            Self::Interval(bounds) => (bounds.upper - bounds.lower) / 2.0 / for_value.abs(),
+        }
+    }
+
+    /// Returns bounds which contain every value the given value could really be.
+    pub fn bounds(self, for_value: &Number) -> Interval {
+        match self {
+            Self::Exact => for_value.enclosure(),
+            Self::Interval(bounds) => bounds,
+            _ => {
+                let for_value = for_value.to_f64();
+                Interval::around(for_value, for_value * self.percent_error(for_value))
+            }
+        }
+    }
+
+    /// Interval bounds only describe real numbers, so complex values carry their uncertainty as a
+    /// percent error of their magnitude instead.
+    fn for_complex(self, magnitude: f64) -> Self {
+        match self {
+            Self::Interval(..) => Self::PercentError(self.percent_error(magnitude)),
+            other => other,
         }
     }
 }
 
+type Complex = (Number, Number);
+
+fn complex_mul((a, b): Complex, (c, d): Complex) -> Complex {
+    (a.clone() * c.clone() - b.clone() * d.clone(), a * d + b * c)
+}
+
+fn complex_div((a, b): Complex, (c, d): Complex) -> Complex {
+    let denominator = c.clone() * c.clone() + d.clone() * d.clone();
+    (
+        (a.clone() * c.clone() + b.clone() * d.clone()) / denominator.clone(),
+        (b * c - a * d) / denominator,
+    )
+}
+
+fn complex_from_polar(magnitude: f64, angle: f64) -> Complex {
+    (
+        (magnitude * angle.cos()).into(),
+        (magnitude * angle.sin()).into(),
+    )
+}
+
+/// How a complex value described as a magnitude and angle shows its angle.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Polar {
+    /// The unit the angle is described in. None to use the instance's default.
+    angle_unit: Option<CompositeUnit>,
+}
+
 #[scones::make_constructor]
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scalar {
-    value: f64,
+    /// The real part of the value, in base units.
+    value: Number,
+    /// The imaginary part of the value, in base units. None for real scalars.
+    #[value(None)]
+    imaginary: Option<Number>,
     precision: Precision,
     unit: CompositeUnitClass,
     display_unit: CompositeUnit,
+    /// Set if a complex value is described as a magnitude and angle instead of `a + bi`.
+    #[value(None)]
+    polar: Option<Box<Polar>>,
+    /// What kind of quantity this is, such as torque rather than energy. None if it could be any
+    /// quantity with its unit class.
+    #[value(None)]
+    kind: Option<String>,
 }
 
 impl Scalar {
@@ -40,11 +110,24 @@ impl Scalar {
         if self.unit != other.unit {
             return Err(());
         }
-        let new_value = self.value + other.value;
-        let lhs_order = self.value.log10() as i32;
-        let rhs_order = other.value.log10() as i32;
+        let kind = match (&self.kind, &other.kind) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => return Err(()),
+            (lhs, rhs) => lhs.clone().or_else(|| rhs.clone()),
+        };
+        let mut result = self.clone();
+        result.kind = kind;
+        result.value = self.value.clone() + other.value.clone();
+        result.set_imaginary(self.imaginary_part() + other.imaginary_part());
+        let new_value = result.precision_value();
+        let (lhs_value, rhs_value) = (self.precision_value(), other.precision_value());
+        let lhs_order = lhs_value.log10() as i32;
+        let rhs_order = rhs_value.log10() as i32;
         let new_order = new_value.log10() as i32;
-        let new_precision = match ((self.value, self.precision), (other.value, other.precision)) {
+        let (lhs_precision, rhs_precision) = (self.complex_precision(), other.complex_precision());
+        let new_precision = match ((lhs_value, lhs_precision), (rhs_value, rhs_precision)) {
+            ((_, Interval(..)), _) | (_, (_, Interval(..))) => {
+                Interval(self.bounds() + other.bounds())
+            }
             ((_, Exact), (_, Exact)) => Exact,
             ((_, Exact), (_, other)) => other,
             ((_, other), (_, Exact)) => other,
@@ -60,27 +143,301 @@ impl Scalar {
                 PercentError(new_range / new_value)
             }
         };
-        Ok(Self {
-            value: new_value,
-            precision: new_precision,
-            unit: self.unit.clone(),
-            display_unit: self.display_unit.clone(),
-        })
+        result.precision = new_precision;
+        result.precision = result.complex_precision();
+        Ok(result)
     }
 
     pub fn sub(&self, other: &Self) -> Result<Self, ()> {
         self.add(&-other.clone())
     }
 
+    /// Dimensioned quantities can only be raised to rational powers, see exponent_from_f64.
     pub fn pow(&self, other: &Self, instance: &Instance) -> Result<Self, ()> {
-        let mut res = self.clone();
+        if !other.unit.is_identity() || other.is_complex() {
+            return Err(());
+        }
+        // Raising to a power which is itself uncertain is not supported.
+        if let Precision::Interval(bounds) = other.precision {
+            if bounds.lower != bounds.upper {
+                return Err(());
+            }
+        }
         let exp = other.display_value(instance);
-        res.value = res.value.powf(exp);
-        res.unit.pow(exp);
-        res.display_unit.pow(exp);
+        let dimensionless = self.unit.is_identity() && self.display_unit.is_identity();
+        // Fractional powers of negative numbers are complex.
+        if self.is_complex() || self.value.to_f64() < 0.0 {
+            return match exponent_from_f64(exp) {
+                Ok(exp) => self.pow_exponent(exp),
+                Err(()) if dimensionless => {
+                    let mut res = self.clone();
+                    res.kind = None;
+                    res.set_parts(complex_from_polar(
+                        self.magnitude().powf(exp),
+                        self.angle() * exp,
+                    ));
+                    res.precision = res.complex_precision();
+                    Ok(res)
+                }
+                Err(()) => Err(()),
+            };
+        }
+        if dimensionless {
+            match exponent_from_f64(exp) {
+                Ok(exp) if exp.is_integer() => return self.pow_exponent(exp),
+                _ => (),
+            }
+            let mut res = self.clone();
+            res.kind = None;
+            res.value = res.value.powf(exp);
+            if let Precision::Interval(bounds) = self.precision {
+                res.precision = Precision::Interval(bounds.powf(exp)?);
+            }
+            return Ok(res);
+        }
+        self.pow_exponent(exponent_from_f64(exp)?)
+    }
+
+    pub fn pow_exponent(&self, exp: Exponent) -> Result<Self, ()> {
+        let mut res = self.clone();
+        if self.is_complex() || (self.value.to_f64() < 0.0 && !exp.is_integer()) {
+            res.set_parts(self.complex_pow(exp));
+            res.precision = res.complex_precision();
+        } else {
+            if let Precision::Interval(bounds) = self.precision {
+                res.precision = Precision::Interval(bounds.pow(exp)?);
+            }
+            res.value = res.value.pow(exp);
+        }
+        res.unit.pow(exp)?;
+        res.display_unit.pow(exp)?;
+        if exp != Exponent::from_integer(1) {
+            res.kind = None;
+        }
+        Ok(res)
+    }
+
+    /// Integer powers are found by repeated multiplication so that exact parts stay exact.
+    fn complex_pow(&self, exp: Exponent) -> Complex {
+        let doubled = exp * 2;
+        if !self.is_complex() && !exp.is_integer() && doubled.is_integer() {
+            // A negative number to an odd number of halves is a multiple of i, like sqrt(-4) = 2i.
+            let magnitude = (-self.value.clone()).pow(exp);
+            return if doubled.to_integer().rem_euclid(4) == 1 {
+                (Number::from_integer(0), magnitude)
+            } else {
+                (Number::from_integer(0), -magnitude)
+            };
+        }
+        if !exp.is_integer() {
+            let exp = *exp.numer() as f64 / *exp.denom() as f64;
+            return complex_from_polar(self.magnitude().powf(exp), self.angle() * exp);
+        }
+        let mut base = self.parts();
+        let mut remaining = exp.to_integer().unsigned_abs();
+        let mut result = (Number::from_integer(1), Number::from_integer(0));
+        while remaining > 0 {
+            if remaining % 2 == 1 {
+                result = complex_mul(result, base.clone());
+            }
+            base = complex_mul(base.clone(), base);
+            remaining /= 2;
+        }
+        if exp < Exponent::from_integer(0) {
+            result = complex_div((Number::from_integer(1), Number::from_integer(0)), result);
+        }
+        result
+    }
+
+    /// Makes a complex scalar from a magnitude and an angle, which is either a plain number of
+    /// radians or a quantity of an angle class. The angle is described in the unit it was given in.
+    pub fn from_polar(magnitude: &Self, angle: &Self, instance: &Instance) -> Result<Self, ()> {
+        if magnitude.is_complex() || angle.is_complex() {
+            return Err(());
+        }
+        let angle_unit = Some(angle.display_unit.clone()).filter(|_| !angle.unit.is_identity());
+        let angle = instance.angle_argument(angle)?;
+        let mut res = magnitude.clone();
+        res.precision = magnitude
+            .precision
+            .for_complex(magnitude.value.to_f64().abs());
+        let angle = angle.value.to_f64();
+        res.set_parts((
+            magnitude.value.clone() * angle.cos().into(),
+            magnitude.value.clone() * angle.sin().into(),
+        ));
+        res.polar = Some(Box::new(Polar { angle_unit }));
+        Ok(res)
+    }
+
+    /// Makes a scalar which lies somewhere between the two given scalars.
+    pub fn interval(lower: &Self, upper: &Self) -> Result<Self, ()> {
+        if lower.unit != upper.unit || lower.is_complex() || upper.is_complex() {
+            return Err(());
+        }
+        let bounds = Interval::new(lower.bounds().lower, upper.bounds().upper)?;
+        let middle = (lower.value.clone() + upper.value.clone()) / Number::from_integer(2);
+        let mut res = lower.clone();
+        res.value = middle;
+        res.precision = Precision::Interval(bounds);
         Ok(res)
     }
 
+    /// Returns bounds, in base units, which contain every value this scalar could really be.
+    pub fn bounds(&self) -> Interval {
+        self.precision.bounds(&self.value)
+    }
+
+    /// The magnitude of complex scalars.
+    pub fn abs(&self) -> Self {
+        let mut res = self.clone();
+        if self.is_complex() {
+            res.value = self.magnitude().into();
+            res.imaginary = None;
+            return res;
+        }
+        if self.value.to_f64() < 0.0 {
+            res.value = -res.value;
+        }
+        if let Precision::Interval(bounds) = self.precision {
+            res.precision = Precision::Interval(bounds.abs());
+        }
+        res
+    }
+
+    /// Applies a function to a dimensionless scalar. The function is evaluated in floating point,
+    /// `bounds` gives its range over an interval.
+    pub fn apply(
+        &self,
+        function: impl Fn(f64) -> f64,
+        bounds: impl Fn(Interval) -> Result<Interval, ()>,
+    ) -> Result<Self, ()> {
+        if !self.unit.is_identity() || self.is_complex() {
+            return Err(());
+        }
+        let precision = match self.precision {
+            Precision::Interval(interval) => Precision::Interval(bounds(interval)?),
+            other => other,
+        };
+        Ok(Self::new(
+            function(self.value.to_f64()).into(),
+            precision,
+            CompositeUnitClass::identity(),
+            CompositeUnit::identity(),
+        ))
+    }
+
+    pub fn is_complex(&self) -> bool {
+        self.imaginary.is_some()
+    }
+
+    /// Adds an imaginary part to this scalar, in base units.
+    pub fn with_imaginary(mut self, imaginary: Number) -> Self {
+        self.set_imaginary(imaginary);
+        self
+    }
+
+    fn set_imaginary(&mut self, imaginary: Number) {
+        self.imaginary = if imaginary.is_zero() {
+            None
+        } else {
+            Some(imaginary)
+        };
+    }
+
+    fn imaginary_part(&self) -> Number {
+        self.imaginary
+            .clone()
+            .unwrap_or_else(|| Number::from_integer(0))
+    }
+
+    fn parts(&self) -> Complex {
+        (self.value.clone(), self.imaginary_part())
+    }
+
+    fn set_parts(&mut self, (real, imaginary): Complex) {
+        self.value = real;
+        self.set_imaginary(imaginary);
+    }
+
+    fn magnitude(&self) -> f64 {
+        self.value.to_f64().hypot(self.imaginary_part().to_f64())
+    }
+
+    /// The angle of this scalar on the complex plane, in radians.
+    fn angle(&self) -> f64 {
+        self.imaginary_part().to_f64().atan2(self.value.to_f64())
+    }
+
+    /// The value precision is relative to, the magnitude for complex scalars.
+    fn precision_value(&self) -> f64 {
+        if self.is_complex() {
+            self.magnitude()
+        } else {
+            self.value.to_f64()
+        }
+    }
+
+    fn complex_precision(&self) -> Precision {
+        if self.is_complex() {
+            self.precision.for_complex(self.magnitude())
+        } else {
+            self.precision
+        }
+    }
+
+    /// The real part of this scalar, keeping its unit.
+    pub fn real_part(&self) -> Self {
+        let mut res = self.clone();
+        res.precision = self.complex_precision();
+        res.imaginary = None;
+        res
+    }
+
+    /// The imaginary part of this scalar as a real scalar, keeping its unit.
+    pub fn imaginary_as_real(&self) -> Self {
+        let mut res = self.real_part();
+        res.value = self.imaginary_part();
+        res
+    }
+
+    pub fn conjugate(&self) -> Self {
+        let mut res = self.clone();
+        res.imaginary = self.imaginary.clone().map(Neg::neg);
+        res
+    }
+
+    /// The dimensionless angle of this scalar on the complex plane, in radians.
+    pub fn argument(&self) -> Self {
+        Self::new(
+            self.angle().into(),
+            self.complex_precision(),
+            CompositeUnitClass::identity(),
+            CompositeUnit::identity(),
+        )
+    }
+
+    /// Chooses whether complex values are described in polar or rectangular form.
+    pub fn set_polar(&mut self, polar: bool) {
+        if !polar {
+            self.polar = None;
+        } else if self.polar.is_none() {
+            self.polar = Some(Box::new(Polar { angle_unit: None }));
+        }
+    }
+
+    /// Returns the value of this scalar as an exponent for a unit or unit class.
+    pub fn as_exponent(&self, instance: &Instance) -> Result<Exponent, ()> {
+        if !self.unit.is_identity() || self.is_complex() {
+            return Err(());
+        }
+        exponent_from_f64(self.display_value(instance))
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
     pub fn unit(&self) -> &CompositeUnitClass {
         &self.unit
     }
@@ -93,35 +450,139 @@ impl Scalar {
         self.display_unit = display_unit;
     }
 
+    /// Takes the angles out of this scalar's unit, see UnitClass::angle.
+    pub fn without_angles(&self, instance: &Instance) -> Result<Self, ()> {
+        let mut res = self.clone();
+        res.unit = self.unit.without_angles(instance);
+        res.display_unit = self.display_unit.without_angles(instance)?;
+        if res.unit != self.unit {
+            res.kind = None;
+        }
+        Ok(res)
+    }
+
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    pub fn set_kind(&mut self, kind: Option<String>) {
+        self.kind = kind;
+    }
+
+    /// Scaling a quantity by a plain number doesn't change what kind of quantity it is.
+    fn is_plain_number(&self) -> bool {
+        self.unit.is_identity() && self.kind.is_none()
+    }
+
     pub fn display_value(&self, instance: &Instance) -> f64 {
-        self.value / self.display_unit.base_ratio(instance)
+        self.display_number(instance).to_f64()
     }
 
-    pub fn raw_value(&self) -> f64 {
-        self.value
+    /// Like display_value, but stays exact when possible.
+    pub fn display_number(&self, instance: &Instance) -> Number {
+        self.value.clone() / self.display_unit.base_ratio(instance)
     }
-}
 
-impl Describe for Scalar {
-    fn describe(&self, into: &mut String, instance: &Instance) {
+    /// The real part of the value in base units.
+    pub fn raw_value(&self) -> &Number {
+        &self.value
+    }
+
+    /// Describes the value in its display unit without writing the unit, for places like table
+    /// cells where the unit is written elsewhere.
+    pub fn describe_number(&self, into: &mut String, instance: &Instance) {
+        self.describe_in(into, instance, false);
+    }
+
+    fn describe_in(&self, into: &mut String, instance: &Instance, with_unit: bool) {
         macro_rules! put {
             ($($t:tt)*) => {
                 write!(into, $($t)*).unwrap();
             }
         }
-        put!("{}", self.value / self.display_unit.base_ratio(instance));
-        if !self.display_unit.is_identity() {
+        let has_unit = with_unit && !self.display_unit.is_identity();
+        if self.is_complex() {
+            self.describe_complex(into, instance, has_unit);
+            return;
+        }
+        if let Precision::Interval(bounds) = self.precision {
+            let ratio = self.display_unit.base_ratio(instance).to_f64();
+            let lower = div_down(bounds.lower, ratio);
+            let upper = div_up(bounds.upper, ratio);
+            put!("[{}, {}]", lower, upper);
+            if has_unit {
+                put!(" ");
+                self.display_unit.describe(into, instance);
+            }
+            return;
+        }
+        put!("{}", self.display_number(instance));
+        if has_unit {
+            self.display_unit.describe(into, instance);
+        }
+    }
+
+    fn describe_complex(&self, into: &mut String, instance: &Instance, has_unit: bool) {
+        let ratio = self.display_unit.base_ratio(instance);
+        let real = self.value.clone() / ratio.clone();
+        let imaginary = self.imaginary_part() / ratio;
+        if let Some(polar) = &self.polar {
+            let (real, imaginary) = (real.to_f64(), imaginary.to_f64());
+            write!(into, "{}", real.hypot(imaginary)).unwrap();
+            if has_unit {
+                self.display_unit.describe(into, instance);
+            }
+            let angle = imaginary.atan2(real);
+            // Without an angle class, the angle is written as a degree literal.
+            match polar
+                .angle_unit
+                .clone()
+                .or_else(|| instance.default_angle_unit())
+            {
+                Some(unit) => {
+                    let ratio = unit.base_ratio(instance).to_f64();
+                    write!(into, " ∠ {}", angle / ratio).unwrap();
+                    unit.describe(into, instance);
+                }
+                None => write!(into, " ∠ {}deg", angle.to_degrees()).unwrap(),
+            }
+            return;
+        }
+        if has_unit {
+            into.push('(');
+        }
+        if real.is_zero() {
+            write!(into, "{}i", imaginary).unwrap();
+        } else if imaginary.to_f64() < 0.0 {
+            write!(into, "{} - {}i", real, -imaginary).unwrap();
+        } else {
+            write!(into, "{} + {}i", real, imaginary).unwrap();
+        }
+        if has_unit {
+            into.push(')');
             self.display_unit.describe(into, instance);
         }
     }
 }
 
+impl Describe for Scalar {
+    fn describe(&self, into: &mut String, instance: &Instance) {
+        self.describe_in(into, instance, true);
+    }
+}
+
+/// Errs if the exponents of the resulting unit are too large to represent.
 impl Mul for Scalar {
-    type Output = Self;
-    fn mul(self, rhs: Self) -> Self {
+    type Output = Result<Self, ()>;
+    fn mul(self, rhs: Self) -> Self::Output {
         use Precision::*;
         // https://www.utm.edu/staff/cerkal/Lect4.html
-        let new_precision = match ((self.value, self.precision), (rhs.value, rhs.precision)) {
+        let (lhs_value, rhs_value) = (self.precision_value(), rhs.precision_value());
+        let (lhs_precision, rhs_precision) = (self.complex_precision(), rhs.complex_precision());
+        let new_precision = match ((lhs_value, lhs_precision), (rhs_value, rhs_precision)) {
+            ((_, Interval(..)), _) | (_, (_, Interval(..))) => {
+                Interval(self.bounds() * rhs.bounds())
+            }
             ((_, Exact), (_, Exact)) => Exact,
             ((_, Exact), (_, other)) => other,
             ((_, other), (_, Exact)) => other,
@@ -132,21 +593,41 @@ impl Mul for Scalar {
                 PercentError((pct * pct + rhs_pct * rhs_pct).sqrt())
             }
         };
-        Self {
-            value: self.value * rhs.value,
+        let mut res = Self {
+            value: self.value.clone() * rhs.value.clone(),
+            imaginary: None,
             precision: new_precision,
-            unit: self.unit * rhs.unit,
-            display_unit: self.display_unit * rhs.display_unit,
+            unit: (self.unit.clone() * rhs.unit.clone())?,
+            display_unit: (self.display_unit.clone() * rhs.display_unit.clone())?,
+            polar: self.polar.clone(),
+            kind: if rhs.is_plain_number() {
+                self.kind.clone()
+            } else if self.is_plain_number() {
+                rhs.kind.clone()
+            } else {
+                None
+            },
+        };
+        if self.is_complex() || rhs.is_complex() {
+            res.set_parts(complex_mul(self.parts(), rhs.parts()));
+            res.precision = res.complex_precision();
         }
+        Ok(res)
     }
 }
 
+/// Errs if the exponents of the resulting unit are too large to represent.
 impl Div for Scalar {
-    type Output = Self;
-    fn div(self, rhs: Self) -> Self {
+    type Output = Result<Self, ()>;
+    fn div(self, rhs: Self) -> Self::Output {
         use Precision::*;
         // https://www.utm.edu/staff/cerkal/Lect4.html
-        let new_precision = match ((self.value, self.precision), (rhs.value, rhs.precision)) {
+        let (lhs_value, rhs_value) = (self.precision_value(), rhs.precision_value());
+        let (lhs_precision, rhs_precision) = (self.complex_precision(), rhs.complex_precision());
+        let new_precision = match ((lhs_value, lhs_precision), (rhs_value, rhs_precision)) {
+            ((_, Interval(..)), _) | (_, (_, Interval(..))) => {
+                Interval(self.bounds() / rhs.bounds())
+            }
             ((_, Exact), (_, Exact)) => Exact,
             ((_, Exact), (_, other)) => other,
             ((_, other), (_, Exact)) => other,
@@ -157,12 +638,24 @@ impl Div for Scalar {
                 PercentError((pct * pct + rhs_pct * rhs_pct).sqrt())
             }
         };
-        Self {
-            value: self.value / rhs.value,
+        let mut res = Self {
+            value: self.value.clone() / rhs.value.clone(),
+            imaginary: None,
             precision: new_precision,
-            unit: self.unit / rhs.unit,
-            display_unit: self.display_unit / rhs.display_unit,
+            unit: (self.unit.clone() / rhs.unit.clone())?,
+            display_unit: (self.display_unit.clone() / rhs.display_unit.clone())?,
+            polar: self.polar.clone(),
+            kind: if rhs.is_plain_number() {
+                self.kind.clone()
+            } else {
+                None
+            },
+        };
+        if self.is_complex() || rhs.is_complex() {
+            res.set_parts(complex_div(self.parts(), rhs.parts()));
+            res.precision = res.complex_precision();
         }
+        Ok(res)
     }
 }
 
@@ -170,6 +663,10 @@ impl Neg for Scalar {
     type Output = Self;
     fn neg(mut self) -> Self {
         self.value = -self.value;
+        self.imaginary = self.imaginary.map(Neg::neg);
+        if let Precision::Interval(bounds) = self.precision {
+            self.precision = Precision::Interval(-bounds);
+        }
         self
     }
 }
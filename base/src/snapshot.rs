@@ -0,0 +1,249 @@
+//! Saving and restoring the complete state of an [`Instance`], so that large preludes can be
+//! loaded once and shipped as precomputed environments.
+//!
+//! Snapshots come in two flavors: human readable JSON and a compact binary form. Both contain the
+//! same data and preserve every `StorageId`, so units, unit classes and entity classes keep
+//! their identities across a save / load cycle.
+
+use crate::{prelude::*, storage::forget_loaded_ids};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Bumped whenever the layout of a snapshot changes in a way that old snapshots can no longer be
+/// read.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 8;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    format_version: u32,
+    instance: &'a Instance,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    format_version: u32,
+    instance: Instance,
+}
+
+impl Instance {
+    fn as_snapshot(&self) -> SnapshotRef<'_> {
+        SnapshotRef {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            instance: self,
+        }
+    }
+
+    /// Fails if the snapshot was written by an incompatible version, or refers to items it
+    /// doesn't contain.
+    fn from_snapshot(snapshot: Snapshot) -> Result<Self, ()> {
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(());
+        }
+        if !snapshot.instance.holds_loaded_ids() {
+            return Err(());
+        }
+        Ok(snapshot.instance)
+    }
+
+    pub fn to_json(&self) -> Result<String, ()> {
+        serde_json::to_string(&self.as_snapshot()).map_err(|_| ())
+    }
+
+    /// Returns Err(()) if the text is not a snapshot or was written by an incompatible version.
+    pub fn from_json(json: &str) -> Result<Self, ()> {
+        forget_loaded_ids();
+        Self::from_snapshot(serde_json::from_str(json).map_err(|_| ())?)
+    }
+
+    pub fn to_binary(&self) -> Result<Vec<u8>, ()> {
+        bincode::serialize(&self.as_snapshot()).map_err(|_| ())
+    }
+
+    /// Returns Err(()) if the bytes are not a snapshot or were written by an incompatible version.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, ()> {
+        forget_loaded_ids();
+        Self::from_snapshot(bincode::deserialize(bytes).map_err(|_| ())?)
+    }
+}
+
+/// Writes a map in key order so that snapshots of identical instances are identical.
+pub(crate) fn serialize_sorted_map<K, V, S>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    K: Ord + Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// Writes a set in order so that snapshots of identical instances are identical.
+pub(crate) fn serialize_sorted_set<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Ord + Serialize,
+    S: Serializer,
+{
+    set.iter().collect::<BTreeSet<_>>().serialize(serializer)
+}
+
+/// Writes floats as JSON numbers where possible. JSON has no way to write infinities or NaN, so
+/// those are written as the strings "inf", "-inf" and "NaN" instead. Binary snapshots can hold
+/// any float as it is.
+pub(crate) mod float {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() || !serializer.is_human_readable() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_str(&value.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Float {
+            Number(f64),
+            Text(String),
+        }
+        if !deserializer.is_human_readable() {
+            return f64::deserialize(deserializer);
+        }
+        match Float::deserialize(deserializer)? {
+            Float::Number(value) => Ok(value),
+            Float::Text(text) => match text.parse::<f64>() {
+                Ok(value) if !value.is_finite() => Ok(value),
+                _ => Err(D::Error::custom("expected a number")),
+            },
+        }
+    }
+}
+
+/// Writes a map as a list of `[key, value]` pairs, for maps whose keys can't be used as JSON
+/// object keys.
+pub(crate) mod map_as_pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Ord + Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{evaluate, instance_with, run};
+
+    const DEFINITIONS: &str = r#"
+        make entity_class called Circle
+        make derived_unit called Foot, Feet { symbol: "ft", value: 0.3048 * Meters, }
+        make label called Velocity for Length / Time
+        make value called MyPizza { Circle, Radius: 0.1 * Meters, }
+    "#;
+
+    fn check_restored(original: &Instance, restored: &Instance) {
+        for expression in &[
+            "3 * Kilometers / Second",
+            "12 * Feet in Centimeters",
+            "(1 * Meter / Millisecond) is Velocity",
+            "MyPizza is Circle",
+        ] {
+            assert_eq!(
+                evaluate(original, expression).unwrap(),
+                evaluate(restored, expression).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let instance = instance_with(DEFINITIONS);
+        let json = instance.to_json().unwrap();
+        let restored = Instance::from_json(&json).unwrap();
+        check_restored(&instance, &restored);
+        // Snapshots must not depend on hashing order.
+        assert_eq!(json, restored.to_json().unwrap());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let instance = instance_with(DEFINITIONS);
+        let bytes = instance.to_binary().unwrap();
+        let restored = Instance::from_binary(&bytes).unwrap();
+        check_restored(&instance, &restored);
+        assert_eq!(bytes, restored.to_binary().unwrap());
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        let instance = instance_with(DEFINITIONS);
+        let json = instance.to_json().unwrap().replacen(
+            &format!("\"format_version\":{}", SNAPSHOT_FORMAT_VERSION),
+            "\"format_version\":0",
+            1,
+        );
+        assert!(Instance::from_json(&json).is_err());
+        let mut bytes = instance.to_binary().unwrap();
+        bytes[0] = bytes[0].wrapping_add(1);
+        assert!(Instance::from_binary(&bytes).is_err());
+    }
+
+    #[test]
+    fn non_finite_values() {
+        let mut instance = instance_with(DEFINITIONS);
+        run(
+            &mut instance,
+            r#"
+            make label called Huge for 1 / 0
+            make label called Tiny for -1 / 0
+            make label called Nothing for 0 / 0
+            "#,
+        )
+        .unwrap();
+        let restored = Instance::from_json(&instance.to_json().unwrap()).unwrap();
+        assert_eq!(evaluate(&restored, "Huge").unwrap(), "inf");
+        assert_eq!(evaluate(&restored, "Tiny").unwrap(), "-inf");
+        assert_eq!(evaluate(&restored, "Nothing").unwrap(), "NaN");
+    }
+
+    #[test]
+    fn reject_missing_items() {
+        let json = instance_with(DEFINITIONS).to_json().unwrap();
+        let kilometer = "\"prefix_origin\":{\"unit\":0,\"prefix\":7}";
+        for (id, missing) in &[
+            // The entity class of MyPizza, the unit class of Feet and the unit Foot refers to.
+            ("\"classes\":[2]", "\"classes\":[1000]"),
+            (
+                "[[[1,1],0]]}},\"symbol\":\"ft\"",
+                "[[[1,1],1000]]}},\"symbol\":\"ft\"",
+            ),
+            ("[[[1,1],42]]", "[[[1,1],1000]]"),
+            // The unit and prefix Kilometers was made from.
+            (kilometer, "\"prefix_origin\":{\"unit\":1000,\"prefix\":7}"),
+            (kilometer, "\"prefix_origin\":{\"unit\":0,\"prefix\":1000}"),
+        ] {
+            assert!(json.contains(id));
+            assert!(Instance::from_json(&json.replacen(id, missing, 1)).is_err());
+        }
+    }
+}
@@ -0,0 +1,804 @@
+//! Numeric solutions of equations which can't be rearranged, like Kepler's equation, and of
+//! systems of simultaneous equations. Solvers work on values in base units, so the unit an unknown
+//! is written in doesn't change the result.
+
+use crate::{
+    data::{Data, MetaData, ValueData},
+    expression::Expression,
+    matrix::count,
+    prelude::*,
+    symbolic::linear_form,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// How the search for a solution starts.
+#[derive(Clone, Debug)]
+pub enum SolveStart {
+    /// An initial guess for Newton's method.
+    Near(Expression),
+    /// Two values on either side of the solution, for Brent's method.
+    Between(Expression, Expression),
+}
+
+/// Why a system of equations couldn't be solved. Equations are numbered from zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SystemError {
+    /// The equation adds or compares quantities of different unit classes.
+    InconsistentUnits(usize),
+    /// The equation couldn't be evaluated, like when it uses a name which isn't defined.
+    Unevaluable(usize),
+    /// The starting value given for the unknown couldn't be evaluated.
+    BadStart(String),
+    /// Nothing in the equations fixes the unit class of the unknown.
+    UnknownUnits(String),
+    /// There are fewer independent equations than unknowns.
+    Underdetermined,
+    /// The equations contradict each other.
+    Inconsistent,
+    /// Nonlinear systems are solved from a starting value for every unknown.
+    NeedsStart,
+    /// Nonlinear systems need exactly as many equations as unknowns.
+    Overdetermined,
+    NoConvergence,
+}
+
+impl Display for SystemError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InconsistentUnits(index) => write!(
+                f,
+                "equation {} mixes quantities of different unit classes",
+                index + 1
+            ),
+            Self::Unevaluable(index) => write!(f, "equation {} could not be evaluated", index + 1),
+            Self::BadStart(name) => {
+                write!(f, "the starting value of {} could not be evaluated", name)
+            }
+            Self::UnknownUnits(name) => write!(
+                f,
+                "the unit class of {} can't be worked out, give it a starting value",
+                name
+            ),
+            Self::Underdetermined => {
+                write!(f, "there are not enough equations to fix every unknown")
+            }
+            Self::Inconsistent => write!(f, "the equations contradict each other"),
+            Self::NeedsStart => write!(
+                f,
+                "nonlinear systems need a starting value for every unknown"
+            ),
+            Self::Overdetermined => {
+                write!(
+                    f,
+                    "nonlinear systems need exactly as many equations as unknowns"
+                )
+            }
+            Self::NoConvergence => write!(f, "the solver did not converge"),
+        }
+    }
+}
+
+const MAX_ITERATIONS: usize = 200;
+/// Solutions are accurate to this fraction of their value.
+const TOLERANCE: f64 = 1e-12;
+
+/// Newton's method, with the derivative estimated by central differences. Returns the solution
+/// and the size of the last step.
+fn newton(f: impl Fn(f64) -> Result<f64, ()>, guess: f64) -> Result<(f64, f64), ()> {
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let fx = f(x)?;
+        if fx == 0.0 {
+            return Ok((x, 0.0));
+        }
+        let h = if x == 0.0 { 1e-7 } else { 1e-7 * x.abs() };
+        let derivative = (f(x + h)? - f(x - h)?) / (2.0 * h);
+        let step = fx / derivative;
+        if !step.is_finite() {
+            return Err(());
+        }
+        x -= step;
+        if step.abs() <= TOLERANCE * x.abs() || step.abs() < f64::MIN_POSITIVE {
+            return Ok((x, step.abs()));
+        }
+    }
+    Err(())
+}
+
+/// Brent's method, which needs the function to change sign between the two values. Returns the
+/// solution and the width of the final bracket.
+fn brent(f: impl Fn(f64) -> Result<f64, ()>, a: f64, b: f64) -> Result<(f64, f64), ()> {
+    let (mut a, mut b) = (a, b);
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+    if fa == 0.0 {
+        return Ok((a, 0.0));
+    }
+    if fa.signum() == fb.signum() {
+        return Err(());
+    }
+    let (mut c, mut fc) = (a, fa);
+    let (mut d, mut e) = (b - a, b - a);
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+        let tolerance = 2.0 * f64::EPSILON * b.abs() + 0.5 * TOLERANCE * b.abs();
+        let half = 0.5 * (c - b);
+        if half.abs() <= tolerance || fb == 0.0 {
+            return Ok((b, (c - b).abs()));
+        }
+        if e.abs() >= tolerance && fa.abs() > fb.abs() {
+            // Inverse quadratic interpolation, or the secant method when only two points differ.
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * half * s, 1.0 - s)
+            } else {
+                let (q, r) = (fa / fc, fb / fc);
+                (
+                    s * (2.0 * half * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+            if 2.0 * p < (3.0 * half * q - (tolerance * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = half;
+                e = d;
+            }
+        } else {
+            // Bisection.
+            d = half;
+            e = d;
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > tolerance {
+            d
+        } else {
+            tolerance.copysign(half)
+        };
+        fb = f(b)?;
+    }
+    Err(())
+}
+
+/// An entry of a row given to eliminate.
+fn entry(value: Number, precision: Precision) -> Scalar {
+    Scalar::new(
+        value,
+        precision,
+        CompositeUnitClass::identity(),
+        CompositeUnit::identity(),
+    )
+}
+
+/// Solves `matrix * x = constants`, given as rows of dimensionless coefficients followed by the
+/// constant, by Gauss-Jordan elimination. Stays exact for exact numbers, and the precision of
+/// each coefficient carries through to the solutions like in any other arithmetic.
+fn eliminate(mut rows: Vec<Vec<Scalar>>, unknowns: usize) -> Result<Vec<Scalar>, SystemError> {
+    // Approximate numbers this much smaller than the largest in their column count as zero.
+    let scales: Vec<f64> = (0..=unknowns)
+        .map(|column| {
+            let values = rows
+                .iter()
+                .map(|row| row[column].raw_value().to_f64().abs());
+            values.fold(0.0, f64::max)
+        })
+        .collect();
+    let negligible = |value: &Scalar, column: usize| {
+        let value = value.raw_value();
+        value.is_zero() || (!value.is_exact() && value.to_f64().abs() <= 1e-12 * scales[column])
+    };
+    // Every entry is a dimensionless scalar, so arithmetic on them can't fail.
+    const DIMENSIONLESS: &str = "rows are dimensionless";
+    let mut pivots = 0;
+    for column in 0..unknowns {
+        let candidates =
+            (pivots..rows.len()).filter(|&row| !negligible(&rows[row][column], column));
+        let best = candidates.max_by(|&a, &b| {
+            let (a, b) = (
+                rows[a][column].raw_value().to_f64().abs(),
+                rows[b][column].raw_value().to_f64().abs(),
+            );
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let best = match best {
+            Some(best) => best,
+            None => continue,
+        };
+        rows.swap(pivots, best);
+        let pivot = rows[pivots][column].clone();
+        let pivot_row: Vec<_> = rows[pivots]
+            .iter()
+            .map(|value| (value.clone() / pivot.clone()).expect(DIMENSIONLESS))
+            .collect();
+        for (index, row) in rows.iter_mut().enumerate() {
+            if index == pivots {
+                continue;
+            }
+            let factor = row[column].clone();
+            for (value, pivot_value) in row.iter_mut().zip(&pivot_row) {
+                let product = (factor.clone() * pivot_value.clone()).expect(DIMENSIONLESS);
+                *value = value.sub(&product).expect(DIMENSIONLESS);
+            }
+        }
+        rows[pivots] = pivot_row;
+        pivots += 1;
+    }
+    // Rows without a pivot have no unknowns left, so their constant has to be zero.
+    if rows[pivots..]
+        .iter()
+        .any(|row| !negligible(&row[unknowns], unknowns))
+    {
+        return Err(SystemError::Inconsistent);
+    }
+    if pivots < unknowns {
+        return Err(SystemError::Underdetermined);
+    }
+    Ok(rows[..unknowns]
+        .iter()
+        .map(|row| row[unknowns].clone())
+        .collect())
+}
+
+/// Newton's method for several unknowns, with the Jacobian estimated by central differences.
+fn newton_system(
+    f: impl Fn(&[f64]) -> Result<Vec<f64>, SystemError>,
+    guess: Vec<f64>,
+) -> Result<Vec<f64>, SystemError> {
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let fx = f(&x)?;
+        if fx.iter().all(|value| *value == 0.0) {
+            return Ok(x);
+        }
+        let mut rows = vec![Vec::new(); fx.len()];
+        for index in 0..x.len() {
+            let h = if x[index] == 0.0 {
+                1e-7
+            } else {
+                1e-7 * x[index].abs()
+            };
+            let (mut above, mut below) = (x.clone(), x.clone());
+            above[index] += h;
+            below[index] -= h;
+            let (above, below) = (f(&above)?, f(&below)?);
+            for (row, (above, below)) in rows.iter_mut().zip(above.iter().zip(below)) {
+                let slope = (above - below) / (2.0 * h);
+                row.push(entry(slope.into(), Precision::Exact));
+            }
+        }
+        for (row, value) in rows.iter_mut().zip(&fx) {
+            row.push(entry((*value).into(), Precision::Exact));
+        }
+        let steps = eliminate(rows, x.len()).map_err(|_| SystemError::NoConvergence)?;
+        let mut converged = true;
+        for (value, step) in x.iter_mut().zip(steps) {
+            let step = step.raw_value().to_f64();
+            if !step.is_finite() {
+                return Err(SystemError::NoConvergence);
+            }
+            *value -= step;
+            if step.abs() > TOLERANCE * value.abs() && step.abs() >= f64::MIN_POSITIVE {
+                converged = false;
+            }
+        }
+        if converged {
+            return Ok(x);
+        }
+    }
+    Err(SystemError::NoConvergence)
+}
+
+impl Instance {
+    /// Resolves an expression which should give a real scalar. Units count as one of themselves.
+    pub(crate) fn resolve_real(
+        &self,
+        expression: &Expression,
+        bindings: &Bindings,
+    ) -> Result<Scalar, ()> {
+        match self.resolve_expression_with(expression, Default::default(), bindings)? {
+            Data::Value(ValueData::Scalar(scalar)) if !scalar.is_complex() => Ok(scalar),
+            Data::Meta(MetaData::Unit(unit)) => unit.as_scalar(self),
+            _ => Err(()),
+        }
+    }
+
+    /// Finds a value of `variable` which makes both sides of the equation equal. The unknown has
+    /// the unit class and display unit of the starting value. The precision of the result is
+    /// estimated from the last step the solver took, plus the uncertainty of both sides of the
+    /// equation divided by how steeply their difference changes with the unknown.
+    pub fn solve(
+        &self,
+        lhs: &Expression,
+        rhs: &Expression,
+        variable: &str,
+        start: &SolveStart,
+    ) -> Result<Scalar, ()> {
+        let no_bindings = Bindings::new();
+        let (first, second) = match start {
+            SolveStart::Near(guess) => (self.resolve_real(guess, &no_bindings)?, None),
+            SolveStart::Between(lower, upper) => (
+                self.resolve_real(lower, &no_bindings)?,
+                Some(self.resolve_real(upper, &no_bindings)?),
+            ),
+        };
+        let unknown = |value: f64| {
+            Scalar::new(
+                value.into(),
+                Precision::Exact,
+                first.unit().clone(),
+                first.display_unit().clone(),
+            )
+        };
+        let sides = |value: f64| -> Result<(Scalar, Scalar), ()> {
+            let mut bindings = Bindings::new();
+            bindings.insert(variable.to_owned(), unknown(value).into());
+            Ok((
+                self.resolve_real(lhs, &bindings)?,
+                self.resolve_real(rhs, &bindings)?,
+            ))
+        };
+        let difference = |value: f64| -> Result<f64, ()> {
+            let (lhs, rhs) = sides(value)?;
+            let difference = lhs.sub(&rhs)?.raw_value().to_f64();
+            if difference.is_finite() {
+                Ok(difference)
+            } else {
+                Err(())
+            }
+        };
+        let start = first.raw_value().to_f64();
+        let (solution, error) = match second {
+            None => newton(difference, start)?,
+            Some(second) if second.unit() == first.unit() => {
+                brent(difference, start, second.raw_value().to_f64())?
+            }
+            Some(_) => return Err(()),
+        };
+        let (lhs, rhs) = sides(solution)?;
+        let spread = |side: &Scalar| {
+            let bounds = side.bounds();
+            (bounds.upper - bounds.lower) / 2.0
+        };
+        let residual_error = spread(&lhs) + spread(&rhs);
+        let error = if residual_error > 0.0 {
+            let h = if solution == 0.0 {
+                1e-7
+            } else {
+                1e-7 * solution.abs()
+            };
+            let slope = (difference(solution + h)? - difference(solution - h)?) / (2.0 * h);
+            let input_error = residual_error / slope.abs();
+            if !input_error.is_finite() {
+                return Err(());
+            }
+            error + input_error
+        } else {
+            error
+        };
+        let mut result = unknown(solution);
+        if error > 0.0 && solution != 0.0 {
+            result = Scalar::new(
+                solution.into(),
+                Precision::PercentError(error / solution.abs()),
+                first.unit().clone(),
+                first.display_unit().clone(),
+            );
+        }
+        Ok(result)
+    }
+}
+
+impl Instance {
+    /// Solves simultaneous equations for the listed unknowns, each of which can have a starting
+    /// value. Systems which are linear in the unknowns are solved by elimination, which is exact
+    /// with the `exact` feature, and the unit class of each unknown is worked out from the
+    /// equations. Other systems are solved by Newton's method from the starting values.
+    pub fn solve_system(
+        &self,
+        equations: &[(Expression, Expression)],
+        unknowns: &[(String, Option<Expression>)],
+    ) -> Result<Vec<Scalar>, SystemError> {
+        let names: Vec<_> = unknowns.iter().map(|(name, _)| name.clone()).collect();
+        let starts = unknowns
+            .iter()
+            .map(|(name, start)| match start {
+                Some(start) => self
+                    .resolve_real(start, &Bindings::new())
+                    .map(Some)
+                    .map_err(|_| SystemError::BadStart(name.clone())),
+                None => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let forms: Option<Vec<_>> = equations
+            .iter()
+            .map(|(lhs, rhs)| linear_form(lhs, rhs, &names))
+            .collect();
+        match forms {
+            Some(forms) => self.solve_linear(forms, &names, &starts),
+            None => self.solve_nonlinear(equations, &names, &starts),
+        }
+    }
+
+    fn solve_linear(
+        &self,
+        forms: Vec<(Vec<Option<Expression>>, Option<Expression>)>,
+        names: &[String],
+        starts: &[Option<Scalar>],
+    ) -> Result<Vec<Scalar>, SystemError> {
+        let mut rows = Vec::new();
+        for (index, (coefficients, constant)) in forms.into_iter().enumerate() {
+            let evaluate = |expression: Option<Expression>| match expression {
+                Some(expression) => self
+                    .resolve_real(&expression, &Bindings::new())
+                    .map(Some)
+                    .map_err(|_| SystemError::Unevaluable(index)),
+                None => Ok(None),
+            };
+            let coefficients = coefficients
+                .into_iter()
+                .map(evaluate)
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.push((coefficients, evaluate(constant)?));
+        }
+        if (0..names.len()).any(|column| rows.iter().all(|(row, _)| row[column].is_none())) {
+            return Err(SystemError::Underdetermined);
+        }
+
+        // Each unknown gets the unit that makes its terms match the rest of its equation.
+        let mut units: Vec<Option<(CompositeUnitClass, CompositeUnit)>> = starts
+            .iter()
+            .map(|start| {
+                let start = start.as_ref()?;
+                Some((start.unit().clone(), start.display_unit().clone()))
+            })
+            .collect();
+        let term_unit =
+            |coefficient: &Scalar, (class, display): &(CompositeUnitClass, CompositeUnit)| {
+                Ok((
+                    (coefficient.unit().clone() * class.clone())?,
+                    (coefficient.display_unit().clone() * display.clone())?,
+                ))
+            };
+        let equation_unit = |row: &[Option<Scalar>], constant: &Option<Scalar>, units: &[_]| {
+            if let Some(constant) = constant {
+                return Some(Ok((
+                    constant.unit().clone(),
+                    constant.display_unit().clone(),
+                )));
+            }
+            row.iter()
+                .zip(units)
+                .find_map(|(coefficient, unit)| match (coefficient, unit) {
+                    (Some(coefficient), Some(unit)) => Some(term_unit(coefficient, unit)),
+                    _ => None,
+                })
+        };
+        loop {
+            let mut changed = false;
+            for (index, (row, constant)) in rows.iter().enumerate() {
+                let overflow = |_| SystemError::InconsistentUnits(index);
+                let (class, display) = match equation_unit(row, constant, &units) {
+                    Some(unit) => unit.map_err(overflow)?,
+                    None => continue,
+                };
+                for (coefficient, unit) in row.iter().zip(units.iter_mut()) {
+                    if let (Some(coefficient), None) = (coefficient, &unit) {
+                        *unit = Some((
+                            (class.clone() / coefficient.unit().clone()).map_err(overflow)?,
+                            (display.clone() / coefficient.display_unit().clone())
+                                .map_err(overflow)?,
+                        ));
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let units = units
+            .into_iter()
+            .zip(names)
+            .map(|(unit, name)| unit.ok_or_else(|| SystemError::UnknownUnits(name.clone())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut matrix = Vec::new();
+        for (index, (row, constant)) in rows.iter().enumerate() {
+            let terms = row
+                .iter()
+                .zip(&units)
+                .filter_map(|(coefficient, unit)| Some(term_unit(coefficient.as_ref()?, unit)))
+                .map(|term| term.map(|(class, _)| class))
+                .collect::<Result<Vec<_>, ()>>()
+                .map_err(|_| SystemError::InconsistentUnits(index))?;
+            let terms = terms.into_iter();
+            let mut classes = terms.chain(constant.iter().map(|c| c.unit().clone()));
+            if let Some(class) = classes.next() {
+                if classes.any(|term| term != class) {
+                    return Err(SystemError::InconsistentUnits(index));
+                }
+            }
+            // Coefficients are written in base units, so their unit classes can be left out.
+            let entry = |coefficient: &Option<Scalar>| match coefficient {
+                Some(c) => entry(c.raw_value().clone(), c.precision()),
+                None => count(0),
+            };
+            let mut values: Vec<_> = row.iter().map(entry).collect();
+            values.push(entry(constant));
+            matrix.push(values);
+        }
+        let values = eliminate(matrix, names.len())?;
+        Ok(values
+            .into_iter()
+            .zip(units)
+            .map(|(value, (class, display))| {
+                Scalar::new(value.raw_value().clone(), value.precision(), class, display)
+            })
+            .collect())
+    }
+
+    fn solve_nonlinear(
+        &self,
+        equations: &[(Expression, Expression)],
+        names: &[String],
+        starts: &[Option<Scalar>],
+    ) -> Result<Vec<Scalar>, SystemError> {
+        let starts = starts
+            .iter()
+            .map(|start| start.clone().ok_or(SystemError::NeedsStart))
+            .collect::<Result<Vec<_>, _>>()?;
+        if equations.len() < names.len() {
+            return Err(SystemError::Underdetermined);
+        } else if equations.len() > names.len() {
+            return Err(SystemError::Overdetermined);
+        }
+        let unknown = |start: &Scalar, value: f64| {
+            Scalar::new(
+                value.into(),
+                Precision::Exact,
+                start.unit().clone(),
+                start.display_unit().clone(),
+            )
+        };
+        let residuals = |values: &[f64]| -> Result<Vec<f64>, SystemError> {
+            let mut bindings = Bindings::new();
+            for ((name, start), value) in names.iter().zip(&starts).zip(values) {
+                bindings.insert(name.clone(), unknown(start, *value).into());
+            }
+            let residual = |index: usize, (lhs, rhs): &(Expression, Expression)| {
+                let evaluate = |side| {
+                    self.resolve_real(side, &bindings)
+                        .map_err(|_| SystemError::Unevaluable(index))
+                };
+                let difference = evaluate(lhs)?
+                    .sub(&evaluate(rhs)?)
+                    .map_err(|_| SystemError::InconsistentUnits(index))?;
+                let difference = difference.raw_value().to_f64();
+                if difference.is_finite() {
+                    Ok(difference)
+                } else {
+                    Err(SystemError::Unevaluable(index))
+                }
+            };
+            equations
+                .iter()
+                .enumerate()
+                .map(|(index, equation)| residual(index, equation))
+                .collect()
+        };
+        let guess = starts
+            .iter()
+            .map(|start| start.raw_value().to_f64())
+            .collect();
+        let values = newton_system(residuals, guess)?;
+        Ok(starts
+            .iter()
+            .zip(values)
+            .map(|(start, value)| unknown(start, value))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Describe;
+    use crate::{
+        statement::{parse_statements, Statement},
+        test_util::{instance_with, run},
+    };
+
+    const DEFINITIONS: &str = r#"
+        make derived_unit called Foot, Feet { value: 0.3048 * Meters, symbol: "ft", }
+    "#;
+
+    fn solve_scalar(instance: &Instance, source: &str) -> Result<Scalar, ()> {
+        let (rest, statements) = parse_statements(source).unwrap();
+        assert_eq!(rest, "");
+        match statements.into_iter().next() {
+            Some(Statement::Solve(lhs, rhs, variable, start)) => {
+                instance.solve(&lhs, &rhs, &variable, &start)
+            }
+            _ => panic!("not a solve statement"),
+        }
+    }
+
+    fn solve(instance: &Instance, source: &str) -> Result<(f64, String), ()> {
+        let result = solve_scalar(instance, source)?;
+        let mut unit = String::new();
+        result.display_unit().describe(&mut unit, instance);
+        Ok((result.display_value(instance), unit))
+    }
+
+    #[test]
+    fn newton() {
+        let instance = instance_with(DEFINITIONS);
+        let (value, unit) =
+            solve(&instance, "solve x^2 = 2 * Meters^2 for x near 1 * Feet").unwrap();
+        assert!((value - 2f64.sqrt() / 0.3048).abs() < 1e-9);
+        assert_eq!(unit, "ft");
+        // Kepler's equation for the eccentric anomaly.
+        let (value, _) = solve(&instance, "solve 1 = e - 0.5 * sin(e) for e near 1").unwrap();
+        assert!((value - 0.5 * value.sin() - 1.0).abs() < 1e-12);
+        assert!(solve(&instance, "solve x = 2 * Seconds for x near 1 * Meters").is_err());
+        assert!(solve(&instance, "solve x^2 = 0 - 1 for x near 1").is_err());
+    }
+
+    #[test]
+    fn uncertain_inputs() {
+        let instance = instance_with(DEFINITIONS);
+        let result = solve_scalar(&instance, "solve x * interval(1, 2) = 3 for x near 1").unwrap();
+        assert!((result.display_value(&instance) - 2.0).abs() < 1e-9);
+        // The left side is 3 ± 1 at the solution and changes by 1.5 per unit of x.
+        let error = result.precision().percent_error(2.0);
+        assert!((error - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn brent() {
+        let instance = instance_with(DEFINITIONS);
+        let (value, _) = solve(&instance, "solve x^3 - 2 * x = 5 for x between 2 and 3").unwrap();
+        assert!((value - 2.0945514815423265).abs() < 1e-12);
+        let (value, unit) = solve(
+            &instance,
+            "solve x * x = 9 * Meters^2 for x between 0 * Meters and 10 * Meters",
+        )
+        .unwrap();
+        assert!((value - 3.0).abs() < 1e-12);
+        assert_eq!(unit, "m");
+        assert!(solve(&instance, "solve x^2 = 9 for x between 4 and 10").is_err());
+        assert!(solve(&instance, "solve x = 1 for x between 0 and 2 * Meters").is_err());
+    }
+
+    fn solve_system(instance: &Instance, source: &str) -> Result<Vec<String>, SystemError> {
+        let (rest, statements) = parse_statements(source).unwrap();
+        assert_eq!(rest, "");
+        match statements.into_iter().next() {
+            Some(Statement::SolveSystem(equations, unknowns)) => {
+                let solutions = instance.solve_system(&equations, &unknowns)?;
+                Ok(solutions
+                    .iter()
+                    .map(|solution| {
+                        let mut description = String::new();
+                        solution.describe(&mut description, instance);
+                        description
+                    })
+                    .collect())
+            }
+            _ => panic!("not a system of equations"),
+        }
+    }
+
+    #[test]
+    fn linear_systems() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            solve_system(
+                &instance,
+                "solve { a + b = 10 * Meters, a - b = 2 * Meters } for a, b"
+            )
+            .unwrap(),
+            ["6m", "4m"]
+        );
+        assert_eq!(
+            solve_system(&instance, "solve { 2 * Seconds * v = 10 * Meters } for v").unwrap(),
+            ["5m/s"]
+        );
+        assert_eq!(
+            solve_system(&instance, "solve { a + b = 1 * Meters } for a, b"),
+            Err(SystemError::Underdetermined)
+        );
+        assert_eq!(
+            solve_system(&instance, "solve { a + b = 1, 2 * a + 2 * b = 3 } for a, b"),
+            Err(SystemError::Inconsistent)
+        );
+        assert_eq!(
+            solve_system(
+                &instance,
+                "solve { a + b = 1 * Meters, a = 2 * Seconds } for a, b"
+            ),
+            Err(SystemError::InconsistentUnits(1))
+        );
+        assert_eq!(
+            solve_system(&instance, "solve { a = 2 * b } for a, b"),
+            Err(SystemError::UnknownUnits("a".to_owned()))
+        );
+        // Uncertain coefficients give uncertain solutions.
+        assert_eq!(
+            solve_system(&instance, "solve { interval(1, 2) * x = 3 * Meters } for x").unwrap(),
+            ["[1.5, 3] m"]
+        );
+    }
+
+    #[test]
+    fn system_errors_are_kept() {
+        let mut instance = instance_with(DEFINITIONS);
+        assert!(run(&mut instance, "solve { a + b = 1 * Meters } for a, b").is_err());
+        assert_eq!(
+            instance.take_error().unwrap(),
+            "there are not enough equations to fix every unknown"
+        );
+    }
+
+    #[cfg(feature = "exact")]
+    #[test]
+    fn exact_linear_systems() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            solve_system(
+                &instance,
+                "solve { 2 * x + y - z = 8, -3 * x - y + 2 * z = -11, -2 * x + y + 2 * z = -3 } \
+                 for x, y, z"
+            )
+            .unwrap(),
+            ["2", "3", "-1"]
+        );
+        assert_eq!(
+            solve_system(&instance, "solve { 3 * x = 1, x + y = 1 } for x, y").unwrap(),
+            [(1.0 / 3.0).to_string(), (2.0 / 3.0).to_string()]
+        );
+    }
+
+    #[test]
+    fn nonlinear_systems() {
+        let instance = instance_with(DEFINITIONS);
+        assert_eq!(
+            solve_system(
+                &instance,
+                "solve { x * y = 6 * Meters^2, x + y = 5 * Meters } \
+                 for x near 1 * Meters, y near 4 * Meters"
+            )
+            .unwrap(),
+            ["2m", "3m"]
+        );
+        assert_eq!(
+            solve_system(&instance, "solve { x * y = 6, x + y = 5 } for x, y"),
+            Err(SystemError::NeedsStart)
+        );
+        assert_eq!(
+            solve_system(
+                &instance,
+                "solve { x * y = 6 * Meters^2 } for x near 1 * Meters, y near 1 * Meters"
+            ),
+            Err(SystemError::Underdetermined)
+        );
+    }
+}
@@ -1,15 +1,95 @@
-use crate::expression::Expression;
+use crate::{
+    export::ExportFormat,
+    expression::Expression,
+    import::ImportFormat,
+    instance::{ItemKind, ItemRef},
+    plot::Scales,
+    solve::SolveStart,
+    table::TableFormat,
+};
 
 #[derive(Clone, Debug)]
 pub enum Statement {
     MakeUnitClass(Vec<String>),
+    /// Declares a unit class for angles, see UnitClass::angle.
+    MakeAngleClass(Vec<String>),
     MakeBaseUnit(Vec<String>, Expression),
     MakeDerivedUnit(Vec<String>, Expression),
     MakeEntityClass(Vec<String>, Expression),
     MakeLabel(Vec<String>, Expression),
     MakeValue(Vec<String>, Expression),
+    /// Declares a kind of quantity, like torque, as a label for the unit class it has.
+    MakeKind(Vec<String>, Expression),
+    /// Replaces an existing item with the one declared by the contained make statement.
+    Redefine(Box<Statement>),
+    Forget(ItemKind, String),
+    /// Changes or adds a property of a value, `set MyPizza.Radius = 0.15 * Meters`.
+    SetProperty(String, String, Expression),
+    /// Adds the classes and properties of an entity builder to a value,
+    /// `MyPizza += { Topping: "cheese" }`.
+    UpdateValue(String, Expression),
+
+    /// Writes the unit tables to the given path.
+    Export(ExportFormat, String),
+    /// Reads units from the unit database at the given path.
+    Import(ImportFormat, String),
 
     Show(Expression),
+    /// Prints a column named by the string, evaluated for every combination of the swept
+    /// variables.
+    Table(String, Expression, Vec<(String, Expression)>, TableFormat),
+    /// Prints the dimensionless products of the named quantities.
+    DimensionlessGroups(Vec<String>),
+    /// Prints the simplest products of the listed quantities with the unit class of the first.
+    SolveForUnits(String, Vec<String>),
+    /// Plots named series over a swept variable and writes the SVG to the given path.
+    Plot(
+        Vec<(String, Expression)>,
+        (String, Expression),
+        Scales,
+        String,
+    ),
+    /// Prints the expression with like terms collected and common factors cancelled.
+    Simplify(Expression),
+    /// Prints the equation rearranged to give the named variable.
+    Rearrange(Expression, Expression, String),
+    /// Numerically solves the equation for the named variable and prints the solution.
+    Solve(Expression, Expression, String, SolveStart),
+    /// Solves simultaneous equations for the named unknowns, each with an optional starting value.
+    SolveSystem(
+        Vec<(Expression, Expression)>,
+        Vec<(String, Option<Expression>)>,
+    ),
+}
+
+impl Statement {
+    /// For statements that declare an item which can later be redefined or forgotten, returns
+    /// that item along with the expression it was defined by.
+    pub fn declared_item(&self) -> Option<(ItemRef, &Expression)> {
+        let (kind, names, definition) = match self {
+            Self::MakeBaseUnit(names, definition) | Self::MakeDerivedUnit(names, definition) => {
+                (ItemKind::Unit, names, definition)
+            }
+            Self::MakeLabel(names, definition) | Self::MakeKind(names, definition) => {
+                (ItemKind::Label, names, definition)
+            }
+            Self::MakeValue(names, definition) => (ItemKind::Value, names, definition),
+            _ => return None,
+        };
+        let item = ItemRef {
+            kind,
+            name: names[0].clone(),
+        };
+        Some((item, definition))
+    }
+
+    /// For statements that change an existing value, returns the name they refer to it by.
+    pub fn updated_value(&self) -> Option<&String> {
+        match self {
+            Self::SetProperty(value, ..) | Self::UpdateValue(value, _) => Some(value),
+            _ => None,
+        }
+    }
 }
 
 mod parse {
@@ -18,12 +98,12 @@ mod parse {
     use super::*;
     use nom::{
         branch::alt,
-        bytes::complete::{tag, take, take_while1},
-        character::complete::{char, one_of},
-        combinator::not,
+        bytes::complete::{tag, take, take_while, take_while1},
+        character::complete::{char, one_of, satisfy},
+        combinator::{map, not, opt},
         error::{make_error, ErrorKind},
         multi::{many1, separated_list1},
-        sequence::delimited,
+        sequence::{delimited, pair, preceded, terminated},
         IResult,
     };
     use Statement::*;
@@ -41,7 +121,7 @@ mod parse {
             } else if let Ok((ni, _)) = tag::<_, _, PlainError>("\n")(input) {
                 input = ni;
                 comment = false;
-            } else if comment && input.len() > 0 {
+            } else if comment && !input.is_empty() {
                 input = take(1usize)(input)?.0;
             } else {
                 break;
@@ -50,6 +130,15 @@ mod parse {
         Ok((input, ()))
     }
 
+    /// Matches a keyword only if it isn't the start of a longer identifier, so that `setX.Y = 1`
+    /// is not read as `set X.Y = 1`.
+    fn keyword<'i>(word: &'static str) -> impl FnMut(&'i str) -> IResult<&'i str, &'i str> {
+        terminated(
+            tag(word),
+            not(satisfy(|c: char| c.is_alphanumeric() || c == '_')),
+        )
+    }
+
     fn identifier(input: &str) -> IResult<&str, String> {
         let (input, _) = not(one_of("0123456789"))(input)?;
         let (input, value) = take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)?;
@@ -57,27 +146,41 @@ mod parse {
     }
 
     fn parse_make(input: &str) -> IResult<&str, Statement> {
-        let (input, _) = tag("make")(input)?;
+        let (input, _) = keyword("make")(input)?;
+        let (input, _) = whitespace(input)?;
+        make_body(input)
+    }
+
+    fn parse_redefine(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("redefine")(input)?;
         let (input, _) = whitespace(input)?;
+        let (input, statement) = make_body(input)?;
+        Ok((input, Redefine(Box::new(statement))))
+    }
+
+    /// Everything in a make statement after the "make" keyword.
+    fn make_body(input: &str) -> IResult<&str, Statement> {
         let (input, label) = alt((
-            tag("unit_class"),
-            tag("base_unit"),
-            tag("derived_unit"),
-            tag("entity_class"),
-            tag("label"),
-            tag("value"),
+            keyword("unit_class"),
+            keyword("angle_class"),
+            keyword("base_unit"),
+            keyword("derived_unit"),
+            keyword("entity_class"),
+            keyword("label"),
+            keyword("value"),
+            keyword("kind"),
         ))(input)?;
         let (input, _) = whitespace(input)?;
-        let (input, _) = tag("called")(input)?;
+        let (input, _) = keyword("called")(input)?;
         let (input, names) =
             separated_list1(char(','), delimited(whitespace, identifier, whitespace))(input)?;
         let (mut input, _) = whitespace(input)?;
-        if label == "label" {
-            input = tag("for")(input)?.0;
+        if label == "label" || label == "kind" {
+            input = keyword("for")(input)?.0;
             input = whitespace(input)?.0;
         }
 
-        let mut parse_value = label != "unit_class";
+        let mut parse_value = label != "unit_class" && label != "angle_class";
         if label == "entity_class" {
             // Entity class may or may not have a value. If it has a value it will be an entity
             // builder. Check if there is the start of an entity builder before going and parsing
@@ -104,6 +207,7 @@ mod parse {
             input,
             match (label, value) {
                 ("unit_class", _) => MakeUnitClass(names),
+                ("angle_class", _) => MakeAngleClass(names),
                 ("base_unit", Some(value)) => MakeBaseUnit(names, value),
                 ("base_unit", None) => ret_error!(),
                 ("derived_unit", Some(value)) => MakeDerivedUnit(names, value),
@@ -119,21 +223,261 @@ mod parse {
                 ("label", None) => ret_error!(),
                 ("value", Some(value)) => MakeValue(names, value),
                 ("value", None) => ret_error!(),
+                ("kind", Some(value)) => MakeKind(names, value),
+                ("kind", None) => ret_error!(),
                 _ => unreachable!(),
             },
         ))
     }
 
+    fn parse_forget(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("forget")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, kind) = alt((keyword("unit"), keyword("label"), keyword("value")))(input)?;
+        let kind = match kind {
+            "unit" => ItemKind::Unit,
+            "label" => ItemKind::Label,
+            "value" => ItemKind::Value,
+            _ => unreachable!(),
+        };
+        let (input, _) = whitespace(input)?;
+        let (input, name) = identifier(input)?;
+        Ok((input, Forget(kind, name)))
+    }
+
+    fn parse_set(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("set")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, value) = identifier(input)?;
+        let (input, _) = char('.')(input)?;
+        let (input, property) = identifier(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, _) = char('=')(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, expression) = expression::parse_expression(input)?;
+        Ok((input, SetProperty(value, property, expression)))
+    }
+
+    fn parse_update(input: &str) -> IResult<&str, Statement> {
+        let (input, value) = identifier(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, _) = tag("+=")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, expression) = expression::parse_expression(input)?;
+        Ok((input, UpdateValue(value, expression)))
+    }
+
+    fn parse_export(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("export")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, format) = alt((keyword("json"), keyword("csv")))(input)?;
+        let format = match format {
+            "json" => ExportFormat::Json,
+            "csv" => ExportFormat::Csv,
+            _ => unreachable!(),
+        };
+        let (input, _) = whitespace(input)?;
+        let (input, _) = keyword("to")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, path) =
+            delimited(char('"'), take_while(|c| c != '"' && c != '\n'), char('"'))(input)?;
+        Ok((input, Export(format, path.to_owned())))
+    }
+
+    fn parse_import(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("import")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, format) = alt((keyword("ucum"), keyword("qudt")))(input)?;
+        let format = match format {
+            "ucum" => ImportFormat::Ucum,
+            "qudt" => ImportFormat::Qudt,
+            _ => unreachable!(),
+        };
+        let (input, _) = whitespace(input)?;
+        let (input, _) = keyword("from")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, path) =
+            delimited(char('"'), take_while(|c| c != '"' && c != '\n'), char('"'))(input)?;
+        Ok((input, Import(format, path.to_owned())))
+    }
+
+    fn sweep(input: &str) -> IResult<&str, (String, Expression)> {
+        let (input, name) = delimited(whitespace, identifier, whitespace)(input)?;
+        let (input, _) = char('=')(input)?;
+        let (input, values) = expression::parse_expression(input)?;
+        Ok((input, (name, values)))
+    }
+
+    /// `table name = expression for variable = values, ... as format`
+    fn parse_table(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("table")(input)?;
+        let (input, (column, expression)) = sweep(input)?;
+        let (input, _) = keyword("for")(input)?;
+        let (input, sweeps) = separated_list1(char(','), sweep)(input)?;
+        let (input, format) = opt(preceded(
+            pair(keyword("as"), whitespace),
+            alt((keyword("text"), keyword("markdown"), keyword("csv"))),
+        ))(input)?;
+        let format = match format {
+            None | Some("text") => TableFormat::Text,
+            Some("markdown") => TableFormat::Markdown,
+            Some("csv") => TableFormat::Csv,
+            _ => unreachable!(),
+        };
+        Ok((input, Table(column, expression, sweeps, format)))
+    }
+
+    /// `plot name = expression, ... for variable = values log_x log_y to "path"`
+    fn parse_plot(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("plot")(input)?;
+        let (input, series) = separated_list1(char(','), sweep)(input)?;
+        let (input, _) = keyword("for")(input)?;
+        let (mut input, variable) = sweep(input)?;
+        let mut scales = Scales::default();
+        while let Ok((rest, scale)) =
+            terminated(alt((keyword("log_x"), keyword("log_y"))), whitespace)(input)
+        {
+            match scale {
+                "log_x" => scales.log_x = true,
+                _ => scales.log_y = true,
+            }
+            input = rest;
+        }
+        let (input, _) = keyword("to")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, path) =
+            delimited(char('"'), take_while(|c| c != '"' && c != '\n'), char('"'))(input)?;
+        Ok((input, Plot(series, variable, scales, path.to_owned())))
+    }
+
+    fn parse_dimensionless_groups(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("dimensionless_groups")(input)?;
+        let (input, names) =
+            separated_list1(char(','), delimited(whitespace, identifier, whitespace))(input)?;
+        Ok((input, DimensionlessGroups(names)))
+    }
+
+    /// `solve_for_units target using quantity, ...`
+    fn parse_solve_for_units(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("solve_for_units")(input)?;
+        let (input, target) = delimited(whitespace, identifier, whitespace)(input)?;
+        let (input, _) = keyword("using")(input)?;
+        let (input, names) =
+            separated_list1(char(','), delimited(whitespace, identifier, whitespace))(input)?;
+        Ok((input, SolveForUnits(target, names)))
+    }
+
+    fn parse_simplify(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("simplify")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, value) = expression::parse_expression(input)?;
+        Ok((input, Simplify(value)))
+    }
+
+    fn parse_rearrange(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("rearrange")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, lhs) = expression::parse_expression(input)?;
+        let (input, _) = delimited(whitespace, char('='), whitespace)(input)?;
+        let (input, rhs) = expression::parse_expression(input)?;
+        let (input, _) = delimited(whitespace, keyword("for"), whitespace)(input)?;
+        let (input, variable) = identifier(input)?;
+        Ok((input, Rearrange(lhs, rhs, variable)))
+    }
+
+    fn parse_solve_start(input: &str) -> IResult<&str, SolveStart> {
+        alt((
+            map(
+                preceded(
+                    pair(keyword("near"), whitespace),
+                    expression::parse_expression,
+                ),
+                SolveStart::Near,
+            ),
+            map(
+                pair(
+                    preceded(
+                        pair(keyword("between"), whitespace),
+                        expression::parse_expression,
+                    ),
+                    preceded(
+                        delimited(whitespace, keyword("and"), whitespace),
+                        expression::parse_expression,
+                    ),
+                ),
+                |(lower, upper)| SolveStart::Between(lower, upper),
+            ),
+        ))(input)
+    }
+
+    fn equation(input: &str) -> IResult<&str, (Expression, Expression)> {
+        let (input, lhs) = expression::parse_expression(input)?;
+        let (input, _) = delimited(whitespace, char('='), whitespace)(input)?;
+        let (input, rhs) = expression::parse_expression(input)?;
+        Ok((input, (lhs, rhs)))
+    }
+
+    fn parse_solve_system(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("solve")(input)?;
+        let (input, equations) = delimited(
+            pair(whitespace, char('{')),
+            separated_list1(char(','), delimited(whitespace, equation, whitespace)),
+            pair(opt(char(',')), pair(whitespace, char('}'))),
+        )(input)?;
+        let (input, _) = delimited(whitespace, keyword("for"), whitespace)(input)?;
+        let unknown = pair(
+            identifier,
+            opt(preceded(
+                delimited(whitespace, keyword("near"), whitespace),
+                expression::parse_expression,
+            )),
+        );
+        let (input, unknowns) =
+            separated_list1(char(','), delimited(whitespace, unknown, whitespace))(input)?;
+        Ok((input, SolveSystem(equations, unknowns)))
+    }
+
+    fn parse_solve(input: &str) -> IResult<&str, Statement> {
+        let (input, _) = keyword("solve")(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, (lhs, rhs)) = equation(input)?;
+        let (input, _) = delimited(whitespace, keyword("for"), whitespace)(input)?;
+        let (input, variable) = identifier(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, start) = parse_solve_start(input)?;
+        Ok((input, Solve(lhs, rhs, variable, start)))
+    }
+
     fn parse_show(input: &str) -> IResult<&str, Statement> {
-        let (input, _) = tag("show")(input)?;
+        let (input, _) = keyword("show")(input)?;
         let (input, _) = whitespace(input)?;
         let (input, value) = expression::parse_expression(input)?;
         Ok((input, Show(value)))
     }
 
     pub fn parse_statement(input: &str) -> IResult<&str, Statement> {
-        let (input, result) =
-            delimited(whitespace, alt((parse_make, parse_show)), whitespace)(input)?;
+        let (input, result) = delimited(
+            whitespace,
+            alt((
+                parse_make,
+                parse_redefine,
+                parse_forget,
+                parse_export,
+                parse_import,
+                parse_show,
+                parse_table,
+                parse_plot,
+                parse_dimensionless_groups,
+                parse_solve_for_units,
+                parse_simplify,
+                parse_rearrange,
+                parse_solve_system,
+                parse_solve,
+                parse_set,
+                parse_update,
+            )),
+            whitespace,
+        )(input)?;
         Ok((input, result))
     }
 
@@ -144,3 +488,34 @@ mod parse {
 }
 
 pub use parse::{parse_statement, parse_statements};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_end_at_word_boundaries() {
+        assert!(parse_statement("setX.Radius = 1").is_err());
+        assert!(parse_statement("showX").is_err());
+        assert!(parse_statement("makelabel called A for 1").is_err());
+        assert!(parse_statement("make labels called A for 1").is_err());
+        assert!(parse_statement("make label calledA for 1").is_err());
+        match parse_statement("set X.Radius = 1").unwrap() {
+            ("", Statement::SetProperty(value, property, _)) => {
+                assert_eq!((&value[..], &property[..]), ("X", "Radius"))
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_statement("solve_for_units Speed using Distance, Duration").unwrap() {
+            ("", Statement::SolveForUnits(target, names)) => {
+                assert_eq!(target, "Speed");
+                assert_eq!(names, vec!["Distance", "Duration"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            parse_statement("show(1)").unwrap(),
+            ("", Statement::Show(_))
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{data::Describe, prelude::Instance};
 use std::{
     cmp::Ordering,
+    collections::BTreeSet,
     fmt::{Debug, Formatter},
     hash::{Hash, Hasher},
     ops::{Index, IndexMut},
@@ -8,6 +9,13 @@ use std::{
 
 pub struct StorageId<T>(usize, std::marker::PhantomData<T>);
 
+impl<T> StorageId<T> {
+    /// The position of the item in the pool it is stored in.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
 impl<T> Describe for StorageId<T>
 where
     T: Describe,
@@ -20,7 +28,7 @@ where
 
 impl<T> Clone for StorageId<T> {
     fn clone(&self) -> Self {
-        Self(self.0, self.1)
+        *self
     }
 }
 
@@ -41,7 +49,7 @@ impl<T> Debug for StorageId<T> {
             "{} instance {}",
             std::any::type_name::<T>()
                 .split(':')
-                .last()
+                .next_back()
                 .unwrap_or_default(),
             self.0
         )
@@ -56,7 +64,7 @@ impl<T> Hash for StorageId<T> {
 
 impl<T> PartialOrd for StorageId<T> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.0.partial_cmp(&other.0)
+        Some(self.cmp(other))
     }
 }
 
@@ -66,23 +74,101 @@ impl<T> Ord for StorageId<T> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<T> serde::Serialize for StorageId<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+#[cfg(feature = "serde")]
+thread_local! {
+    /// The largest index read for each type of id since the last call to forget_loaded_ids. Ids
+    /// can't be checked as they are read, since the pools they refer to might not have been read
+    /// yet, see StoragePool::holds_loaded_ids.
+    static LARGEST_LOADED_INDEX: std::cell::RefCell<std::collections::HashMap<std::any::TypeId, usize>> =
+        Default::default();
+}
+
+/// Called before reading a snapshot, so that only the ids in that snapshot are checked.
+#[cfg(feature = "serde")]
+pub(crate) fn forget_loaded_ids() {
+    LARGEST_LOADED_INDEX.with(|largest| largest.borrow_mut().clear());
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: 'static> serde::Deserialize<'de> for StorageId<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let index = u64::deserialize(deserializer)?;
+        let index = index as usize;
+        LARGEST_LOADED_INDEX.with(|largest| {
+            let mut largest = largest.borrow_mut();
+            let entry = largest.entry(std::any::TypeId::of::<T>()).or_insert(0);
+            *entry = (*entry).max(index);
+        });
+        Ok(Self(index, std::marker::PhantomData))
+    }
+}
+
+/// Items are never actually deleted from a pool. Removing an item only marks its id as a
+/// tombstone, so that ids are never reused and anything still holding the id can keep using the
+/// old item.
 #[derive(Clone)]
-pub struct StoragePool<T>(Vec<T>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoragePool<T> {
+    items: Vec<T>,
+    tombstones: BTreeSet<usize>,
+}
 
 impl<T> StoragePool<T> {
     pub fn new() -> Self {
-        Self(Vec::new())
+        Self {
+            items: Vec::new(),
+            tombstones: BTreeSet::new(),
+        }
     }
 
     pub fn next_id(&self) -> StorageId<T> {
-        StorageId(self.0.len(), std::marker::PhantomData)
+        StorageId(self.items.len(), std::marker::PhantomData)
     }
 
     pub fn push(&mut self, item: T) -> StorageId<T> {
         let id = self.next_id();
-        self.0.push(item);
+        self.items.push(item);
         id
     }
+
+    pub fn remove(&mut self, id: StorageId<T>) {
+        self.tombstones.insert(id.0);
+    }
+
+    pub fn is_removed(&self, id: StorageId<T>) -> bool {
+        self.tombstones.contains(&id.0)
+    }
+
+    /// Whether every id of this pool's type read since forget_loaded_ids refers to an item in the
+    /// pool.
+    #[cfg(feature = "serde")]
+    pub(crate) fn holds_loaded_ids(&self) -> bool
+    where
+        T: 'static,
+    {
+        LARGEST_LOADED_INDEX.with(|largest| {
+            largest
+                .borrow()
+                .get(&std::any::TypeId::of::<T>())
+                .is_none_or(|&index| index < self.items.len())
+        })
+    }
+
+    /// Iterates over every item that has not been removed.
+    pub fn iter(&self) -> impl Iterator<Item = (StorageId<T>, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (StorageId(index, std::marker::PhantomData), item))
+            .filter(move |(id, _)| !self.is_removed(*id))
+    }
 }
 
 impl<T> Debug for StoragePool<T>
@@ -95,22 +181,22 @@ where
             "StoragePool<{}>",
             std::any::type_name::<T>()
                 .split(':')
-                .last()
+                .next_back()
                 .unwrap_or_default()
         )?;
-        f.debug_list().entries(&self.0).finish()
+        f.debug_list().entries(&self.items).finish()
     }
 }
 
 impl<T> Index<StorageId<T>> for StoragePool<T> {
     type Output = T;
     fn index(&self, index: StorageId<T>) -> &T {
-        &self.0[index.0]
+        &self.items[index.0]
     }
 }
 
 impl<T> IndexMut<StorageId<T>> for StoragePool<T> {
     fn index_mut(&mut self, index: StorageId<T>) -> &mut T {
-        &mut self.0[index.0]
+        &mut self.items[index.0]
     }
 }
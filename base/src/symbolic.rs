@@ -0,0 +1,598 @@
+//! Symbolic manipulation of expressions. Names are kept as symbols instead of being looked up, so
+//! that a formula can be simplified or rearranged and printed before it is evaluated.
+
+use crate::{
+    expression::{BinaryOp, Expression, UnaryOp},
+    number::Number,
+    units::{exponent_from_f64, Exponent},
+};
+
+/// Sums of several terms are only multiplied out up to this power, so `(a + b)^20` stays short.
+const MAX_EXPANDED_POWER: i64 = 4;
+
+/// A name, or an expression which can't be simplified any further like a function call.
+#[derive(Clone, Debug)]
+struct Symbol {
+    /// The expression as text, used to find equal symbols.
+    key: String,
+    expression: Expression,
+}
+
+/// A coefficient multiplied by powers of symbols.
+#[derive(Clone, Debug)]
+struct Term {
+    coefficient: Number,
+    factors: Vec<(Symbol, Exponent)>,
+}
+
+/// A sum of terms, with like terms collected.
+#[derive(Clone, Debug, Default)]
+struct Sum {
+    terms: Vec<Term>,
+}
+
+fn binary(lhs: Expression, op: BinaryOp, rhs: Expression) -> Expression {
+    Expression::BinaryExpr(Box::new(lhs), op, Box::new(rhs))
+}
+
+fn exponent_expression(exponent: Exponent) -> Expression {
+    let numer = Expression::NumericLiteral(Number::from_integer(*exponent.numer()));
+    if exponent.is_integer() {
+        return numer;
+    }
+    let denom = Expression::NumericLiteral(Number::from_integer(*exponent.denom()));
+    binary(numer, BinaryOp::Div, denom)
+}
+
+impl Symbol {
+    fn new(expression: Expression) -> Self {
+        Self {
+            key: expression.to_string(),
+            expression,
+        }
+    }
+
+    fn power(&self, exponent: Exponent) -> Expression {
+        if exponent == Exponent::from(1) {
+            self.expression.clone()
+        } else {
+            let exponent = exponent_expression(exponent);
+            binary(self.expression.clone(), BinaryOp::Pow, exponent)
+        }
+    }
+}
+
+impl Term {
+    fn is_negative(&self) -> bool {
+        self.coefficient.to_f64() < 0.0
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let mut factors = self.factors.clone();
+        for (symbol, exponent) in &other.factors {
+            match factors.iter_mut().find(|(s, _)| s.key == symbol.key) {
+                Some((_, existing)) => *existing += exponent,
+                None => factors.push((symbol.clone(), *exponent)),
+            }
+        }
+        factors.retain(|(_, exponent)| *exponent != Exponent::from(0));
+        Self {
+            coefficient: self.coefficient.clone() * other.coefficient.clone(),
+            factors,
+        }
+    }
+
+    /// Fails for fractional powers of negative coefficients, which have no real value.
+    fn pow(&self, exponent: Exponent) -> Option<Self> {
+        if !exponent.is_integer() && self.is_negative() {
+            return None;
+        }
+        Some(Self {
+            coefficient: self.coefficient.pow(exponent),
+            factors: self
+                .factors
+                .iter()
+                .map(|(symbol, power)| (symbol.clone(), power * exponent))
+                .collect(),
+        })
+    }
+
+    /// Whether both terms have the same powers of the same symbols.
+    fn is_like(&self, other: &Self) -> bool {
+        self.factors.len() == other.factors.len()
+            && self.factors.iter().all(|(symbol, exponent)| {
+                other
+                    .factors
+                    .iter()
+                    .any(|(s, e)| s.key == symbol.key && e == exponent)
+            })
+    }
+
+    /// Splits off the power of the variable. Fails if the variable is hidden inside a symbol,
+    /// like in `sqrt(x)`.
+    fn split(mut self, variable: &str) -> Result<(Exponent, Self), ()> {
+        let mut power = Exponent::from(0);
+        for (symbol, exponent) in std::mem::take(&mut self.factors) {
+            if symbol.expression == Expression::LookupName(variable.to_owned()) {
+                power = exponent;
+            } else if symbol.expression.referenced_names().contains(variable) {
+                return Err(());
+            } else {
+                self.factors.push((symbol, exponent));
+            }
+        }
+        Ok((power, self))
+    }
+
+    fn to_expression(&self) -> Expression {
+        let mut numerator = Vec::new();
+        let mut denominator = Vec::new();
+        if self.coefficient != Number::from_integer(1) || self.factors.is_empty() {
+            numerator.push(Expression::NumericLiteral(self.coefficient.clone()));
+        }
+        for (symbol, exponent) in &self.factors {
+            if *exponent > Exponent::from(0) {
+                numerator.push(symbol.power(*exponent));
+            } else {
+                denominator.push(symbol.power(-exponent));
+            }
+        }
+        let product = |factors: Vec<Expression>| {
+            factors
+                .into_iter()
+                .reduce(|lhs, rhs| binary(lhs, BinaryOp::Mul, rhs))
+        };
+        let numerator = product(numerator)
+            .unwrap_or_else(|| Expression::NumericLiteral(Number::from_integer(1)));
+        match product(denominator) {
+            Some(denominator) => binary(numerator, BinaryOp::Div, denominator),
+            None => numerator,
+        }
+    }
+}
+
+impl Sum {
+    fn constant(value: Number) -> Self {
+        let mut result = Self::default();
+        result.push(Term {
+            coefficient: value,
+            factors: Vec::new(),
+        });
+        result
+    }
+
+    fn symbol(expression: Expression) -> Self {
+        Self {
+            terms: vec![Term {
+                coefficient: Number::from_integer(1),
+                factors: vec![(Symbol::new(expression), 1.into())],
+            }],
+        }
+    }
+
+    /// Adds a term, combining it with a like term if there is one.
+    fn push(&mut self, term: Term) {
+        if term.coefficient.is_zero() {
+            return;
+        }
+        match self.terms.iter().position(|t| t.is_like(&term)) {
+            Some(index) => {
+                let existing = &mut self.terms[index];
+                existing.coefficient = existing.coefficient.clone() + term.coefficient;
+                if existing.coefficient.is_zero() {
+                    self.terms.remove(index);
+                }
+            }
+            None => self.terms.push(term),
+        }
+    }
+
+    fn add(mut self, other: Self) -> Self {
+        for term in other.terms {
+            self.push(term);
+        }
+        self
+    }
+
+    fn negate(self) -> Self {
+        self.mul(&Self::constant(Number::from_integer(-1)))
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let mut result = Self::default();
+        for lhs in &self.terms {
+            for rhs in &other.terms {
+                result.push(lhs.mul(rhs));
+            }
+        }
+        result
+    }
+
+    fn pow(&self, exponent: Exponent) -> Self {
+        if exponent == Exponent::from(0) {
+            return Self::constant(Number::from_integer(1));
+        }
+        if self.terms.len() == 1 {
+            if let Some(term) = self.terms[0].pow(exponent) {
+                return Self { terms: vec![term] };
+            }
+        }
+        let power = exponent.to_integer();
+        if self.terms.len() > 1
+            && exponent.is_integer()
+            && (2..=MAX_EXPANDED_POWER).contains(&power)
+        {
+            let mut result = self.clone();
+            for _ in 1..power {
+                result = result.mul(self);
+            }
+            return result;
+        }
+        Self {
+            terms: vec![Term {
+                coefficient: Number::from_integer(1),
+                factors: vec![(Symbol::new(self.to_expression()), exponent)],
+            }],
+        }
+    }
+
+    /// Whether both sums have the same terms.
+    fn is_like(&self, other: &Self) -> bool {
+        self.terms.len() == other.terms.len()
+            && self.terms.iter().all(|term| {
+                other
+                    .terms
+                    .iter()
+                    .any(|t| t.is_like(term) && t.coefficient == term.coefficient)
+            })
+    }
+
+    fn as_constant(&self) -> Option<Number> {
+        match self.terms.as_slice() {
+            [] => Some(Number::from_integer(0)),
+            [term] if term.factors.is_empty() => Some(term.coefficient.clone()),
+            _ => None,
+        }
+    }
+
+    fn from_expression(expression: &Expression) -> Self {
+        match expression {
+            Expression::NumericLiteral(value) => Self::constant(value.clone()),
+            Expression::UnaryExpr(UnaryOp::Negate, rhs) => Self::from_expression(rhs).negate(),
+            Expression::BinaryExpr(lhs, op, rhs) => {
+                let (lhs, rhs) = (Self::from_expression(lhs), Self::from_expression(rhs));
+                match op {
+                    BinaryOp::Add => lhs.add(rhs),
+                    BinaryOp::Sub => lhs.add(rhs.negate()),
+                    BinaryOp::Mul => lhs.mul(&rhs),
+                    // A sum over itself, which would otherwise be divided term by term.
+                    BinaryOp::Div if lhs.terms.len() > 1 && lhs.is_like(&rhs) => {
+                        Self::constant(Number::from_integer(1))
+                    }
+                    BinaryOp::Div => lhs.mul(&rhs.pow((-1).into())),
+                    BinaryOp::Pow => {
+                        let exponent = rhs.as_constant();
+                        match exponent.and_then(|e| exponent_from_f64(e.to_f64()).ok()) {
+                            Some(exponent) => lhs.pow(exponent),
+                            None => Self::symbol(binary(
+                                lhs.to_expression(),
+                                BinaryOp::Pow,
+                                rhs.to_expression(),
+                            )),
+                        }
+                    }
+                    _ => Self::symbol(binary(lhs.to_expression(), *op, rhs.to_expression())),
+                }
+            }
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => {
+                if let (Expression::LookupName(name), [f, Expression::LookupName(variable)]) =
+                    (&**function, arguments.as_slice())
+                {
+                    if name == "derivative" {
+                        if let Ok(derivative) = derivative_of(f, variable) {
+                            return Self::from_expression(&derivative);
+                        }
+                    }
+                }
+                Self::symbol(Expression::ApplyFunction {
+                    function: function.clone(),
+                    arguments: arguments.iter().map(simplify).collect(),
+                })
+            }
+            other => Self::symbol(other.clone()),
+        }
+    }
+
+    fn to_expression(&self) -> Expression {
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        // Starts with a positive term if there is one, so that `b - a` isn't written `-1 * a + b`.
+        if let Some(index) = terms.iter().position(|term| !term.is_negative()) {
+            let term = terms.remove(index);
+            terms.insert(0, term);
+        }
+        let mut result: Option<Expression> = None;
+        for term in terms {
+            result = Some(match result {
+                None => term.to_expression(),
+                Some(sum) if term.is_negative() => {
+                    let mut positive = term.clone();
+                    positive.coefficient = -positive.coefficient;
+                    binary(sum, BinaryOp::Sub, positive.to_expression())
+                }
+                Some(sum) => binary(sum, BinaryOp::Add, term.to_expression()),
+            });
+        }
+        result.unwrap_or_else(|| Expression::NumericLiteral(Number::from_integer(0)))
+    }
+}
+
+/// Simplifies an expression, keeping names as symbols. Like terms are collected, common factors
+/// are cancelled and products of sums are multiplied out. Symbols are assumed to be positive, so
+/// `(x^2)^(1/2)` becomes `x`.
+pub fn simplify(expression: &Expression) -> Expression {
+    Sum::from_expression(expression).to_expression()
+}
+
+/// Differentiates an expression with respect to the variable, treating every other name as a
+/// constant. Fails for functions without a known derivative.
+pub fn differentiate(expression: &Expression, variable: &str) -> Result<Expression, ()> {
+    Ok(simplify(&derivative_of(expression, variable)?))
+}
+
+fn derivative_of(expression: &Expression, variable: &str) -> Result<Expression, ()> {
+    use BinaryOp::*;
+    let literal = |value| Expression::NumericLiteral(Number::from_integer(value));
+    if !expression.referenced_names().contains(variable) {
+        return Ok(literal(0));
+    }
+    let d = |expression| derivative_of(expression, variable);
+    let call = |name: &str, argument: &Expression| Expression::ApplyFunction {
+        function: Box::new(Expression::LookupName(name.to_owned())),
+        arguments: vec![argument.clone()],
+    };
+    Ok(match expression {
+        // Any other name was handled above as a constant.
+        Expression::LookupName(_) => literal(1),
+        Expression::UnaryExpr(UnaryOp::Negate, u) => {
+            Expression::UnaryExpr(UnaryOp::Negate, Box::new(d(u)?))
+        }
+        Expression::BinaryExpr(u, op @ (Add | Sub), v) => binary(d(u)?, *op, d(v)?),
+        Expression::BinaryExpr(u, Mul, v) => binary(
+            binary(d(u)?, Mul, (**v).clone()),
+            Add,
+            binary((**u).clone(), Mul, d(v)?),
+        ),
+        Expression::BinaryExpr(u, Div, v) => binary(
+            binary(
+                binary(d(u)?, Mul, (**v).clone()),
+                Sub,
+                binary((**u).clone(), Mul, d(v)?),
+            ),
+            Div,
+            binary((**v).clone(), Pow, literal(2)),
+        ),
+        Expression::BinaryExpr(u, Pow, v) if !v.referenced_names().contains(variable) => {
+            let power = binary((**u).clone(), Pow, binary((**v).clone(), Sub, literal(1)));
+            binary(binary((**v).clone(), Mul, power), Mul, d(u)?)
+        }
+        Expression::BinaryExpr(u, Pow, v) => {
+            // u^v * (v' ln(u) + v u' / u)
+            let inner = binary(
+                binary(d(v)?, Mul, call("ln", u)),
+                Add,
+                binary(binary((**v).clone(), Mul, d(u)?), Div, (**u).clone()),
+            );
+            binary(expression.clone(), Mul, inner)
+        }
+        Expression::ApplyFunction {
+            function,
+            arguments,
+        } => {
+            let (name, u) = match (&**function, arguments.as_slice()) {
+                (Expression::LookupName(name), [u]) => (name.as_str(), u),
+                _ => return Err(()),
+            };
+            let outer = match name {
+                "sin" => call("cos", u),
+                "cos" => binary(literal(0), Sub, call("sin", u)),
+                "tan" => binary(literal(1), Div, binary(call("cos", u), Pow, literal(2))),
+                "exp" => call("exp", u),
+                "ln" => binary(literal(1), Div, u.clone()),
+                "sqrt" => binary(literal(1), Div, binary(literal(2), Mul, call("sqrt", u))),
+                _ => return Err(()),
+            };
+            binary(outer, Mul, d(u)?)
+        }
+        _ => return Err(()),
+    })
+}
+
+/// Writes `numerator / denominator`, keeping a sum in the numerator together instead of dividing
+/// each of its terms.
+fn quotient(numerator: Sum, mut denominator: Sum) -> Sum {
+    if numerator.terms.len() <= 1 || denominator.terms.len() != 1 {
+        return numerator.mul(&denominator.pow((-1).into()));
+    }
+    let mut denominator = denominator.terms.remove(0);
+    let scale = Number::from_integer(1) / denominator.coefficient.clone();
+    let numerator = numerator.mul(&Sum::constant(scale));
+    if denominator.factors.is_empty() {
+        return numerator;
+    }
+    denominator.coefficient = Number::from_integer(1);
+    let expression = binary(
+        numerator.to_expression(),
+        BinaryOp::Div,
+        denominator.to_expression(),
+    );
+    Sum::symbol(expression)
+}
+
+/// Rearranges the equation `lhs = rhs` to give the value of `variable`. The variable may appear
+/// in a single power, like `a * x^2 + b = c`, or in a quadratic, in which case the larger root is
+/// given.
+pub fn rearrange(lhs: &Expression, rhs: &Expression, variable: &str) -> Result<Expression, ()> {
+    let difference = Sum::from_expression(lhs).add(Sum::from_expression(rhs).negate());
+    // Sums of the terms multiplying each power of the variable, and the terms without it.
+    let mut powers: Vec<(Exponent, Sum)> = Vec::new();
+    let mut constant = Sum::default();
+    for term in difference.terms {
+        let (power, rest) = term.split(variable)?;
+        if power == Exponent::from(0) {
+            constant.push(rest);
+        } else if let Some((_, sum)) = powers.iter_mut().find(|(p, _)| *p == power) {
+            sum.push(rest);
+        } else {
+            powers.push((power, Sum { terms: vec![rest] }));
+        }
+    }
+    powers.sort_by_key(|(power, _)| *power);
+    let solution = match powers.as_slice() {
+        [(power, factor)] => {
+            // factor * variable^power + constant = 0
+            let (mut numerator, mut denominator) = (constant.negate(), factor.clone());
+            let mut power = *power;
+            if power < Exponent::from(0) {
+                std::mem::swap(&mut numerator, &mut denominator);
+                power = -power;
+            }
+            let value = quotient(numerator, denominator);
+            match value.as_constant() {
+                _ if power == Exponent::from(1) => value,
+                Some(value) => Sum::constant(value).pow(power.recip()),
+                // Keeps the root of a quotient together, as in `(E / m)^(1/2)`.
+                None => Sum::symbol(value.to_expression()).pow(power.recip()),
+            }
+        }
+        [(one, b), (two, a)] if *one == 1.into() && *two == 2.into() => {
+            // Flips the signs so that a is positive, which makes the root the larger one.
+            let (a, b, constant) = match a.terms.first() {
+                Some(term) if term.is_negative() => {
+                    (a.clone().negate(), b.clone().negate(), constant.negate())
+                }
+                _ => (a.clone(), b.clone(), constant),
+            };
+            let four = Sum::constant(Number::from_integer(4));
+            let discriminant = b.mul(&b).add(four.mul(&a).mul(&constant).negate());
+            let root = discriminant.pow(Exponent::new(1, 2));
+            let two = Sum::constant(Number::from_integer(2));
+            quotient(b.negate().add(root), two.mul(&a))
+        }
+        _ => return Err(()),
+    };
+    Ok(solution.to_expression())
+}
+
+/// Writes the equation `lhs = rhs` as `a * x + b * y + ... = c`, returning the coefficient of each
+/// variable and the constant. Coefficients and constants which are zero are None. Returns None if
+/// the equation is not linear in the variables.
+pub(crate) fn linear_form(
+    lhs: &Expression,
+    rhs: &Expression,
+    variables: &[String],
+) -> Option<(Vec<Option<Expression>>, Option<Expression>)> {
+    let difference = Sum::from_expression(lhs).add(Sum::from_expression(rhs).negate());
+    let mut coefficients = vec![Sum::default(); variables.len()];
+    let mut constant = Sum::default();
+    for mut term in difference.terms {
+        let mut linear_in = None;
+        for (index, variable) in variables.iter().enumerate() {
+            let (power, rest) = term.split(variable).ok()?;
+            term = rest;
+            match (power, linear_in) {
+                (power, _) if power == Exponent::from(0) => (),
+                (power, None) if power == Exponent::from(1) => linear_in = Some(index),
+                _ => return None,
+            }
+        }
+        match linear_in {
+            Some(index) => coefficients[index].push(term),
+            None => constant.push(term),
+        }
+    }
+    let expression = |sum: Sum| {
+        if sum.terms.is_empty() {
+            None
+        } else {
+            Some(sum.to_expression())
+        }
+    };
+    Some((
+        coefficients.into_iter().map(expression).collect(),
+        expression(constant.negate()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::parse_expression;
+
+    fn parse(source: &str) -> Expression {
+        let (rest, expression) = parse_expression(source).unwrap();
+        assert_eq!(rest, "");
+        expression
+    }
+
+    fn simplified(source: &str) -> String {
+        simplify(&parse(source)).to_string()
+    }
+
+    fn rearranged(lhs: &str, rhs: &str, variable: &str) -> Result<String, ()> {
+        rearrange(&parse(lhs), &parse(rhs), variable).map(|e| e.to_string())
+    }
+
+    #[test]
+    fn printing() {
+        for source in [
+            "(a - (b - c)) / (d * e)^2",
+            "2^3^x",
+            "(2^3)^x",
+            "sqrt(a + b) * v[1] in Meters",
+            "0..10 step 2",
+        ] {
+            assert_eq!(parse(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn simplification() {
+        assert_eq!(simplified("x + x + 2 * y - y"), "2 * x + y");
+        assert_eq!(simplified("x * y / x"), "y");
+        assert_eq!(simplified("(a + b)^2"), "a^2 + 2 * a * b + b^2");
+        assert_eq!(simplified("(x^2)^(1/2) * x^3 / x"), "x^3");
+        assert_eq!(simplified("a / b - c"), "a / b - c");
+        assert_eq!(
+            simplified("(a + b) / (a + b) + sqrt(4 * x / 2)"),
+            "1 + sqrt(2 * x)"
+        );
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("derivative(x^3 + 2 * x, x)"), "3 * x^2 + 2");
+    }
+
+    #[test]
+    fn differentiation() {
+        let derivative = |source: &str| differentiate(&parse(source), "x").map(|e| e.to_string());
+        assert_eq!(derivative("a * x^2 + b * x + c").unwrap(), "2 * a * x + b");
+        assert_eq!(derivative("1 / x").unwrap(), "-1 / x^2");
+        assert_eq!(derivative("sin(x^2)").unwrap(), "2 * cos(x^2) * x");
+        assert_eq!(derivative("2^x").unwrap(), "2^x * ln(2)");
+        assert_eq!(derivative("exp(y)").unwrap(), "0");
+        assert!(derivative("abs(x)").is_err());
+    }
+
+    #[test]
+    fn rearrangement() {
+        assert_eq!(rearranged("v", "u + a * t", "t").unwrap(), "(v - u) / a");
+        assert_eq!(rearranged("F", "m * a", "a").unwrap(), "F / m");
+        assert_eq!(rearranged("E", "m * c^2", "c").unwrap(), "(E / m)^(1 / 2)");
+        assert_eq!(rearranged("1 / f", "2", "f").unwrap(), "0.5");
+        assert_eq!(rearranged("0", "x^2 - 3 * x + 2", "x").unwrap(), "2");
+        assert!(rearranged("y", "sqrt(x)", "x").is_err());
+        assert!(rearranged("y", "x^3 + x", "x").is_err());
+        assert!(rearranged("y", "z", "x").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Tables of an expression evaluated for every combination of some swept variables, like a
+//! braking distance for speeds from 10 to 100 km/hr.
+
+use crate::{
+    data::{Data, Describe, ValueData},
+    export::csv_field,
+    expression::Expression,
+    prelude::*,
+};
+
+/// The most rows a table can have. Each sweep is limited on its own, but sweeping several
+/// variables multiplies their lengths together.
+const MAX_ROWS: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    /// Columns padded with spaces so that they line up.
+    Text,
+    Markdown,
+    Csv,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    /// Column names, followed by the unit of the column in brackets if it has one.
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Writes a column of values. Scalars are converted to the display unit of the first value so
+/// that the unit only has to be written in the header.
+fn describe_column(name: &str, values: &[Data], instance: &Instance) -> (String, Vec<String>) {
+    let first_scalar = match values.first() {
+        Some(Data::Value(ValueData::Scalar(scalar))) => Some(scalar),
+        _ => None,
+    };
+    let mut header = name.to_owned();
+    let unit = match first_scalar {
+        Some(scalar) if !scalar.display_unit().is_identity() => {
+            header.push_str(" (");
+            scalar.display_unit().describe(&mut header, instance);
+            header.push(')');
+            Some((scalar.unit(), scalar.display_unit()))
+        }
+        _ => None,
+    };
+    let cells = values
+        .iter()
+        .map(|value| {
+            let mut cell = String::new();
+            match (value, unit) {
+                (Data::Value(ValueData::Scalar(scalar)), Some((class, display_unit)))
+                    if scalar.unit() == class =>
+                {
+                    let mut scalar = scalar.clone();
+                    scalar.set_display_unit(display_unit.clone());
+                    scalar.describe_number(&mut cell, instance);
+                }
+                (Data::Value(ValueData::Scalar(scalar)), None) if scalar.unit().is_identity() => {
+                    scalar.describe_number(&mut cell, instance);
+                }
+                (value, _) => value.describe(&mut cell, instance),
+            }
+            cell
+        })
+        .collect();
+    (header, cells)
+}
+
+/// The values a swept variable takes, from a list or a single scalar.
+pub(crate) fn sweep_values(data: Data) -> Result<Vec<Data>, ()> {
+    match data {
+        Data::Value(ValueData::Matrix(list)) if list.is_vector() => {
+            Ok(list.components().iter().cloned().map(Into::into).collect())
+        }
+        Data::Value(ValueData::Scalar(scalar)) => Ok(vec![scalar.into()]),
+        _ => Err(()),
+    }
+}
+
+impl Instance {
+    /// Evaluates the expression for every combination of values of the swept variables. The
+    /// first variable changes slowest. Each row lists the variables followed by the result.
+    /// Returns Err(()) if there would be more than MAX_ROWS rows.
+    pub fn table(
+        &self,
+        column: &str,
+        expression: &Expression,
+        sweeps: &[(String, Expression)],
+    ) -> Result<Table, ()> {
+        let sweeps = sweeps
+            .iter()
+            .map(|(name, values)| {
+                let values = self.resolve_expression(values, Default::default())?;
+                Ok((name, sweep_values(values)?))
+            })
+            .collect::<Result<Vec<_>, ()>>()?;
+        let row_count = sweeps
+            .iter()
+            .try_fold(1usize, |rows, (_, values)| rows.checked_mul(values.len()))
+            .filter(|&rows| rows <= MAX_ROWS)
+            .ok_or(())?;
+        let mut columns = vec![Vec::new(); sweeps.len() + 1];
+        let mut indexes = vec![0; sweeps.len()];
+        for _ in 0..row_count {
+            let mut bindings = Bindings::new();
+            for (column, ((name, values), &index)) in sweeps.iter().zip(&indexes).enumerate() {
+                bindings.insert((*name).clone(), values[index].clone());
+                columns[column].push(values[index].clone());
+            }
+            let result = self.resolve_expression_with(expression, Default::default(), &bindings)?;
+            columns[sweeps.len()].push(result);
+            // Counts up like an odometer, the last variable changing fastest.
+            for (index, (_, values)) in indexes.iter_mut().zip(&sweeps).rev() {
+                *index += 1;
+                if *index < values.len() {
+                    break;
+                }
+                *index = 0;
+            }
+        }
+        let names = sweeps.iter().map(|(name, _)| name.as_str());
+        let described: Vec<_> = names
+            .chain(std::iter::once(column))
+            .zip(&columns)
+            .map(|(name, values)| describe_column(name, values, self))
+            .collect();
+        let headers = described.iter().map(|(header, _)| header.clone()).collect();
+        let rows = (0..row_count)
+            .map(|row| {
+                described
+                    .iter()
+                    .map(|(_, cells)| cells[row].clone())
+                    .collect()
+            })
+            .collect();
+        Ok(Table { headers, rows })
+    }
+}
+
+impl Table {
+    pub fn render(&self, format: TableFormat) -> String {
+        let mut output = String::new();
+        match format {
+            TableFormat::Text => {
+                let mut widths: Vec<_> = self.headers.iter().map(|h| h.chars().count()).collect();
+                for row in &self.rows {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.chars().count());
+                    }
+                }
+                let rule: Vec<_> = widths.iter().map(|width| "-".repeat(*width)).collect();
+                for line in std::iter::once(&self.headers)
+                    .chain(std::iter::once(&rule))
+                    .chain(&self.rows)
+                {
+                    let cells: Vec<_> = line
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, width)| {
+                            let padding = width - cell.chars().count();
+                            format!("{}{}", cell, " ".repeat(padding))
+                        })
+                        .collect();
+                    output.push_str(cells.join("  ").trim_end());
+                    output.push('\n');
+                }
+            }
+            TableFormat::Markdown => {
+                let rule = vec!["---".to_owned(); self.headers.len()];
+                for line in std::iter::once(&self.headers)
+                    .chain(std::iter::once(&rule))
+                    .chain(&self.rows)
+                {
+                    let cells: Vec<_> = line.iter().map(|cell| cell.replace('|', "\\|")).collect();
+                    output.push_str(&format!("| {} |\n", cells.join(" | ")));
+                }
+            }
+            TableFormat::Csv => {
+                for line in std::iter::once(&self.headers).chain(&self.rows) {
+                    let cells: Vec<_> = line.iter().map(|cell| csv_field(cell)).collect();
+                    output.push_str(&cells.join(","));
+                    output.push('\n');
+                }
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        statement::{parse_statements, Statement},
+        test_util::make_instance,
+    };
+
+    fn table(instance: &Instance, source: &str) -> Result<(Table, TableFormat), ()> {
+        let (rest, statements) = parse_statements(source).unwrap();
+        assert_eq!(rest, "");
+        match statements.into_iter().next() {
+            Some(Statement::Table(column, expression, sweeps, format)) => {
+                Ok((instance.table(&column, &expression, &sweeps)?, format))
+            }
+            _ => panic!("not a table statement"),
+        }
+    }
+
+    #[test]
+    fn sweeps() {
+        let instance = make_instance();
+        let (result, format) = table(
+            &instance,
+            "table distance = speed * time in Meters \
+             for speed = (1..3) * Meters / Second, time = [1, 10] * Seconds as csv",
+        )
+        .unwrap();
+        assert_eq!(format, TableFormat::Csv);
+        assert_eq!(
+            result.render(format),
+            "speed (m/s),time (s),distance (m)\n\
+             1,1,1\n1,10,10\n2,1,2\n2,10,20\n3,1,3\n3,10,30\n"
+        );
+        let (result, format) =
+            table(&instance, "table area = side^2 for side = 0..1 step 0.5").unwrap();
+        assert_eq!(format, TableFormat::Text);
+        assert_eq!(
+            result.render(format),
+            "side  area\n----  ----\n0     0\n0.5   0.25\n1     1\n"
+        );
+        assert_eq!(
+            result.render(TableFormat::Markdown),
+            "| side | area |\n| --- | --- |\n| 0 | 0 |\n| 0.5 | 0.25 |\n| 1 | 1 |\n"
+        );
+        // Each sweep is short enough, but together they make too many rows.
+        assert!(table(&instance, "table x = a * b for a = 1..1000, b = 1..1000").is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        let instance = make_instance();
+        let describe = |source: &str| {
+            let (_, expression) = crate::expression::parse_expression(source).unwrap();
+            let data = instance.resolve_expression(&expression, Default::default())?;
+            let mut description = String::new();
+            data.describe(&mut description, &instance);
+            Ok::<_, ()>(description)
+        };
+        assert_eq!(
+            describe("1 * Meters..150 * Centimeters step 25 * Centimeters").unwrap(),
+            "[1m, 1.25m, 1.5m]"
+        );
+        assert_eq!(describe("3..1 step (0 - 1)").unwrap(), "[3, 2, 1]");
+        assert!(describe("1..3 step 0").is_err());
+        assert!(describe("1..3 step (0 - 1)").is_err());
+        assert!(describe("1 * Meters..3").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+//! Fixtures shared by the tests of the other modules.
+
+use crate::{
+    data::Describe, expression::parse_expression, prelude::*, statement::parse_statements,
+};
+
+const UNITS: &str = r#"
+    make entity_class called metric
+    make entity_class called partial_metric
+    make unit_class called Length
+    make unit_class called Time
+    make base_unit called Meter, Meters { class: Length, symbol: "m", metric, }
+    make base_unit called Second, Seconds { class: Time, symbol: "s", metric, }
+"#;
+
+/// Runs a script against the instance, which must parse completely.
+pub fn run(instance: &mut Instance, code: &str) -> Result<(), ()> {
+    let (remainder, statements) = parse_statements(code).unwrap();
+    assert_eq!(remainder, "");
+    instance.execute_statements(statements)
+}
+
+/// An instance which knows about meters, seconds and the metric prefixes. Tests which need more
+/// units add them with `run`.
+pub fn make_instance() -> Instance {
+    let mut instance = Instance::new();
+    run(&mut instance, UNITS).unwrap();
+    instance
+}
+
+/// The instance from `make_instance` with the given definitions run against it.
+pub fn instance_with(definitions: &str) -> Instance {
+    let mut instance = make_instance();
+    run(&mut instance, definitions).unwrap();
+    instance
+}
+
+/// Evaluates an expression and describes the result.
+pub fn evaluate(instance: &Instance, expression: &str) -> Result<String, ()> {
+    let (rest, expression) = parse_expression(expression).unwrap();
+    assert_eq!(rest, "");
+    let data = instance.resolve_expression(&expression, Default::default())?;
+    let mut description = String::new();
+    data.describe(&mut description, instance);
+    Ok(description)
+}
@@ -1,41 +1,105 @@
 use crate::{data::Describe, prelude::*, storage::StorageId};
+use num_rational::Rational64;
+use num_traits::{CheckedAdd, CheckedMul, One, Signed, ToPrimitive, Zero};
 use std::{
     cmp::Ordering,
     fmt::{Debug, Formatter, Write},
     hash::Hash,
-    ops::{Div, DivAssign, Index, Mul, MulAssign},
+    ops::{Div, Index, Mul},
 };
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnitClass {
     pub names: Vec<String>,
+    /// Angles are a pseudo-dimension. They are dropped from a quantity when it has to match a
+    /// unit class without them, so that radians per second times meters can be a velocity.
+    pub angle: bool,
 }
 
-pub const METRIC_PREFIXES: &[(&str, &str, f64)] = &[
-    ("Yotta", "Y", 1e24),
-    ("Zetta", "Z", 1e21),
-    ("Exa", "E", 1e18),
-    ("Peta", "P", 1e15),
-    ("Tera", "T", 1e12),
-    ("Giga", "G", 1e9),
-    ("Mega", "M", 1e6),
-    ("Kilo", "k", 1e3),
-    ("Hecto", "h", 1e2),
-    ("Deka", "da", 1e1),
+/// The name and symbol of each prefix along with the power of ten it multiplies by.
+pub const METRIC_PREFIXES: &[(&str, &str, i32)] = &[
+    ("Yotta", "Y", 24),
+    ("Zetta", "Z", 21),
+    ("Exa", "E", 18),
+    ("Peta", "P", 15),
+    ("Tera", "T", 12),
+    ("Giga", "G", 9),
+    ("Mega", "M", 6),
+    ("Kilo", "k", 3),
+    ("Hecto", "h", 2),
+    ("Deka", "da", 1),
     // ----------------
-    ("Deci", "d", 1e-1),
-    ("Centi", "c", 1e-2),
-    ("Milli", "m", 1e-3),
-    ("Micro", "μ", 1e-6),
-    ("Nano", "n", 1e-9),
-    ("Pico", "p", 1e-12),
-    ("Femto", "f", 1e-15),
-    ("Atto", "a", 1e-18),
-    ("Zepto", "z", 1e-21),
-    ("Yocto", "y", 1e-24),
+    ("Deci", "d", -1),
+    ("Centi", "c", -2),
+    ("Milli", "m", -3),
+    ("Micro", "μ", -6),
+    ("Nano", "n", -9),
+    ("Pico", "p", -12),
+    ("Femto", "f", -15),
+    ("Atto", "a", -18),
+    ("Zepto", "z", -21),
+    ("Yocto", "y", -24),
 ];
 pub const SMALL_PREFIXES_START: usize = 10;
 
+/// The power a unit or unit class is raised to. Exponents are exact fractions so that, for
+/// example, the square root of an area is exactly a length.
+pub type Exponent = Rational64;
+
+/// Fractions with larger denominators than this are not considered when turning a number into an
+/// exponent.
+const MAX_EXPONENT_DENOMINATOR: i64 = 1000;
+
+/// Returns the fraction equal to the given number, or Err(()) if the number is not close to any
+/// fraction with a reasonably small denominator (like pi.)
+pub fn exponent_from_f64(value: f64) -> Result<Exponent, ()> {
+    if !value.is_finite() {
+        return Err(());
+    }
+    let tolerance = 1e-9 * value.abs().max(1.0);
+    // Successive continued fraction convergents of the value.
+    let (mut prev_numer, mut numer) = (0i64, 1i64);
+    let (mut prev_denom, mut denom) = (1i64, 0i64);
+    let mut remainder = value;
+    loop {
+        let whole = remainder.floor();
+        if whole.abs() >= i64::MAX as f64 {
+            return Err(());
+        }
+        let whole = whole as i64;
+        let next_numer = whole
+            .checked_mul(numer)
+            .and_then(|n| n.checked_add(prev_numer))
+            .ok_or(())?;
+        let next_denom = whole
+            .checked_mul(denom)
+            .and_then(|d| d.checked_add(prev_denom))
+            .ok_or(())?;
+        if next_denom > MAX_EXPONENT_DENOMINATOR {
+            return Err(());
+        }
+        if (next_numer as f64 / next_denom as f64 - value).abs() <= tolerance {
+            return Ok(Exponent::new(next_numer, next_denom));
+        }
+        prev_numer = numer;
+        numer = next_numer;
+        prev_denom = denom;
+        denom = next_denom;
+        remainder = 1.0 / (remainder - whole as f64);
+    }
+}
+
+/// Raises a number to an exact power.
+pub fn pow_f64(base: f64, exp: Exponent) -> f64 {
+    if exp.is_integer() {
+        if let Some(exp) = exp.to_integer().to_i32() {
+            return base.powi(exp);
+        }
+    }
+    base.powf(exp.to_f64().unwrap())
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum UnitPrefixType {
     /// A plain unit.
@@ -48,21 +112,24 @@ pub enum UnitPrefixType {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct QuantityBag<T: Ord> {
-    items: Vec<(f64, T)>,
+    items: Vec<(Exponent, T)>,
 }
 
 impl<T: Ord> QuantityBag<T> {
+    #[cfg_attr(not(test), allow(dead_code))]
     fn item_index(&self, item: &T) -> Result<usize, usize> {
         self.items
             .binary_search_by(|candidate| candidate.1.cmp(item))
     }
 
-    fn add(&mut self, item: T, amount: f64) {
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn add(&mut self, item: T, amount: Exponent) {
         match self.item_index(&item) {
             Ok(exists_index) => {
                 self.items[exists_index].0 += amount;
-                if self.items[exists_index].0.abs() < 1e-10 {
+                if self.items[exists_index].0.is_zero() {
                     self.items.remove(exists_index);
                 }
             }
@@ -70,24 +137,27 @@ impl<T: Ord> QuantityBag<T> {
         }
     }
 
-    fn mul(&mut self, factor: f64) {
-        if factor == 0.0 {
+    /// Returns Err(()) if an exponent no longer fits in an Exponent.
+    fn mul(&mut self, factor: Exponent) -> Result<(), ()> {
+        if factor.is_zero() {
             self.items.clear();
         }
         for (quantity, _) in &mut self.items {
-            *quantity *= factor;
+            *quantity = quantity.checked_mul(&factor).ok_or(())?;
         }
+        Ok(())
     }
 
-    fn union(self, other: Self) -> Self
+    /// Returns Err(()) if an exponent no longer fits in an Exponent.
+    fn union(self, other: Self) -> Result<Self, ()>
     where
         T: Clone,
     {
-        if self.items.len() == 0 {
-            return other;
+        if self.items.is_empty() {
+            return Ok(other);
         }
-        if other.items.len() == 0 {
-            return self;
+        if other.items.is_empty() {
+            return Ok(self);
         }
         let mut self_items = self.items.into_iter();
         let mut other_items = other.items.into_iter();
@@ -97,8 +167,8 @@ impl<T: Ord> QuantityBag<T> {
         loop {
             match a.1.cmp(&b.1) {
                 Ordering::Equal => {
-                    let new_quantity = a.0 + b.0;
-                    if new_quantity.abs() > 1e-10 {
+                    let new_quantity = a.0.checked_add(&b.0).ok_or(())?;
+                    if !new_quantity.is_zero() {
                         new_items.push((new_quantity, a.1));
                     }
                     match (self_items.next(), other_items.next()) {
@@ -150,28 +220,44 @@ impl<T: Ord> QuantityBag<T> {
             sorted.sort_by(|a, b| a.1.cmp(&b.1));
             sorted == new_items
         });
-        Self { items: new_items }
+        Ok(Self { items: new_items })
     }
 
-    fn get(&self, item: &T) -> f64 {
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn get(&self, item: &T) -> Exponent {
         if let Ok(exists_at) = self.item_index(item) {
             self.items[exists_at].0
         } else {
-            0.0
+            Exponent::zero()
         }
     }
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Unit {
     pub names: Vec<String>,
     pub class: CompositeUnitClass,
     pub symbol: String,
     // Multiply a value in the base unit by this number to get the value in this unit.
-    pub base_ratio: f64,
+    pub base_ratio: Number,
+    /// Set if this unit was generated by putting a metric prefix in front of another unit.
+    pub prefix_origin: Option<PrefixOrigin>,
+    /// The kind of quantity this unit measures, if it is more specific than its class. Values
+    /// of another kind can't be displayed in this unit.
+    pub kind: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrefixOrigin {
+    pub unit: UnitId,
+    /// Index into METRIC_PREFIXES.
+    pub prefix: usize,
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Composite<I: Ord + Eq + Copy + Debug> {
     factors: QuantityBag<I>,
 }
@@ -187,26 +273,63 @@ impl<I: Ord + Eq + Copy + Debug> Composite<I> {
     }
 
     pub fn is_identity(&self) -> bool {
-        self.factors.items.len() == 0
+        self.factors.items.is_empty()
     }
 
-    pub fn pow(&mut self, exp: f64) {
-        self.factors.mul(exp);
+    /// Returns Err(()) if the resulting exponents are too large to represent.
+    pub fn pow(&mut self, exp: Exponent) -> Result<(), ()> {
+        self.factors.mul(exp)
+    }
+
+    /// Returns each item in this composite along with the power it is raised to, ordered by
+    /// item.
+    pub fn powers(&self) -> impl Iterator<Item = (I, Exponent)> + '_ {
+        self.factors
+            .items
+            .iter()
+            .map(|(power, item)| (*item, *power))
+    }
+
+    /// Returns the part of this composite made of the items which pass the given filter.
+    pub fn filter(&self, keep: impl Fn(I) -> bool) -> Self {
+        let items = self
+            .factors
+            .items
+            .iter()
+            .filter(|(_, item)| keep(*item))
+            .cloned()
+            .collect();
+        Self {
+            factors: QuantityBag { items },
+        }
+    }
+
+    /// Returns the only item in this composite if it is just that item to the first power.
+    pub fn as_single(&self) -> Option<I> {
+        match &self.factors.items[..] {
+            [(power, item)] if power.is_one() => Some(*item),
+            _ => None,
+        }
     }
 }
 
 fn describe_factor<T>(
     into: &mut String,
     instance: &Instance,
-    factor: &(f64, StorageId<T>),
+    factor: &(Exponent, StorageId<T>),
     factor_describer: impl Fn(&T) -> &str,
 ) where
     Instance: Index<StorageId<T>, Output = T>,
 {
     let power = factor.0;
     write!(into, "{}", factor_describer(&instance[factor.1])).unwrap();
-    if (power - 1.0).abs() > 1e-10 {
-        write!(into, "^{}", power).unwrap();
+    if power.is_integer() {
+        if !power.is_one() {
+            write!(into, "^{}", power).unwrap();
+        }
+    } else {
+        // Parenthesized so that "^1/2" isn't read as "(^1) / 2".
+        write!(into, "^({})", power).unwrap();
     }
 }
 
@@ -218,13 +341,13 @@ impl Describe for CompositeUnitClass {
         let mut numerator = Vec::new();
         let mut denominator = Vec::new();
         for item in &self.factors.items {
-            if item.0 > 0.0 {
+            if item.0.is_positive() {
                 numerator.push(item);
             } else {
                 denominator.push(item);
             }
         }
-        if numerator.len() == 0 {
+        if numerator.is_empty() {
             write!(into, "1").unwrap();
         } else {
             describe_factor(into, instance, numerator[0], |uc| &uc.names[0][..]);
@@ -233,7 +356,7 @@ impl Describe for CompositeUnitClass {
                 describe_factor(into, instance, factor, |uc| &uc.names[0][..]);
             }
         }
-        if denominator.len() > 0 {
+        if !denominator.is_empty() {
             write!(into, "/").unwrap();
             let factor = (-denominator[0].0, denominator[0].1);
             describe_factor(into, instance, &factor, |uc| &uc.names[0][..]);
@@ -247,31 +370,39 @@ impl Describe for CompositeUnitClass {
 }
 
 impl CompositeUnit {
-    pub fn base_ratio(&self, instance: &Instance) -> f64 {
-        let mut ratio = 1.0;
+    pub fn base_ratio(&self, instance: &Instance) -> Number {
+        let mut ratio = Number::from_integer(1);
         for (power, unit) in &self.factors.items {
-            ratio *= instance[*unit].base_ratio.powf(*power);
+            ratio = ratio * instance[*unit].base_ratio.pow(*power);
         }
         ratio
     }
 
-    pub fn unit_class(&self, instance: &Instance) -> CompositeUnitClass {
+    pub fn unit_class(&self, instance: &Instance) -> Result<CompositeUnitClass, ()> {
         let mut result = CompositeUnitClass::identity();
         for (power, unit_id) in &self.factors.items {
             let mut class = instance[*unit_id].class.clone();
-            class.factors.mul(*power);
-            result.factors = result.factors.union(class.factors);
+            class.factors.mul(*power)?;
+            result.factors = result.factors.union(class.factors)?;
         }
-        result
+        Ok(result)
     }
 
-    pub fn as_scalar(&self, instance: &Instance) -> Scalar {
-        Scalar::new(
+    pub fn as_scalar(&self, instance: &Instance) -> Result<Scalar, ()> {
+        let mut scalar = Scalar::new(
             self.base_ratio(instance),
             Precision::Exact,
-            self.unit_class(instance),
+            self.unit_class(instance)?,
             self.clone(),
-        )
+        );
+        scalar.set_kind(self.kind(instance).map(str::to_owned));
+        Ok(scalar)
+    }
+
+    /// The kind of quantity measured by this unit, if it is a single unit with a kind.
+    pub fn kind<'a>(&self, instance: &'a Instance) -> Option<&'a str> {
+        self.as_single()
+            .and_then(|unit| instance[unit].kind.as_deref())
     }
 }
 
@@ -283,7 +414,7 @@ impl Describe for CompositeUnit {
         let mut numerator = Vec::new();
         let mut denominator = Vec::new();
         for item in &self.factors.items {
-            if item.0 > 0.0 {
+            if item.0.is_positive() {
                 numerator.push(item);
             } else {
                 denominator.push(item);
@@ -297,7 +428,7 @@ impl Describe for CompositeUnit {
         if nl == 0 {
             write!(into, "1").unwrap();
         }
-        if denominator.len() > 0 {
+        if !denominator.is_empty() {
             write!(into, "/").unwrap();
         }
         for factor in denominator {
@@ -318,7 +449,7 @@ impl<I: Ord + Eq + Copy + Debug> Debug for Composite<I> {
             }
             write!(f, "({:?})^{}", item, power)?;
         }
-        if self.factors.items.len() == 0 {
+        if self.factors.items.is_empty() {
             write!(f, "1")?;
         }
         Ok(())
@@ -329,44 +460,35 @@ impl<I: Ord + Eq + Copy + Debug> From<I> for Composite<I> {
     fn from(item: I) -> Self {
         Self {
             factors: QuantityBag {
-                items: vec![(1.0, item)],
+                items: vec![(Exponent::one(), item)],
             },
         }
     }
 }
 
+/// Errs if the exponents of the product are too large to represent.
 impl<I: Ord + Eq + Copy + Debug> Mul for Composite<I> {
-    type Output = Self;
+    type Output = Result<Self, ()>;
     fn mul(mut self, rhs: Self) -> Self::Output {
-        self.factors = self.factors.union(rhs.factors);
-        self
-    }
-}
-
-impl<I: Ord + Eq + Copy + Debug> MulAssign for Composite<I> {
-    fn mul_assign(&mut self, rhs: Self) {
-        *self = self.clone() * rhs;
+        self.factors = self.factors.union(rhs.factors)?;
+        Ok(self)
     }
 }
 
+/// Errs if the exponents of the quotient are too large to represent.
 impl<I: Ord + Eq + Copy + Debug> Div for Composite<I> {
-    type Output = Self;
+    type Output = Result<Self, ()>;
     fn div(mut self, mut rhs: Self) -> Self::Output {
-        rhs.factors.mul(-1.0);
-        self.factors = self.factors.union(rhs.factors);
-        self
-    }
-}
-
-impl<I: Ord + Eq + Copy + Debug> DivAssign for Composite<I> {
-    fn div_assign(&mut self, rhs: Self) {
-        *self = self.clone() * rhs;
+        rhs.factors.mul(-Exponent::one())?;
+        self.factors = self.factors.union(rhs.factors)?;
+        Ok(self)
     }
 }
 
 impl<I: Ord + Eq + Copy + Debug> PartialEq for Composite<I> {
     fn eq(&self, other: &Self) -> bool {
-        (self.clone() / other.clone()).is_identity()
+        // Factors are kept sorted with no zero exponents, so equal composites have equal lists.
+        self.factors == other.factors
     }
 }
 
@@ -379,44 +501,70 @@ mod tests {
     #[test]
     fn union_operation() {
         let mut set1 = QuantityBag { items: Vec::new() };
-        set1.add(7, 10.0);
-        set1.add(9, 10.0);
-        set1.add(3, 1.0);
-        set1.add(5, 1.0);
-        set1.add(2, 1.0);
+        set1.add(7, Exponent::from(10));
+        set1.add(9, Exponent::from(10));
+        set1.add(3, Exponent::from(1));
+        set1.add(5, Exponent::from(1));
+        set1.add(2, Exponent::from(1));
         let mut set2 = QuantityBag { items: Vec::new() };
-        set2.add(1, 1.0);
-        set2.add(2, 1.0);
-        set2.add(3, 1.0);
-        let set = set1.union(set2);
+        set2.add(1, Exponent::from(1));
+        set2.add(2, Exponent::from(1));
+        set2.add(3, Exponent::from(1));
+        let set = set1.union(set2).unwrap();
         assert_eq!(set.items.len(), 6);
-        assert_eq!(set.get(&2), 2.0);
-        assert_eq!(set.get(&9), 10.0);
+        assert_eq!(set.get(&2), Exponent::from(2));
+        assert_eq!(set.get(&9), Exponent::from(10));
     }
 
     #[test]
     fn fully_disjoint_union_operation() {
         let mut set1 = QuantityBag { items: Vec::new() };
-        set1.add(1, 10.0);
+        set1.add(1, Exponent::from(10));
         let mut set2 = QuantityBag { items: Vec::new() };
-        set2.add(2, 20.0);
-        let set = set1.clone().union(set2.clone());
+        set2.add(2, Exponent::from(20));
+        let set = set1.clone().union(set2.clone()).unwrap();
         assert_eq!(set.items.len(), 2);
-        assert_eq!(set.get(&1), 10.0);
-        assert_eq!(set.get(&2), 20.0);
+        assert_eq!(set.get(&1), Exponent::from(10));
+        assert_eq!(set.get(&2), Exponent::from(20));
 
         // Check that the operator is symmetric
-        assert_eq!(set, set2.union(set1));
+        assert_eq!(set, set2.union(set1).unwrap());
     }
 
     #[test]
     fn union_operation_cancel_out() {
         let mut set1 = QuantityBag { items: Vec::new() };
-        set1.add(1, 10.0);
+        set1.add(1, Exponent::from(10));
         let mut set2 = set1.clone();
-        set2.mul(-1.0);
+        set2.mul(Exponent::from(-1)).unwrap();
         // The two sets should cancel out to make an empty set.
-        let set = set1.union(set2);
+        let set = set1.union(set2).unwrap();
         assert_eq!(set.items.len(), 0);
     }
+
+    #[test]
+    fn division_divides() {
+        let composite = (Composite::from(1) / Composite::from(2)).unwrap();
+        assert_eq!(composite.factors.get(&1), Exponent::from(1));
+        assert_eq!(composite.factors.get(&2), Exponent::from(-1));
+        let composite = (composite / Composite::from(1)).unwrap();
+        assert_eq!(
+            composite,
+            (Composite::identity() / Composite::from(2)).unwrap()
+        );
+    }
+
+    #[test]
+    fn exponent_overflow_is_an_error() {
+        let mut composite = Composite::from(1);
+        for denominator in &[997, 991, 983, 977, 971, 967] {
+            composite.pow(Exponent::new(1, *denominator)).unwrap();
+        }
+        assert!(composite.pow(Exponent::new(1, 953)).is_err());
+        let mut set1 = QuantityBag { items: Vec::new() };
+        set1.add(1, Exponent::new(1, i64::MAX));
+        let mut set2 = QuantityBag { items: Vec::new() };
+        set2.add(1, Exponent::new(1, i64::MAX - 1));
+        assert!(set1.union(set2).is_err());
+    }
 }
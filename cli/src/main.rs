@@ -33,9 +33,21 @@ fn main() {
     let (remainder, statements) = ackulator::statement::parse_statements(BOOTSTRAP).unwrap();
     assert_eq!(remainder.len(), 0, "{}", remainder);
 
-    for statement in statements {
-        // println!("{:#?}", statement);
-        instance.execute_statement(statement).unwrap();
+    let result = instance.execute_statements(statements);
+    for report in instance.take_import_reports() {
+        eprintln!("imported {} units", report.imported.len());
+        for (name, reason) in report.skipped {
+            eprintln!("skipped {}: {}", name, reason);
+        }
+    }
+    for warning in instance.take_warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    if result.is_err() {
+        if let Some(message) = instance.take_error() {
+            eprintln!("error: {}", message);
+        }
+        std::process::exit(1);
     }
 
     // let res = ackulator::expression::parse_expression("1 * Meter + 1 * Feet").unwrap();